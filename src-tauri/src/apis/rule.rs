@@ -1,9 +1,10 @@
 use crate::errors::Result;
 use config::views::address_view::AddressView;
-use config::views::features_view::FeaturesView;
+use config::views::disasm_view::DisasmViews;
 use config::views::files_view::FileView;
 use config::views::files_view::FilesView;
 use config::views::orignal_view::OrignalViews;
+use config::views::patch_view::PatchView;
 use config::views::path_view::PathView;
 use services::rule;
 
@@ -24,7 +25,7 @@ pub async fn rule_walk_files(code: &str) -> Result<FilesView> {
 }
 
 #[tauri::command(async)]
-pub async fn rule_patch(code: &str, num: usize, fcode: &str, status: bool) -> Result<FeaturesView> {
+pub async fn rule_patch(code: &str, num: usize, fcode: &str, status: bool) -> Result<PatchView> {
     Ok(rule::rule_patch(code, num, fcode, status).await?)
 }
 
@@ -43,6 +44,11 @@ pub async fn rule_read_orignal(code: &str, num: usize, fcode: &str) -> Result<Or
     Ok(rule::rule_read_orignal(code, num, fcode).await?)
 }
 
+#[tauri::command(async)]
+pub async fn rule_read_disasm(code: &str, num: usize, fcode: &str) -> Result<DisasmViews> {
+    Ok(rule::rule_read_disasm(code, num, fcode).await?)
+}
+
 #[tauri::command(async)]
 pub async fn rule_patch_by_replace(
     code: &str,
@@ -52,3 +58,13 @@ pub async fn rule_patch_by_replace(
 ) -> Result<()> {
     Ok(rule::rule_patch_by_replace(code, num, fcode, &ovs).await?)
 }
+
+#[tauri::command(async)]
+pub async fn rule_export_diff(code: &str, num: usize, fcode: &str, dir: &str) -> Result<String> {
+    Ok(rule::rule_export_diff(code, num, fcode, dir).await?)
+}
+
+#[tauri::command(async)]
+pub async fn rule_import_diff(code: &str, num: usize, file: &str) -> Result<PatchView> {
+    Ok(rule::rule_import_diff(code, num, file).await?)
+}
@@ -0,0 +1,18 @@
+//! 将 JSON5、TOML、YAML 格式的规则配置转换为发布用的紧凑 JSON
+//!
+//! cargo run -p config --example convert_config -- config.toml config.json
+
+use config::format::write_canonical_json;
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let [input, output] = args.as_slice() else {
+        eprintln!("用法：convert_config <输入文件> <输出文件>");
+        std::process::exit(2);
+    };
+    if let Err(e) = write_canonical_json(input, output) {
+        eprintln!("转换失败：{}", e);
+        std::process::exit(1);
+    }
+    println!("已转换：{} -> {}", input, output);
+}
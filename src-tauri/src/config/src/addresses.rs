@@ -2,16 +2,27 @@ use crate::errors::ConfigError;
 use crate::errors::Result;
 use crate::serders::skippers::skip_if_empty;
 use crate::variables::Variables;
+use crate::views::disasm_view::DisasmView;
 use crate::views::orignal_view::OrignalView;
 use log::debug;
 use log::error;
 use macros::ImpConfigVecIsEmptyTrait;
 use serde::Deserialize;
 use serde::Serialize;
+use utils::empty::Empty;
+use utils::patch::capture::CaptureValue;
+use utils::patch::disasm::align_start;
+use utils::patch::disasm::diff_ranges;
+use utils::patch::disasm::disassemble;
 use utils::patch::patch::UPatch;
+use utils::patch::types::BytePattern;
+use utils::patch::types::Bytes;
 use utils::tools::replace_ellipsis;
 use utils::tools::replace_wildcards;
 
+// 反汇编时基址前后各读取的字节数
+const DISASM_CONTEXT: usize = 32;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default, ImpConfigVecIsEmptyTrait)]
 pub struct Addresses(pub Vec<Address>);
 
@@ -36,15 +47,18 @@ impl Addresses {
                 Ok(orignal) => {
                     debug!("地址：{}， 原始补丁：{:?}", pos, orignal);
                     debug!("地址：{}， 替换补丁：{:?}", pos, replace);
-                    let start_rva = upatch.foa_to_rva(pos as u64)? as usize;
-                    addresses.push(Address::new(
+                    let start_rva = upatch.foa_to_rva(pos as u64)?;
+                    let start_va = upatch.rva_to_va(start_rva)? as usize;
+                    let mut address = Address::new(
                         orignal,
                         replace.to_string(),
                         pos,
-                        start_rva,
+                        start_rva as usize,
                         len,
                         usereplace,
-                    ));
+                    );
+                    address.start_va = start_va;
+                    addresses.push(address);
                 }
                 Err(e) => {
                     error!("读取地址失败！地址：{}， 长度：{}， 错误：{}", pos, len, e);
@@ -83,6 +97,13 @@ impl Addresses {
         Err(ConfigError::AddressesEmptyError)
     }
 
+    pub fn read_disasm(&self, upatch: &UPatch) -> Result<Vec<DisasmView>> {
+        self.0
+            .iter()
+            .map(|address| address.read_disasm(upatch))
+            .collect()
+    }
+
     pub fn patch(&mut self, upatch: &mut UPatch, status: bool) -> Result<()> {
         for address in &mut self.0 {
             address.patch(upatch, status)?;
@@ -118,6 +139,9 @@ pub struct Address {
     pub start_rva: usize,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
+    pub start_va: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
     pub len: usize,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
@@ -125,6 +149,41 @@ pub struct Address {
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     pub patched: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 跟随位移解析出的目标，作为变量使用
+    pub target: Option<ResolvedTarget>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 特征码命名捕获的值，作为变量使用
+    pub captures: Vec<AddressCapture>,
+}
+
+/// 命名捕获解码后的值，hex 为捕获的原始字节
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AddressCapture {
+    pub code: String,
+    pub value: CaptureValue,
+    pub hex: String,
+}
+
+/// 位移解析出的目标地址，start 为 0 表示目标不在文件中
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResolvedTarget {
+    pub code: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub start: usize,
+    pub start_rva: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub start_va: usize,
+}
+
+impl Empty for ResolvedTarget {
+    fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
 }
 
 impl Address {
@@ -134,23 +193,27 @@ impl Address {
             replace,
             start,
             start_rva,
+            start_va: 0,
             len,
             end: start + len,
             patched,
+            target: None,
+            captures: Vec::new(),
         }
     }
 
     pub fn init(&mut self, variables: &Variables,pattern_code:&str) -> Result<()> {
         let _ = variables.get_num_hex()?;
-        if self.replace.is_empty() || self.replace.as_str() == "..." {
+        let mut replace = BytePattern::compact(&self.replace);
+        if replace.is_empty() || replace.as_str() == "..." {
             self.replace = "".to_string();
             return Ok(())
         }
-        let mut replace = self.replace.to_string();
         replace = variables.substitute_add(replace,false,pattern_code)?;
-        replace = variables.substitute(replace);
+        replace = variables.substitute(replace)?;
         replace = replace_ellipsis(replace.as_str(), self.orignal.as_ref())?;
-        self.replace = replace_wildcards(replace.as_str(), self.orignal.as_ref())?;
+        let replace = BytePattern::parse(replace)?;
+        self.replace = replace_wildcards(&replace, self.orignal.as_str())?;
         //初始化 patched
         self.check_replace_data()?;
         self.patched = false;
@@ -163,6 +226,45 @@ impl Address {
         Ok(self.patched)
     }
 
+    /// 反汇编基址附近的原始数据与替换数据
+    pub fn read_disasm(&self, upatch: &UPatch) -> Result<DisasmView> {
+        let before = DISASM_CONTEXT.min(self.start);
+        let window_start = self.start - before;
+        let window_end = (self.start + self.len + DISASM_CONTEXT).min(upatch.len());
+        let mut orignal = upatch
+            .read(window_start, window_end - window_start)?
+            .as_bytes()
+            .to_vec();
+        // 文件可能已修补，先还原原始数据
+        let orignal_data = Bytes::try_from_hex(self.orignal.clone())?;
+        orignal[before..before + orignal_data.len()].copy_from_slice(orignal_data.as_bytes());
+        let mut replace = orignal.clone();
+        if !self.replace.is_empty() {
+            let replace_data = Bytes::try_from_hex(self.replace.clone())?;
+            replace[before..before + replace_data.len()].copy_from_slice(replace_data.as_bytes());
+        }
+
+        let bitness = upatch.get_bitness();
+        let ip = self.start_rva.saturating_sub(before) as u64;
+        let changed = diff_ranges(&orignal, &replace);
+        let render = |data: &[u8]| {
+            let start = align_start(data, ip, bitness, before);
+            let changed: Vec<(usize, usize)> = changed
+                .iter()
+                .map(|(s, e)| (s - start, e - start))
+                .collect();
+            disassemble(&data[start..], ip + start as u64, bitness, &changed)
+        };
+        Ok(DisasmView {
+            start: self.start,
+            start_rva: self.start_rva,
+            len: self.len,
+            orignal: render(&orignal),
+            replace: render(&replace),
+            ..Default::default()
+        })
+    }
+
     pub fn patch(&mut self, upatch: &mut UPatch, status: bool) -> Result<()> {
         self.patched = status;
         let base_data = upatch.read_hex(self.start, self.len)?;
@@ -6,19 +6,34 @@ use utils::empty::Empty;
 use utils::patch::patch::UPatch;
 
 #[derive(Default)]
-pub struct Cache(HashMap<String, UPatch>);
+pub struct Cache {
+    patches: HashMap<String, UPatch>,
+    // 暂存模式下写入只修改内存副本，由事务统一提交
+    staged: bool,
+}
 
 impl Cache {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self::default()
+    }
+
+    pub fn new_staged() -> Self {
+        Self {
+            patches: HashMap::new(),
+            staged: true,
+        }
+    }
+
+    pub fn is_staged(&self) -> bool {
+        self.staged
     }
 
     pub fn get(&self, key: &str) -> Option<&UPatch> {
-        self.0.get(key)
+        self.patches.get(key)
     }
 
     pub fn insert(&mut self, key: &str, patch: UPatch) {
-        self.0.insert(key.to_string(), patch);
+        self.patches.insert(key.to_string(), patch);
     }
 
     pub fn get_or_insert(
@@ -28,29 +43,40 @@ impl Cache {
         save: &str,
         with_write: bool,
     ) -> crate::errors::Result<&mut UPatch> {
-        if !self.0.contains_key(key) {
-            let patch: UPatch = UPatch::create(input, save, with_write)?;
-            self.0.insert(key.to_string(), patch);
+        if !self.patches.contains_key(key) {
+            let patch: UPatch = match self.staged && with_write {
+                true => UPatch::create_staged(input, save)?,
+                false => UPatch::create(input, save, with_write)?,
+            };
+            self.patches.insert(key.to_string(), patch);
         }
-        self.0
+        self.patches
             .get_mut(key)
             .ok_or(ConfigError::CacheNotFindError.into())
     }
 
     pub fn clear(&mut self) {
-        self.0.clear();
+        self.patches.clear();
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.patches.is_empty()
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.patches.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &UPatch)> {
+        self.patches.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut UPatch)> {
+        self.patches.iter_mut()
     }
 
-    pub fn save(&self) -> Result<()> {
-        for (_, patch) in &self.0 {
+    pub fn save(&mut self) -> Result<()> {
+        for (_, patch) in &mut self.patches {
             patch.save()?;
         }
         Ok(())
@@ -59,7 +85,7 @@ impl Cache {
 
 impl Debug for Cache {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for (key, patch) in &self.0 {
+        for (key, patch) in &self.patches {
             writeln!(f, "key:{},len: {}", key, patch.len())?;
         }
         Ok(())
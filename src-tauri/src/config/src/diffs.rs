@@ -0,0 +1,318 @@
+use crate::errors::ConfigError;
+use crate::errors::Result;
+use crate::patches::Patch;
+use crate::serders::skippers::skip_if_empty;
+use log::info;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use utils::file::get_file_name;
+use utils::patch::diff::DiffRecord;
+use utils::patch::diff::decode_bps;
+use utils::patch::diff::decode_ips;
+use utils::patch::diff::encode_bps;
+use utils::patch::diff::encode_ips;
+use utils::patch::diff::merge_records;
+use utils::patch::diff::sort_records;
+use utils::patch::diff::source_hash;
+use utils::patch::patch::UPatch;
+use utils::patch::types::Bytes;
+
+pub const DIFF_MANIFEST_VERSION: usize = 1;
+
+/// 补丁清单，随 IPS/BPS 文件一起导出
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffManifest {
+    pub version: usize,
+    pub rule: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub feature: String,
+    #[serde(default)]
+    pub files: Vec<DiffFile>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffFile {
+    pub patch: String,
+    pub file: String,
+    pub size: usize,
+    /// 修改区域还原为原始数据后的 SHA-256
+    pub sha256: String,
+    // 超出 IPS 偏移范围时为空
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub ips: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub bps: String,
+    #[serde(default)]
+    pub records: Vec<DiffRecordView>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffRecordView {
+    pub pattern: String,
+    pub start: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub start_rva: usize,
+    pub orignal: String,
+    pub replace: String,
+}
+
+impl DiffRecordView {
+    pub fn to_record(&self) -> Result<DiffRecord> {
+        let orignal = Bytes::try_from_hex(self.orignal.clone())?;
+        let replace = Bytes::try_from_hex(self.replace.clone())?;
+        if orignal.len() != replace.len() {
+            return Err(ConfigError::InvalidDiffManifest(format!(
+                "{} 原始数据与替换数据长度不一致",
+                self.pattern
+            )));
+        }
+        Ok(DiffRecord::new(
+            self.start,
+            orignal.as_bytes().to_vec(),
+            replace.as_bytes().to_vec(),
+        ))
+    }
+}
+
+impl DiffManifest {
+    pub fn new(rule: &str, feature: &str) -> Self {
+        Self {
+            version: DIFF_MANIFEST_VERSION,
+            rule: rule.to_string(),
+            feature: feature.to_string(),
+            files: Vec::new(),
+        }
+    }
+
+    pub fn read(file: &str) -> Result<Self> {
+        let data = std::fs::read_to_string(file)?;
+        let manifest: Self = serde_json::from_str(&data)
+            .map_err(|e| ConfigError::InvalidDiffManifest(e.to_string()))?;
+        if manifest.version != DIFF_MANIFEST_VERSION {
+            return Err(ConfigError::InvalidDiffManifest(format!(
+                "不支持的版本 {}",
+                manifest.version
+            )));
+        }
+        Ok(manifest)
+    }
+
+    /// 写出清单以及每个文件的 IPS/BPS 补丁
+    pub fn write(&mut self, dir: &str, sources: &[&UPatch]) -> Result<String> {
+        let prefix = match self.feature.is_empty() {
+            true => self.rule.clone(),
+            false => format!("{}-{}", self.rule, self.feature),
+        };
+        std::fs::create_dir_all(dir)?;
+        for (diff_file, upatch) in self.files.iter_mut().zip(sources) {
+            let records = diff_file.to_records()?;
+            let name = format!("{}-{}", prefix, diff_file.patch);
+
+            let bps = format!("{}.bps", name);
+            std::fs::write(
+                Path::new(dir).join(&bps),
+                encode_bps(upatch.get_data(), &records),
+            )?;
+            diff_file.bps = bps;
+
+            // IPS 只能寻址 16MB，大文件只导出 BPS
+            match encode_ips(&records) {
+                Ok(data) => {
+                    let ips = format!("{}.ips", name);
+                    std::fs::write(Path::new(dir).join(&ips), data)?;
+                    diff_file.ips = ips;
+                }
+                Err(e) => info!("{} 跳过 IPS 导出：{}", diff_file.file, e),
+            }
+        }
+        let manifest = Path::new(dir).join(format!("{}.json", prefix));
+        let data = serde_json::to_string_pretty(self)
+            .map_err(|e| ConfigError::InvalidDiffManifest(e.to_string()))?;
+        std::fs::write(&manifest, data)?;
+        let manifest = manifest.to_string_lossy().to_string();
+        info!("补丁已导出：{}", manifest);
+        Ok(manifest)
+    }
+}
+
+impl DiffFile {
+    /// 导出补丁中指定特征码的修改，pcodes 为空时导出已修补的全部特征码
+    pub fn from_patch(patch: &Patch, pcodes: &[String], upatch: &UPatch) -> Result<Self> {
+        let mut records = Vec::new();
+        for pattern in &patch.patterns.0 {
+            if pattern.disabled {
+                continue;
+            }
+            let selected = match pcodes.is_empty() {
+                true => pattern.patched,
+                false => pcodes.contains(&pattern.code),
+            };
+            if !selected {
+                continue;
+            }
+            for address in &pattern.addresses.0 {
+                if address.replace.is_empty() {
+                    continue;
+                }
+                // 替换数据可能比原始数据短
+                let orignal = &address.orignal[..address.replace.len().min(address.orignal.len())];
+                records.push(DiffRecordView {
+                    pattern: pattern.code.clone(),
+                    start: address.start,
+                    start_rva: address.start_rva,
+                    orignal: orignal.to_string(),
+                    replace: address.replace.clone(),
+                });
+            }
+        }
+        let mut diff_file = Self {
+            patch: patch.code.clone(),
+            file: get_file_name(patch.get_savefile())?,
+            size: upatch.len(),
+            records,
+            ..Default::default()
+        };
+        diff_file.sha256 = source_hash(upatch.get_data(), &diff_file.to_records()?);
+        Ok(diff_file)
+    }
+
+    /// 清单中的记录，超出文件大小的视为文件不匹配
+    pub fn to_records(&self) -> Result<Vec<DiffRecord>> {
+        let mut records = self
+            .records
+            .iter()
+            .map(|record| record.to_record())
+            .collect::<Result<Vec<_>>>()?;
+        let out_of_file = records.iter().any(|record| {
+            record
+                .offset
+                .checked_add(record.replace.len())
+                .is_none_or(|end| end > self.size)
+        });
+        if out_of_file {
+            return Err(ConfigError::DiffSourceMismatch(self.file.clone()));
+        }
+        sort_records(&mut records)?;
+        Ok(records)
+    }
+
+    /// 校验文件与清单一致后，按导出的 IPS/BPS 补丁写入
+    pub fn apply(&self, upatch: &mut UPatch, dir: &Path) -> Result<()> {
+        if upatch.len() != self.size {
+            return Err(ConfigError::DiffSourceMismatch(self.file.clone()));
+        }
+        let records = self.to_records()?;
+        if source_hash(upatch.get_data(), &records) != self.sha256 {
+            return Err(ConfigError::DiffSourceMismatch(self.file.clone()));
+        }
+        let diff_records = self.read_diff(dir, upatch.get_data(), &records)?;
+        if merge_records(&diff_records) != merge_records(&records) {
+            return Err(ConfigError::InvalidDiffManifest(format!(
+                "{} 的补丁文件与清单记录不一致",
+                self.file
+            )));
+        }
+        upatch.apply_diff(&diff_records)?;
+        Ok(())
+    }
+
+    /// 读取导出的补丁文件，优先使用带校验的 BPS
+    fn read_diff(
+        &self,
+        dir: &Path,
+        data: &[u8],
+        records: &[DiffRecord],
+    ) -> Result<Vec<DiffRecord>> {
+        // 补丁文件只在清单所在目录中查找
+        let path = |name: &str| dir.join(Path::new(name).file_name().unwrap_or_default());
+        if !self.bps.is_empty() {
+            let bps = std::fs::read(path(&self.bps))?;
+            // BPS 校验原始文件，已修补的区域先还原
+            let mut source = data.to_vec();
+            for record in records {
+                source[record.offset..record.end()].copy_from_slice(&record.orignal);
+            }
+            return Ok(decode_bps(&source, &bps)?);
+        }
+        if !self.ips.is_empty() {
+            let ips = std::fs::read(path(&self.ips))?;
+            return Ok(decode_ips(&ips)?);
+        }
+        Err(ConfigError::InvalidDiffManifest(format!(
+            "{} 缺少 IPS/BPS 补丁文件",
+            self.file
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::EXE_NAME;
+    use crate::fixture::REVOKE_OFFSET;
+    use crate::fixture::RULE_CODE;
+    use crate::fixture::RuleFixture;
+
+    #[test]
+    fn test_diff_import() {
+        let fixture = RuleFixture::new("diff-import");
+        let exe = fixture.exe().to_string_lossy().to_string();
+        let foa = fixture.builder.foa(".text", REVOKE_OFFSET).unwrap() as usize;
+        let upatch = UPatch::create(&exe, &exe, false).unwrap();
+        let mut diff_file = DiffFile {
+            patch: "app".to_string(),
+            file: EXE_NAME.to_string(),
+            size: upatch.len(),
+            records: vec![DiffRecordView {
+                pattern: "revoke".to_string(),
+                start: foa,
+                orignal: "741C".to_string(),
+                replace: "EB1C".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        diff_file.sha256 = source_hash(upatch.get_data(), &diff_file.to_records().unwrap());
+        let mut manifest = DiffManifest::new(RULE_CODE, "revoke");
+        manifest.files.push(diff_file);
+        let dir = fixture.dir.join("diff");
+        manifest.write(dir.to_str().unwrap(), &[&upatch]).unwrap();
+        drop(upatch);
+
+        // BPS 和 IPS 分别导入，已修补的文件可以重复导入
+        let mut upatch = UPatch::create_staged(&exe, &exe).unwrap();
+        let mut diff_file = manifest.files[0].clone();
+        diff_file.apply(&mut upatch, &dir).unwrap();
+        assert_eq!(upatch.read_hex(foa, 2).unwrap(), "EB1C");
+        diff_file.apply(&mut upatch, &dir).unwrap();
+        diff_file.bps.clear();
+        diff_file.apply(&mut upatch, &dir).unwrap();
+        assert_eq!(upatch.read_hex(foa, 2).unwrap(), "EB1C");
+
+        // 补丁文件与清单不一致，或者缺少补丁文件
+        diff_file.records[0].replace = "EB1D".to_string();
+        let result = diff_file.apply(&mut upatch, &dir);
+        assert!(matches!(result, Err(ConfigError::InvalidDiffManifest(_))));
+        diff_file.ips.clear();
+        let result = diff_file.apply(&mut upatch, &dir);
+        assert!(matches!(result, Err(ConfigError::InvalidDiffManifest(_))));
+
+        // 越界和重叠的记录返回错误，不会读取文件数据
+        let mut diff_file = manifest.files[0].clone();
+        diff_file.records[0].start = diff_file.size - 1;
+        let result = diff_file.apply(&mut upatch, &dir);
+        assert!(matches!(result, Err(ConfigError::DiffSourceMismatch(_))));
+        diff_file.records[0].start = usize::MAX;
+        assert!(diff_file.to_records().is_err());
+        let mut diff_file = manifest.files[0].clone();
+        let mut overlap = diff_file.records[0].clone();
+        overlap.start += 1;
+        diff_file.records.push(overlap);
+        assert!(diff_file.apply(&mut upatch, &dir).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::num::ParseIntError;
 
 use crate::update::UpdatesError;
+use crate::validate::ConfigIssues;
 use thiserror::Error;
 use tokio::task::JoinError;
 use utils::errors::UtilsError;
@@ -64,8 +65,14 @@ pub enum ConfigError {
     #[error("获取变量值 {0} 失败")]
     GetVariabledValueError(String),
 
-    #[error("变量 {0} 无效，请检查配置文件")]
-    InvalidVariable(String),
+    #[error("变量 {0} 无效，{1}，请检查配置文件")]
+    InvalidVariable(String, String),
+
+    #[error("变量 {0} 引用了未定义的变量 {1}，请检查配置文件")]
+    UnresolvedVariable(String, String),
+
+    #[error("变量 {0} 存在循环引用：{1}，请检查配置文件")]
+    VariableCycle(String, String),
 
     #[error("变量 {0} 类型无效，请检查配置文件")]
     InvalidVariableType(String),
@@ -112,9 +119,36 @@ pub enum ConfigError {
     #[error("找到的 {0} 基址 {1} 个，期待 {2} 个，可能不支持当前版本")]
     AddressesTooMuchError(String, usize, usize),
 
+    #[error("找到的 {0} 基址 {2} 个，无法选取第 {1} 个")]
+    AddressesNthError(String, i64, usize),
+
+    #[error("{0} 偏移 {1} 超出文件范围")]
+    InvalidGroupOffset(String, i64),
+
+    #[error("{0} 解析位移失败：{1}")]
+    ResolveAddressError(String, String),
+
+    #[error("{0} 使用 xref 搜索时必须指定 len")]
+    InvalidXrefLen(String),
+
     #[error("基址无效")]
     InvalidAddress,
 
+    #[error("写入文件失败，已恢复原文件：{0}")]
+    TransactionCommitError(String),
+
+    #[error("恢复文件失败，请尝试重装或删除共存后重建：{0}，写入失败原因：{1}")]
+    TransactionRollbackError(String, String),
+
+    #[error("补丁清单无效：{0}")]
+    InvalidDiffManifest(String),
+
+    #[error("文件 {0} 与补丁清单不匹配，请确认文件版本")]
+    DiffSourceMismatch(String),
+
+    #[error("配置文件校验失败：\n{0}")]
+    InvalidConfig(#[from] ConfigIssues),
+
     #[error("CacheLockError")]
     CacheLockError,
 
@@ -0,0 +1,660 @@
+use crate::errors::ConfigError;
+use crate::errors::Result;
+use crate::variables::VariableValue;
+use crate::variables::Variables;
+use std::result::Result as RResult;
+use utils::patch::types::Bytes;
+
+// 二元运算符及优先级，数值越大越先计算
+const BINARY_OPERATORS: [(&str, u8); 13] = [
+    ("||", 1),
+    ("&&", 2),
+    ("==", 3),
+    ("!=", 3),
+    ("<=", 4),
+    (">=", 4),
+    ("<", 4),
+    (">", 4),
+    ("+", 5),
+    ("-", 5),
+    ("*", 6),
+    ("/", 6),
+    ("%", 6),
+];
+const UNARY_OPERATORS: [&str; 2] = ["-", "!"];
+// pad、hex 的最大宽度
+const MAX_WIDTH: usize = 64;
+// 括号、函数参数和一元运算符的最大嵌套层数
+const MAX_DEPTH: usize = 32;
+
+/// 计算过程的结果，None 表示引用了未定义或未替换完成的变量，错误为原因说明
+type Eval = RResult<Option<VariableValue>, String>;
+
+/// `${...}` 中的表达式
+///
+/// 只能读取变量和调用内置函数，没有赋值、循环和外部访问：
+/// - 字面量：`12`、`0x1F`、`1.5`、`"text"`、`'text'`、`true`、`false`
+/// - 运算：`+ - * / %`、`== != < <= > >=`、`&& || !`，`+` 的任一侧为字符串时拼接
+/// - 函数：`if(c, a, b)`、`str(x)`、`int(x)`、`pad(x, width[, fill])`、`upper(s)`、`lower(s)`、
+///   `hex(n[, digits])`、`le(n, size)`、`be(n, size)`、`utf8(s)`、`utf16(s)`
+///
+/// 整数结果非负时为 Usize，与地址变量类型一致，负数为 Number
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(VariableValue),
+    Ident(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl Expression {
+    pub fn parse<S: Into<String>>(source: S) -> Result<Self> {
+        let source = source.into();
+        let expr = Parser::new(&source)
+            .and_then(|mut parser| parser.parse())
+            .map_err(|reason| invalid(&source, reason))?;
+        Ok(Self { source, expr })
+    }
+
+    /// 引用了未定义或者值中仍有 `${...}` 的变量时返回 None，调用方保留原文本
+    pub fn eval(&self, variables: &Variables) -> Result<Option<VariableValue>> {
+        eval(&self.expr, variables).map_err(|reason| invalid(&self.source, reason))
+    }
+
+    /// 表达式引用的变量，不包含函数名
+    pub fn identifiers(&self) -> Vec<&str> {
+        let mut identifiers = Vec::new();
+        collect_identifiers(&self.expr, &mut identifiers);
+        identifiers
+    }
+}
+
+fn invalid(source: &str, reason: String) -> ConfigError {
+    ConfigError::InvalidVariable(format!("${{{}}}", source), reason)
+}
+
+fn collect_identifiers<'a>(expr: &'a Expr, identifiers: &mut Vec<&'a str>) {
+    match expr {
+        Expr::Literal(_) => {}
+        Expr::Ident(code) => {
+            if !identifiers.contains(&code.as_str()) {
+                identifiers.push(code);
+            }
+        }
+        Expr::Unary(_, expr) => collect_identifiers(expr, identifiers),
+        Expr::Binary(_, left, right) => {
+            collect_identifiers(left, identifiers);
+            collect_identifiers(right, identifiers);
+        }
+        Expr::Call(_, args) => args
+            .iter()
+            .for_each(|arg| collect_identifiers(arg, identifiers)),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> RResult<Self, String> {
+        Ok(Self {
+            tokens: tokenize(source)?,
+            pos: 0,
+            depth: 0,
+        })
+    }
+
+    fn parse(&mut self) -> RResult<Expr, String> {
+        if self.tokens.is_empty() {
+            return Err("表达式为空".to_string());
+        }
+        let expr = self.parse_binary(0)?;
+        match self.peek() {
+            Some(token) => Err(format!("多余的内容 {:?}", token)),
+            None => Ok(expr),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, name: &str) -> RResult<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(format!("缺少 {}", name)),
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> RResult<Expr, String> {
+        let mut left = self.parse_unary()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let Some((op, precedence)) = BINARY_OPERATORS.into_iter().find(|(o, _)| o == op) else {
+                break;
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let right = self.parse_binary(precedence + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// 每层嵌套都经过这里，超过 MAX_DEPTH 时报错，避免递归过深
+    fn parse_unary(&mut self) -> RResult<Expr, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("嵌套超过 {} 层", MAX_DEPTH));
+        }
+        let expr = if let Some(Token::Op(op)) = self.peek()
+            && let Some(op) = UNARY_OPERATORS.into_iter().find(|o| o == op)
+        {
+            self.pos += 1;
+            Expr::Unary(op, Box::new(self.parse_unary()?))
+        } else {
+            self.parse_primary()?
+        };
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> RResult<Expr, String> {
+        match self.next() {
+            Some(Token::Int(n)) => Ok(Expr::Literal(int_value(n))),
+            Some(Token::Float(f)) => Ok(Expr::Literal(VariableValue::Float(f))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(VariableValue::String(s))),
+            Some(Token::LParen) => {
+                let expr = self.parse_binary(0)?;
+                self.expect(Token::RParen, ")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::LParen) => {
+                self.pos += 1;
+                let mut args = Vec::new();
+                if self.peek() == Some(&Token::RParen) {
+                    self.pos += 1;
+                    return Ok(Expr::Call(name, args));
+                }
+                loop {
+                    args.push(self.parse_binary(0)?);
+                    match self.next() {
+                        Some(Token::Comma) => continue,
+                        Some(Token::RParen) => break,
+                        _ => return Err(format!("函数 {} 缺少 )", name)),
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(VariableValue::Boolean(true))),
+                "false" => Ok(Expr::Literal(VariableValue::Boolean(false))),
+                _ => Ok(Expr::Ident(name)),
+            },
+            Some(token) => Err(format!("无法识别 {:?}", token)),
+            None => Err("表达式不完整".to_string()),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> RResult<Vec<Token>, String> {
+    let chars = source.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        match c {
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            ',' => tokens.push(Token::Comma),
+            '"' | '\'' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\\') => {
+                            let escaped = chars.get(i + 1).ok_or("字符串未结束")?;
+                            text.push(*escaped);
+                            i += 2;
+                        }
+                        Some(q) if *q == c => break,
+                        Some(ch) => {
+                            text.push(*ch);
+                            i += 1;
+                        }
+                        None => return Err("字符串未结束".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(text));
+            }
+            c if c.is_ascii_digit() => {
+                while i + 1 < chars.len()
+                    && (chars[i + 1].is_ascii_alphanumeric() || chars[i + 1] == '.')
+                {
+                    i += 1;
+                }
+                let text = chars[start..=i].iter().collect::<String>();
+                tokens.push(parse_number(&text)?);
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                while i + 1 < chars.len() && (chars[i + 1].is_alphanumeric() || chars[i + 1] == '_')
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..=i].iter().collect()));
+            }
+            _ => {
+                let rest = chars[i..].iter().take(2).collect::<String>();
+                let op = BINARY_OPERATORS
+                    .iter()
+                    .map(|(op, _)| *op)
+                    .chain(UNARY_OPERATORS)
+                    .find(|op| rest.starts_with(op))
+                    .ok_or_else(|| format!("无法识别的字符 {}", c))?;
+                i += op.len() - 1;
+                tokens.push(Token::Op(op));
+            }
+        }
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> RResult<Token, String> {
+    let invalid = || format!("无效的数字 {}", text);
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return i64::from_str_radix(hex, 16)
+            .map(Token::Int)
+            .map_err(|_| invalid());
+    }
+    if text.contains('.') {
+        return text.parse::<f64>().map(Token::Float).map_err(|_| invalid());
+    }
+    text.parse::<i64>().map(Token::Int).map_err(|_| invalid())
+}
+
+fn eval(expr: &Expr, variables: &Variables) -> Eval {
+    match expr {
+        Expr::Literal(value) => Ok(Some(value.clone())),
+        Expr::Ident(code) => match variables.find_variable(code) {
+            Some(v) if v.as_str().is_some_and(|s| s.contains("${")) => Ok(None),
+            Some(v) => Ok(Some(v.get_value().clone())),
+            None => Ok(None),
+        },
+        Expr::Unary(op, expr) => {
+            let Some(value) = eval(expr, variables)? else {
+                return Ok(None);
+            };
+            match *op {
+                "!" => Ok(Some(VariableValue::Boolean(!truthy(&value)))),
+                _ => match to_number(&value)? {
+                    Number::Int(n) => Ok(Some(int_value(n.checked_neg().ok_or("数值溢出")?))),
+                    Number::Float(f) => Ok(Some(VariableValue::Float(-f))),
+                },
+            }
+        }
+        Expr::Binary(op @ ("&&" | "||"), left, right) => {
+            let Some(left) = eval(left, variables)? else {
+                return Ok(None);
+            };
+            if truthy(&left) == (*op == "||") {
+                return Ok(Some(VariableValue::Boolean(truthy(&left))));
+            }
+            let Some(right) = eval(right, variables)? else {
+                return Ok(None);
+            };
+            Ok(Some(VariableValue::Boolean(truthy(&right))))
+        }
+        Expr::Binary(op, left, right) => {
+            let (Some(left), Some(right)) = (eval(left, variables)?, eval(right, variables)?)
+            else {
+                return Ok(None);
+            };
+            binary(op, &left, &right).map(Some)
+        }
+        Expr::Call(name, args) if name == "if" => {
+            let [cond, then, otherwise] = args.as_slice() else {
+                return Err("if 需要 3 个参数".to_string());
+            };
+            let Some(cond) = eval(cond, variables)? else {
+                return Ok(None);
+            };
+            eval(if truthy(&cond) { then } else { otherwise }, variables)
+        }
+        Expr::Call(name, args) => {
+            let mut values = Vec::new();
+            for arg in args {
+                let Some(value) = eval(arg, variables)? else {
+                    return Ok(None);
+                };
+                values.push(value);
+            }
+            call(name, &values).map(Some)
+        }
+    }
+}
+
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+fn to_number(value: &VariableValue) -> RResult<Number, String> {
+    match value {
+        VariableValue::Number(n) => Ok(Number::Int(*n)),
+        VariableValue::Usize(u) => i64::try_from(*u)
+            .map(Number::Int)
+            .map_err(|_| format!("数值 {} 超出范围", u)),
+        VariableValue::Float(f) => Ok(Number::Float(*f)),
+        _ => Err(format!("{} 不是数字，可以使用 int() 转换", value)),
+    }
+}
+
+fn to_int(value: &VariableValue) -> RResult<i64, String> {
+    match to_number(value)? {
+        Number::Int(n) => Ok(n),
+        Number::Float(f) => Err(format!("{} 不是整数", f)),
+    }
+}
+
+fn to_usize(value: &VariableValue) -> RResult<usize, String> {
+    usize::try_from(to_int(value)?).map_err(|_| format!("{} 不能为负数", value))
+}
+
+fn int_value(n: i64) -> VariableValue {
+    match usize::try_from(n) {
+        Ok(u) => VariableValue::Usize(u),
+        Err(_) => VariableValue::Number(n),
+    }
+}
+
+fn truthy(value: &VariableValue) -> bool {
+    match value {
+        VariableValue::Boolean(b) => *b,
+        VariableValue::String(s) => !s.is_empty(),
+        VariableValue::Number(n) => *n != 0,
+        VariableValue::Usize(u) => *u != 0,
+        VariableValue::Float(f) => *f != 0.0,
+    }
+}
+
+fn binary(op: &str, left: &VariableValue, right: &VariableValue) -> RResult<VariableValue, String> {
+    let is_string = |v: &VariableValue| matches!(v, VariableValue::String(_));
+    if op == "+" && (is_string(left) || is_string(right)) {
+        return Ok(VariableValue::String(format!("{}{}", left, right)));
+    }
+    if matches!(op, "==" | "!=") {
+        let equal = match (to_number(left), to_number(right)) {
+            (Ok(Number::Int(a)), Ok(Number::Int(b))) => a == b,
+            (Ok(a), Ok(b)) => as_f64(a) == as_f64(b),
+            _ => left.to_string() == right.to_string(),
+        };
+        return Ok(VariableValue::Boolean(equal == (op == "==")));
+    }
+    let value = match (to_number(left)?, to_number(right)?) {
+        (Number::Int(a), Number::Int(b)) => {
+            let result = match op {
+                "+" => a.checked_add(b),
+                "-" => a.checked_sub(b),
+                "*" => a.checked_mul(b),
+                "/" if b == 0 => return Err("除数为 0".to_string()),
+                "/" => a.checked_div(b),
+                "%" if b == 0 => return Err("除数为 0".to_string()),
+                "%" => a.checked_rem(b),
+                _ => return Ok(VariableValue::Boolean(compare(op, a.cmp(&b)))),
+            };
+            int_value(result.ok_or("数值溢出")?)
+        }
+        (a, b) => {
+            let (a, b) = (as_f64(a), as_f64(b));
+            match op {
+                "+" => VariableValue::Float(a + b),
+                "-" => VariableValue::Float(a - b),
+                "*" => VariableValue::Float(a * b),
+                "/" => VariableValue::Float(a / b),
+                "%" => VariableValue::Float(a % b),
+                _ => {
+                    let ordering = a.partial_cmp(&b).ok_or("无法比较")?;
+                    VariableValue::Boolean(compare(op, ordering))
+                }
+            }
+        }
+    };
+    Ok(value)
+}
+
+fn as_f64(number: Number) -> f64 {
+    match number {
+        Number::Int(n) => n as f64,
+        Number::Float(f) => f,
+    }
+}
+
+fn compare(op: &str, ordering: std::cmp::Ordering) -> bool {
+    match op {
+        "<" => ordering.is_lt(),
+        "<=" => ordering.is_le(),
+        ">" => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    }
+}
+
+fn call(name: &str, args: &[VariableValue]) -> RResult<VariableValue, String> {
+    let arity = |min: usize, max: usize| {
+        if args.len() < min || args.len() > max {
+            return Err(format!("{} 的参数个数不正确", name));
+        }
+        Ok(())
+    };
+    let value = match name {
+        "str" => {
+            arity(1, 1)?;
+            VariableValue::String(args[0].to_string())
+        }
+        "int" => {
+            arity(1, 1)?;
+            match &args[0] {
+                VariableValue::String(s) => match parse_number(s.trim()) {
+                    Ok(Token::Int(n)) => int_value(n),
+                    _ => return Err(format!("{} 不是整数", s)),
+                },
+                VariableValue::Boolean(b) => VariableValue::Usize(*b as usize),
+                VariableValue::Float(f) => int_value(f.trunc() as i64),
+                value => int_value(to_int(value)?),
+            }
+        }
+        "pad" => {
+            arity(2, 3)?;
+            let width = check_width(to_usize(&args[1])?)?;
+            let fill = match args.get(2) {
+                Some(fill) => fill.to_string().chars().next().ok_or("填充字符为空")?,
+                None => '0',
+            };
+            let text = args[0].to_string();
+            let count = width.saturating_sub(text.chars().count());
+            VariableValue::String(format!("{}{}", fill.to_string().repeat(count), text))
+        }
+        "upper" => {
+            arity(1, 1)?;
+            VariableValue::String(args[0].to_string().to_uppercase())
+        }
+        "lower" => {
+            arity(1, 1)?;
+            VariableValue::String(args[0].to_string().to_lowercase())
+        }
+        "hex" => {
+            arity(1, 2)?;
+            let n = to_usize(&args[0])?;
+            let digits = args.get(1).map(to_usize).transpose()?.unwrap_or(0);
+            let digits = check_width(digits)?;
+            VariableValue::String(format!("{:0width$X}", n, width = digits))
+        }
+        "le" | "be" => {
+            arity(2, 2)?;
+            let mut bytes = int_bytes(to_int(&args[0])?, to_usize(&args[1])?)?;
+            if name == "be" {
+                bytes.reverse();
+            }
+            VariableValue::String(Bytes::new(bytes).to_hex())
+        }
+        "utf8" => {
+            arity(1, 1)?;
+            VariableValue::String(Bytes::new(args[0].to_string()).to_hex())
+        }
+        "utf16" => {
+            arity(1, 1)?;
+            let bytes = args[0]
+                .to_string()
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<u8>>();
+            VariableValue::String(Bytes::new(bytes).to_hex())
+        }
+        _ => return Err(format!("未知函数 {}", name)),
+    };
+    Ok(value)
+}
+
+fn check_width(width: usize) -> RResult<usize, String> {
+    if width > MAX_WIDTH {
+        return Err(format!("宽度 {} 超过 {}", width, MAX_WIDTH));
+    }
+    Ok(width)
+}
+
+/// 整数按小端序取 size 个字节，负数使用补码，超出范围时报错
+fn int_bytes(n: i64, size: usize) -> RResult<Vec<u8>, String> {
+    if size == 0 || size > 8 {
+        return Err(format!("字节数 {} 应为 1 到 8", size));
+    }
+    let bits = size as u32 * 8;
+    let fits = if n < 0 {
+        bits == 64 || n >= -(1i64 << (bits - 1))
+    } else {
+        bits == 64 || (n as u64) >> bits == 0
+    };
+    if !fits {
+        return Err(format!("{} 无法用 {} 字节表示", n, size));
+    }
+    Ok(n.to_le_bytes()[..size].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variables::ISMAIN_CODE;
+    use crate::variables::NUM_CODE;
+
+    fn eval_str(source: &str, variables: &Variables) -> Option<String> {
+        let value = Expression::parse(source).unwrap().eval(variables).unwrap();
+        value.map(|value| value.to_string())
+    }
+
+    #[test]
+    fn test_expression() {
+        let mut variables = Variables::default();
+        variables.set_value(NUM_CODE, 3usize);
+        variables.set_value(ISMAIN_CODE, false);
+        variables.set_value("name", "微信");
+        variables.set_value("pending", "${install_location}/a.exe");
+
+        let cases = [
+            ("num + 1", "4"),
+            ("(num + 1) * 2 - 10 / 4 % 3", "6"),
+            ("pad(num, 3)", "003"),
+            ("pad(num, 3, ' ')", "  3"),
+            ("hex(num * 100, 4)", "012C"),
+            ("le(num, 4)", "03000000"),
+            ("be(0x1234, 2)", "1234"),
+            ("le(-2, 2)", "FEFF"),
+            ("utf8(str(num))", "33"),
+            ("utf16(name)", "AE5FE14F"),
+            ("if(ismain, 'main', 'app' + num + '.exe')", "app3.exe"),
+            ("upper(\"a\\\"b\")", "A\"B"),
+            ("num >= 3 && !ismain", "true"),
+            ("int('0x10') + 1.5", "17.5"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(
+                eval_str(source, &variables).as_deref(),
+                Some(expected),
+                "{}",
+                source
+            );
+        }
+
+        // 嵌套层数包括最外层
+        let nested = |depth: usize| format!("{}num{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(
+            eval_str(&nested(MAX_DEPTH - 1), &variables).as_deref(),
+            Some("3")
+        );
+
+        // 未定义或者未替换完成的变量，保留原文本
+        assert_eq!(eval_str("missing + 1", &variables), None);
+        assert_eq!(eval_str("upper(pending)", &variables), None);
+        assert_eq!(
+            eval_str("if(true, num, missing)", &variables).as_deref(),
+            Some("3")
+        );
+        assert_eq!(
+            Expression::parse("le(num, missing)").unwrap().identifiers(),
+            vec!["num", "missing"]
+        );
+
+        for source in [
+            "hex(num",
+            "num +",
+            "le(256, 1)",
+            "num / 0",
+            "nope(1)",
+            "upper(1, 2)",
+            "num + 'a",
+            "pad(num, 65)",
+            "hex(num, 100000000)",
+            &nested(MAX_DEPTH),
+            &"-".repeat(10000),
+        ] {
+            let result = Expression::parse(source).and_then(|expr| expr.eval(&variables));
+            assert!(
+                matches!(result, Err(ConfigError::InvalidVariable(..))),
+                "{} 应报错",
+                source
+            );
+        }
+    }
+}
@@ -186,6 +186,9 @@ pub struct Feature {
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     pub disabled: bool, // 是否禁用
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub overlay: bool, // 本地覆盖配置添加或修改
     #[serde(default = "default_true")]
     #[serde(skip_serializing_if = "skip_if_empty")]
     pub supported: bool, // 是否支持
@@ -249,7 +252,7 @@ impl Feature {
         if !self.target.is_empty() && (self.inhead || num != 10) {
             self.target = variables.fix_main_target(self.target.as_str());
 
-            self.target = variables.substitute(self.target.as_str());
+            self.target = variables.substitute(self.target.as_str())?;
 
             trace!("功能：{}，修正target：{}", self.get_name(), self.target);
         }
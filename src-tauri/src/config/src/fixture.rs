@@ -0,0 +1,137 @@
+use crate::Config;
+use serde_json::Value;
+use serde_json::json;
+use std::path::PathBuf;
+use tempfile::TempDir;
+use utils::patch::fixture::PeBuilder;
+use utils::store::set_test_data_dir;
+
+pub const RULE_CODE: &str = "fixture";
+pub const EXE_NAME: &str = "app.exe";
+pub const INSTALL_VERSION: &str = "4.0.1";
+
+/// push rbp; mov rbp,rsp; test eax,eax; jnz +0A; je +1C; mov rcx,rbx; call 0; pop rbp; ret
+pub const TEXT: &[u8] =
+    b"\x55\x48\x89\xE5\x85\xC0\x75\x0A\x74\x1C\x48\x8B\xCB\xE8\x00\x00\x00\x00\x5D\xC3";
+/// revoke 补丁在 .text 中的偏移
+pub const REVOKE_OFFSET: u64 = 8;
+/// multi 补丁在 .text 中的偏移
+pub const MULTI_OFFSET: u64 = 4;
+
+/// 端到端测试用的规则，在临时目录生成 PE 文件和对应的配置
+pub struct RuleFixture {
+    pub dir: PathBuf,
+    pub builder: PeBuilder,
+    // 离开作用域时删除目录
+    _temp: TempDir,
+}
+
+impl RuleFixture {
+    /// 每次创建使用新的临时目录，name 只用于区分目录名
+    pub fn new(name: &str) -> Self {
+        let temp = tempfile::Builder::new()
+            .prefix(&format!("betterwx-test-{}-", name))
+            .tempdir()
+            .unwrap();
+        let dir = temp.path().to_path_buf();
+        let builder = PeBuilder::new()
+            .section(".text", TEXT)
+            .section(".rdata", b"fixture\0");
+        builder.write(dir.join(EXE_NAME)).unwrap();
+        // 搜索缓存等数据写入临时目录
+        set_test_data_dir(Some(dir.join("data")));
+        Self {
+            dir,
+            builder,
+            _temp: temp,
+        }
+    }
+
+    pub fn exe(&self) -> PathBuf {
+        self.dir.join(EXE_NAME)
+    }
+
+    pub fn config_json(&self) -> String {
+        let location = self.dir.to_string_lossy().replace('\\', "/");
+        config_value(&location).to_string()
+    }
+
+    pub fn config(&self) -> Config {
+        serde_json::from_str(&self.config_json()).unwrap()
+    }
+}
+
+/// 安装位置和版本使用 calculate 方法，不依赖注册表和文件信息
+pub fn config_value(location: &str) -> Value {
+    json!({
+        "version": "1.0.0",
+        "rules": [{
+            "code": RULE_CODE,
+            "index": 0,
+            "version": "1.0.0",
+            "name": "测试程序",
+            "paths": [
+                {
+                    "code": "install_location",
+                    "index": 0,
+                    "file": format!("${{value}}/{}", EXE_NAME),
+                    "methods": [{ "method": "calculate", "index": 0, "args": { "value": location } }]
+                },
+                {
+                    "code": "install_version",
+                    "index": 1,
+                    "methods": [{ "method": "calculate", "index": 0, "args": { "value": INSTALL_VERSION } }]
+                }
+            ],
+            "variables": {
+                "exe_base": format!("${{install_location}}/{}", EXE_NAME),
+                "exe_back": format!("${{install_location}}/{}.bak", EXE_NAME),
+                "exe_save": "${install_location}/app${num}.exe",
+                "exe_path": "${install_location}",
+                "exe_name_base": EXE_NAME,
+                "exe_name_save": "app${num}.exe"
+            },
+            "patches": [{
+                "code": "app",
+                "basefile": "${exe_base}",
+                "backfile": "${exe_back}",
+                "savefile": "${exe_save}",
+                "patterns": [
+                    {
+                        "code": "revoke",
+                        "groups": [
+                            { "version": "4.0.0", "pattern": "74 1C 48 8B CB E8", "replace": "EB ...", "section": ".text" },
+                            { "version": "3.0.0", "pattern": "74 1C 48 8B CB E9", "replace": "EB ..." }
+                        ]
+                    },
+                    {
+                        "code": "multi",
+                        "groups": [
+                            { "version": "4.0.0", "pattern": "85 C0 75 0A", "replace": "85 C0 EB ..." }
+                        ]
+                    }
+                ]
+            }],
+            "features": [
+                {
+                    "code": "revoke",
+                    "index": 1,
+                    "name": "防撤回",
+                    "method": "patch",
+                    "bntype": "switch",
+                    "inmain": true,
+                    "dependpatches": ["revoke"]
+                },
+                {
+                    "code": "multi",
+                    "index": 2,
+                    "name": "多开",
+                    "method": "patch",
+                    "bntype": "switch",
+                    "inmain": true,
+                    "dependpatches": ["multi"]
+                }
+            ]
+        }]
+    })
+}
@@ -0,0 +1,256 @@
+use crate::errors::Result;
+use crate::validate::ConfigIssues;
+use crate::validate::validate_config;
+use serde_json::Value;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+// 文件头中指定格式，如 `# format: yaml`、`// format: json5`
+const HEADER_KEY: &str = "format:";
+const YAML_DOCUMENT: &str = "---";
+
+/// 规则配置的文件格式，发布时统一转换为紧凑的 JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Json5,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// 按名称查找同名文件时的顺序
+    pub const ALL: [ConfigFormat; 4] = [Self::Json, Self::Json5, Self::Toml, Self::Yaml];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "json5" => Some(Self::Json5),
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        Self::from_name(extension)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Json5 => "json5",
+            Self::Toml => "toml",
+            Self::Yaml => "yaml",
+        }
+    }
+
+    /// 根据内容判断格式，优先使用文件头，其次根据第一行有效内容推断，无法判断时按 JSON 处理
+    pub fn detect(data: &str) -> Self {
+        let lines = data.lines().map(str::trim).filter(|line| !line.is_empty());
+        for line in lines {
+            if let Some(format) = Self::from_header(line) {
+                return format;
+            }
+            // TOML 和 YAML 的注释
+            if line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with("//") || line.starts_with("/*") {
+                return Self::Json5;
+            }
+            if line.starts_with(YAML_DOCUMENT) {
+                return Self::Yaml;
+            }
+            if line.starts_with('{') {
+                return Self::Json;
+            }
+            // 配置顶层是对象，以 [ 开头只能是 TOML 的表
+            if line.starts_with('[') {
+                return Self::Toml;
+            }
+            return match (line.find('='), line.find(':')) {
+                (Some(eq), Some(colon)) if colon < eq => Self::Yaml,
+                (Some(_), _) => Self::Toml,
+                (None, Some(_)) => Self::Yaml,
+                (None, None) => Self::Json,
+            };
+        }
+        Self::Json
+    }
+
+    /// 按路径判断格式，扩展名无法识别时根据内容判断
+    pub fn detect_path<P: AsRef<Path>>(path: P, data: &str) -> Self {
+        Self::from_path(path).unwrap_or_else(|| Self::detect(data))
+    }
+
+    fn from_header(line: &str) -> Option<Self> {
+        let comment = line
+            .strip_prefix("//")
+            .or_else(|| line.strip_prefix('#'))?
+            .trim_start();
+        Self::from_name(comment.strip_prefix(HEADER_KEY)?)
+    }
+
+    pub fn parse(&self, data: &str) -> Result<Value> {
+        let value = match self {
+            Self::Json => serde_json::from_str(data).map_err(|e| self.error(e)),
+            Self::Json5 => json5::from_str(data).map_err(|e| self.error(e)),
+            Self::Toml => toml::from_str(data).map_err(|e| self.error(e)),
+            Self::Yaml => serde_norway::from_str(data).map_err(|e| self.error(e)),
+        }?;
+        Ok(value)
+    }
+
+    fn error<E: Display>(&self, e: E) -> ConfigIssues {
+        ConfigIssues::from_error("", format!("解析 {} 失败，{}", self.extension(), e))
+    }
+}
+
+impl Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// 查找 dir 下与 name 同名的配置文件，name 本身存在时直接使用，否则依次尝试各格式的扩展名
+pub fn find_config_file<P: AsRef<Path>>(dir: P, name: &str) -> Option<PathBuf> {
+    let path = dir.as_ref().join(name);
+    if path.exists() {
+        return Some(path);
+    }
+    ConfigFormat::ALL
+        .iter()
+        .map(|format| path.with_extension(format.extension()))
+        .find(|path| path.exists())
+}
+
+/// 读取任意格式的文件并转换为紧凑的 JSON
+pub fn read_as_json<P: AsRef<Path>>(path: P) -> Result<String> {
+    let data = fs::read_to_string(&path)?;
+    to_json(&data, ConfigFormat::detect_path(path, &data))
+}
+
+/// 转换为紧凑的 JSON，不做校验，用于更新信息等非规则配置
+pub fn to_json(data: &str, format: ConfigFormat) -> Result<String> {
+    let value = format.parse(data)?;
+    Ok(value.to_string())
+}
+
+/// 转换为发布用的规则配置，校验通过后输出紧凑的 JSON，与 update.zip 中的格式一致
+pub fn to_canonical_json(data: &str, format: ConfigFormat) -> Result<String> {
+    let value = format.parse(data)?;
+    let issues = validate_config(&value);
+    if !issues.is_empty() {
+        return Err(issues.into());
+    }
+    Ok(value.to_string())
+}
+
+/// 将 input 转换为发布用的规则配置写入 output
+pub fn write_canonical_json<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> Result<()> {
+    let data = fs::read_to_string(&input)?;
+    let json = to_canonical_json(&data, ConfigFormat::detect_path(input, &data))?;
+    fs::write(output, json)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = r#"# format: yaml
+version: 1.0.0
+rules:
+  - code: fixture
+    index: 0
+    version: 1.0.0
+    # 注释说明特征码来源
+    patches:
+      - code: app
+        basefile: "${exe_base}"
+        backfile: "${exe_back}"
+        savefile: "${exe_save}"
+        patterns:
+          - code: multi
+            groups:
+              - { version: 4.0.0, pattern: "85 C0 75 0A", replace: "85 C0 EB ..." }
+"#;
+
+    const TOML: &str = r#"version = "1.0.0"
+
+[[rules]]
+code = "fixture"
+index = 0
+version = "1.0.0"
+
+# 注释说明特征码来源
+[[rules.patches]]
+code = "app"
+basefile = "${exe_base}"
+backfile = "${exe_back}"
+savefile = "${exe_save}"
+
+[[rules.patches.patterns]]
+code = "multi"
+groups = [{ version = "4.0.0", pattern = "85 C0 75 0A", replace = "85 C0 EB ..." }]
+"#;
+
+    const JSON5: &str = r#"// 注释说明特征码来源
+{
+    version: "1.0.0",
+    rules: [{
+        code: "fixture",
+        index: 0,
+        version: "1.0.0",
+        patches: [{
+            code: "app",
+            basefile: "${exe_base}",
+            backfile: "${exe_back}",
+            savefile: "${exe_save}",
+            patterns: [{
+                code: "multi",
+                groups: [{ version: "4.0.0", pattern: "85 C0 75 0A", replace: "85 C0 EB ...", }],
+            }],
+        }],
+    }],
+}"#;
+
+    #[test]
+    fn test_config_format() {
+        assert_eq!(ConfigFormat::detect(YAML), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::detect(TOML), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::detect(JSON5), ConfigFormat::Json5);
+        assert_eq!(
+            ConfigFormat::detect("\n{\"version\": \"1.0.0\"}"),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::detect("# format: json5\n{}"),
+            ConfigFormat::Json5
+        );
+        assert_eq!(
+            ConfigFormat::from_path("config.yml"),
+            Some(ConfigFormat::Yaml)
+        );
+
+        let json = to_canonical_json(YAML, ConfigFormat::Yaml).unwrap();
+        assert_eq!(to_canonical_json(TOML, ConfigFormat::Toml).unwrap(), json);
+        assert_eq!(to_canonical_json(JSON5, ConfigFormat::Json5).unwrap(), json);
+        assert!(!json.contains('\n') && json.starts_with("{\"rules\":"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("config.toml");
+        fs::write(&input, TOML).unwrap();
+        assert_eq!(
+            find_config_file(dir.path(), "config.json"),
+            Some(input.clone())
+        );
+        let output = dir.path().join("config.json");
+        write_canonical_json(&input, &output).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), json);
+    }
+}
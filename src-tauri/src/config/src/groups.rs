@@ -1,4 +1,6 @@
+use crate::addresses::AddressCapture;
 use crate::addresses::Addresses;
+use crate::addresses::ResolvedTarget;
 use crate::errors::ConfigError;
 use crate::errors::Result;
 use crate::serders::skippers::skip_if_empty;
@@ -10,10 +12,26 @@ use macros::ImpConfigVecIsEmptyTrait;
 use macros::SortedDeserializeByVersionDesc;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::HashMap;
+use utils::empty::Empty;
+use utils::errors::UtilsError;
+use utils::patch::batch::BatchSearch;
+use utils::patch::errors::UPatchError;
 use utils::patch::patch::UPatch;
+use utils::patch::types::BytePattern;
+use utils::patch::types::Bytes;
+use utils::patch::types::SearchRange;
+use utils::patch::types::SearchScope;
+use utils::patch::xref::StringXref;
 use utils::tools::replace_ellipsis;
 use utils::version::Version;
 
+/// 批量搜索的 key：(特征码 code, 是否使用补丁码)
+pub type SearchKey = (String, bool);
+
+/// 批量搜索结果
+pub type SearchResults = HashMap<SearchKey, Vec<usize>>;
+
 #[derive(
     Debug, Clone, Serialize, Default, ImpConfigVecIsEmptyTrait, SortedDeserializeByVersionDesc,
 )]
@@ -24,14 +42,14 @@ pub struct Group {
     pub version: Version,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
-    pub pattern: String,
+    pub pattern: BytePattern,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     pub replace: String,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     // replace2 用于搜索特征码时临时使用
-    pub replace2: String,
+    pub replace2: BytePattern,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     pub name: String,
@@ -43,7 +61,158 @@ pub struct Group {
     pub disabled: bool,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
+    pub overlay: bool, // 本地覆盖配置添加或修改
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
     pub count: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 限定搜索的节，如 .text .rdata
+    pub section: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 限定搜索的 FOA/RVA 范围
+    pub range: Option<SearchRange>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 选取第 n 个匹配，从 1 开始，负数从末尾计算，-1 为最后一个
+    pub nth: i64,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 补丁位置相对匹配位置的偏移
+    pub offset: i64,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 要求附近存在另一个特征码
+    pub near: Option<NearPattern>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 跟随匹配中的相对位移解析目标地址
+    pub resolve: Option<Resolver>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 代替 pattern，通过引用的字符串定位代码
+    pub xref: Option<StringXref>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 使用 xref 时补丁的长度
+    pub len: usize,
+}
+
+/// 附近特征码所在方向
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NearDirection {
+    Before,
+    After,
+    #[default]
+    Both,
+}
+
+/// 匹配位置前后 distance 字节内必须存在的特征码
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NearPattern {
+    pub pattern: BytePattern,
+    pub distance: usize,
+    #[serde(default)]
+    pub direction: NearDirection,
+}
+
+impl NearPattern {
+    pub fn is_near(&self, data: &[u8], pos: usize, len: usize) -> bool {
+        let before = (pos.saturating_sub(self.distance), pos);
+        let after = (pos + len, (pos + len + self.distance).min(data.len()));
+        let find = |(start, end): (usize, usize)| {
+            start < end && !self.pattern.find_in(&data[start..end], false).is_empty()
+        };
+        match self.direction {
+            NearDirection::Before => find(before),
+            NearDirection::After => find(after),
+            NearDirection::Both => find(before) || find(after),
+        }
+    }
+}
+
+impl Empty for NearPattern {
+    fn is_empty(&self) -> bool {
+        self.pattern.is_empty()
+    }
+}
+
+/// 解析匹配中的 rel8/rel32 位移，如 call/jmp rel32、lea/mov [rip+disp32]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Resolver {
+    // 位移在匹配中的偏移
+    pub offset: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 位移字节数，1 或 4，默认 4
+    pub size: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 指令结束位置在匹配中的偏移，默认 offset + size
+    pub next: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    // 不为空时目标地址只作为变量导出，补丁地址仍为匹配位置
+    pub variable: String,
+}
+
+impl Resolver {
+    pub fn get_size(&self) -> usize {
+        if self.size == 0 { 4 } else { self.size }
+    }
+
+    pub fn get_next(&self) -> usize {
+        if self.next == 0 {
+            self.offset + self.get_size()
+        } else {
+            self.next
+        }
+    }
+
+    /// 计算目标 RVA = 下一条指令 RVA + 位移
+    pub fn resolve(&self, upatch: &UPatch, pos: usize, name: &str) -> Result<ResolvedTarget> {
+        let data = upatch.read(pos + self.offset, self.get_size())?;
+        let disp = match data.as_bytes() {
+            [b] => *b as i8 as i64,
+            [b0, b1, b2, b3] => i32::from_le_bytes([*b0, *b1, *b2, *b3]) as i64,
+            _ => {
+                return Err(ConfigError::ResolveAddressError(
+                    name.to_owned(),
+                    format!("不支持的位移长度 {}", self.get_size()),
+                ));
+            }
+        };
+        let next = upatch.foa_to_rva((pos + self.get_next()) as u64)? as i64;
+        let rva = next + disp;
+        if rva <= 0 {
+            return Err(ConfigError::ResolveAddressError(
+                name.to_owned(),
+                format!("目标 RVA {:#x} 无效", rva),
+            ));
+        }
+        let rva = rva as u64;
+        let va = upatch.rva_to_va(rva)?;
+        // 目标可能位于 .bss 等没有文件数据的节
+        let start = upatch.rva_to_foa(rva).ok().unwrap_or_default();
+        debug!(
+            "{} 解析位移 {:#x}，目标 RVA {:#x}，FOA {:#x}",
+            name, disp, rva, start
+        );
+        Ok(ResolvedTarget {
+            code: self.variable.clone(),
+            start: start as usize,
+            start_rva: rva as usize,
+            start_va: va as usize,
+        })
+    }
+}
+
+impl Empty for Resolver {
+    fn is_empty(&self) -> bool {
+        false
+    }
 }
 
 impl Group {
@@ -52,39 +221,126 @@ impl Group {
         // 核对 num 和 num_hex 存在
         let _ = variables.get_num()?;
         let _ = variables.get_num_hex()?;
-        if self.replace.is_empty() || self.replace.as_str() == "..." {
-            self.replace2 = "".to_string();
+        let mut replace = BytePattern::compact(&self.replace);
+        // xref 没有特征码，无法用补丁码搜索
+        if replace.is_empty() || replace.as_str() == "..." || self.is_xref() {
+            self.replace2 = BytePattern::default();
             return Ok(());
         }
         // 判断是否是包含 地址计算
-        let mut replace = self.replace.clone();
-        replace = variables.substitute(replace);
+        replace = variables.substitute(replace)?;
         replace = variables.substitute_add(replace, true, "")?;
-        replace = replace_ellipsis(&replace, &self.pattern)?;
-        self.replace2 = replace;
+        // 补丁位置不是匹配位置时，省略号部分是补丁位置的原始数据，无法预知
+        let orignal = match self.is_anchored() {
+            true => "??".repeat(self.get_len()),
+            false => self.pattern.to_hex(),
+        };
+        replace = replace_ellipsis(replace, orignal)?;
+        self.replace2 = BytePattern::parse(replace)?;
         Ok(())
     }
 
-    pub fn search(&self, upatch: &UPatch, usereplace: bool, name: &str) -> Result<Addresses> {
-        let pattern = &self.pattern;
+    /// 补丁位置不是匹配位置：offset 偏移，或者 resolve 以目标替换匹配位置
+    ///
+    /// 已修补的文件仍用特征码定位，再检查补丁位置的数据是否与 replace2 一致
+    pub fn is_anchored(&self) -> bool {
+        self.offset != 0
+            || self
+                .resolve
+                .as_ref()
+                .is_some_and(|resolver| resolver.variable.is_empty())
+    }
+
+    fn get_search_pattern(&self, usereplace: bool) -> &BytePattern {
+        match usereplace && !self.is_anchored() {
+            true => &self.replace2,
+            false => &self.pattern,
+        }
+    }
+
+    /// 把 pattern 和 replace2 加入批量搜索，xref 不参与
+    pub fn add_to_batch(&self, batch: &mut BatchSearch<SearchKey>, code: &str) {
+        if self.is_xref() {
+            return;
+        }
+        let scope = self.get_scope();
+        batch.add(
+            (code.to_owned(), false),
+            self.pattern.clone(),
+            scope.clone(),
+        );
+        batch.add(
+            (code.to_owned(), true),
+            self.get_search_pattern(true).clone(),
+            scope,
+        );
+    }
+
+    /// found 为批量搜索的结果，为空时单独搜索
+    pub fn search(
+        &self,
+        upatch: &UPatch,
+        usereplace: bool,
+        name: &str,
+        found: Option<&Vec<usize>>,
+    ) -> Result<Addresses> {
         let text = if usereplace {
             "补丁码".to_string()
         } else {
             "特征码".to_string()
         };
-        let p = if usereplace {
-            let replace2 = self.replace2.as_str();
-            if replace2 == "" {
-                return Ok(Addresses::default());
+        if usereplace && self.replace2.is_empty() {
+            return Ok(Addresses::default());
+        }
+        let p = self.get_search_pattern(usereplace);
+
+        let scope = self.get_scope();
+        let result = match &self.xref {
+            Some(xref) if self.is_xref() && !usereplace => {
+                debug!("搜索 {} 地址, 引用字符串:{}, 范围:{}", name, xref, scope);
+                if self.len == 0 {
+                    return Err(ConfigError::InvalidXrefLen(name.to_owned()));
+                }
+                upatch.search_string_xrefs(xref, &scope)
             }
-            replace2
-        } else {
-            pattern.as_str()
+            _ => match found {
+                Some(poses) => {
+                    debug!(
+                        "使用 {} 批量搜索 {} 地址, 特征码:{}, 范围:{}",
+                        text, name, p, scope
+                    );
+                    match poses.is_empty() {
+                        true => Err(UPatchError::PatternNotFindError.into()),
+                        false => Ok(poses.clone()),
+                    }
+                }
+                None => {
+                    debug!(
+                        "使用 {} 搜索 {} 地址, 特征码:{}, 范围:{}",
+                        text, name, p, scope
+                    );
+                    upatch.search_all_in(p, &scope)
+                }
+            },
         };
-
-        debug!("使用 {} 搜索 {} 地址, 特征码:{}", text, name, p);
-        match upatch.search_all(p) {
+        match result {
             Ok(poses) => {
+                let (poses, captures, targets) = match usereplace && !self.is_anchored() {
+                    // 补丁码命中的是已修补的数据，只按附近特征码筛选，不解析位移
+                    true => {
+                        let poses = self.select_near(upatch, poses, name);
+                        let len = poses.len();
+                        (poses, vec![Vec::new(); len], vec![None; len])
+                    }
+                    false => {
+                        let poses = self.select(upatch, poses, name)?;
+                        let captures = Self::capture(upatch, p, &poses)?;
+                        let (poses, targets) = self.resolve(upatch, poses, name)?;
+                        let poses = self.apply_offset(upatch, poses, name)?;
+                        self.check_patched(upatch, &poses, usereplace, name)?;
+                        (poses, captures, targets)
+                    }
+                };
                 let len = poses.len();
 
                 // 基址数量限制
@@ -102,13 +358,19 @@ impl Group {
                     name, text, poses, len, self.count
                 );
 
-                let addresses = Addresses::create(
+                let mut addresses = Addresses::create(
                     &upatch,
                     poses,
                     self.replace.as_str(),
-                    pattern.len() / 2,
+                    self.get_len(),
                     usereplace,
                 )?;
+                for ((address, target), captures) in
+                    addresses.0.iter_mut().zip(targets).zip(captures)
+                {
+                    address.target = target;
+                    address.captures = captures;
+                }
                 Ok(addresses)
             }
             Err(e) => {
@@ -117,4 +379,295 @@ impl Group {
             }
         }
     }
+
+    fn select_near(&self, upatch: &UPatch, mut poses: Vec<usize>, name: &str) -> Vec<usize> {
+        if let Some(near) = &self.near
+            && !near.is_empty()
+        {
+            let data = upatch.get_data();
+            poses.retain(|pos| near.is_near(data, *pos, self.get_len()));
+            debug!(
+                "{} 附近特征码 {} 筛选后地址:{:?}",
+                name, near.pattern, poses
+            );
+        }
+        poses
+    }
+
+    /// 按 near、nth 筛选匹配位置
+    fn select(&self, upatch: &UPatch, poses: Vec<usize>, name: &str) -> Result<Vec<usize>> {
+        let mut poses = self.select_near(upatch, poses, name);
+        if self.nth != 0 {
+            let len = poses.len() as i64;
+            let index = match self.nth > 0 {
+                true => self.nth - 1,
+                false => len + self.nth,
+            };
+            if index < 0 || index >= len {
+                return Err(ConfigError::AddressesNthError(
+                    name.to_owned(),
+                    self.nth,
+                    poses.len(),
+                ));
+            }
+            poses = vec![poses[index as usize]];
+        }
+        Ok(poses)
+    }
+
+    /// 在匹配位置解码命名捕获
+    fn capture(
+        upatch: &UPatch,
+        pattern: &BytePattern,
+        poses: &[usize],
+    ) -> Result<Vec<Vec<AddressCapture>>> {
+        poses
+            .iter()
+            .map(|pos| {
+                let data = &upatch.get_data()[*pos..];
+                let rva = upatch.foa_to_rva(*pos as u64)?;
+                pattern
+                    .captures()
+                    .iter()
+                    .map(|capture| {
+                        let bytes = &data[capture.offset..capture.offset + capture.size];
+                        Ok(AddressCapture {
+                            code: capture.name.clone(),
+                            value: capture.decode(data, rva)?,
+                            hex: Bytes::new(bytes).to_hex(),
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// 跟随位移解析目标，variable 为空时以目标 FOA 替换匹配位置
+    fn resolve(
+        &self,
+        upatch: &UPatch,
+        poses: Vec<usize>,
+        name: &str,
+    ) -> Result<(Vec<usize>, Vec<Option<ResolvedTarget>>)> {
+        let resolver = match &self.resolve {
+            Some(resolver) => resolver,
+            None => {
+                let len = poses.len();
+                return Ok((poses, vec![None; len]));
+            }
+        };
+        let mut targets = Vec::new();
+        let mut new_poses = Vec::new();
+        for pos in poses {
+            let target = resolver.resolve(upatch, pos, name)?;
+            if resolver.variable.is_empty() {
+                if target.start == 0 {
+                    return Err(ConfigError::ResolveAddressError(
+                        name.to_owned(),
+                        format!("目标 RVA {:#x} 不在文件中", target.start_rva),
+                    ));
+                }
+                new_poses.push(target.start);
+                targets.push(None);
+            } else {
+                new_poses.push(pos);
+                targets.push(Some(target));
+            }
+        }
+        Ok((new_poses, targets))
+    }
+
+    /// 补丁位置相对匹配位置偏移
+    fn apply_offset(
+        &self,
+        upatch: &UPatch,
+        mut poses: Vec<usize>,
+        name: &str,
+    ) -> Result<Vec<usize>> {
+        if self.offset != 0 {
+            poses = poses
+                .into_iter()
+                .map(|pos| {
+                    let pos = pos as i64 + self.offset;
+                    if pos < 0 || pos as usize + self.get_len() > upatch.len() {
+                        return Err(ConfigError::InvalidGroupOffset(
+                            name.to_owned(),
+                            self.offset,
+                        ));
+                    }
+                    Ok(pos as usize)
+                })
+                .collect::<Result<Vec<_>>>()?;
+        }
+        Ok(poses)
+    }
+
+    /// 补丁位置不是匹配位置时，按补丁位置的数据区分原始文件和已修补的文件
+    fn check_patched(
+        &self,
+        upatch: &UPatch,
+        poses: &[usize],
+        usereplace: bool,
+        name: &str,
+    ) -> Result<()> {
+        if !self.is_anchored() || self.replace2.is_empty() {
+            return Ok(());
+        }
+        let data = upatch.get_data();
+        let patched = poses
+            .iter()
+            .filter(|pos| self.replace2.is_match(&data[**pos..]))
+            .count();
+        let matched = match usereplace {
+            true => patched > 0 && patched == poses.len(),
+            false => patched == 0,
+        };
+        if !matched {
+            debug!(
+                "{} 补丁位置已修补 {} 个，共 {} 个",
+                name,
+                patched,
+                poses.len()
+            );
+            return Err(UtilsError::from(UPatchError::PatternNotFindError).into());
+        }
+        Ok(())
+    }
+
+    pub fn is_xref(&self) -> bool {
+        self.pattern.is_empty() && self.xref.as_ref().is_some_and(|xref| !xref.is_empty())
+    }
+
+    /// 补丁长度，xref 没有特征码时使用 len
+    pub fn get_len(&self) -> usize {
+        if self.is_xref() {
+            self.len
+        } else {
+            self.pattern.len()
+        }
+    }
+
+    pub fn get_scope(&self) -> SearchScope {
+        let section = if self.section.is_empty() {
+            None
+        } else {
+            Some(self.section.clone())
+        };
+        SearchScope::new(section, self.range.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::MULTI_OFFSET;
+    use crate::fixture::REVOKE_OFFSET;
+    use crate::fixture::RuleFixture;
+    use crate::variables::NUM_CODE;
+    use crate::variables::NUM_HEX_CODE;
+    use crate::variables::Variable;
+
+    fn group(value: serde_json::Value) -> Group {
+        let mut group: Group = serde_json::from_value(value).unwrap();
+        let mut variables = Variables::default();
+        variables.push(Variable::new(NUM_CODE, 0usize));
+        variables.push(Variable::new(NUM_HEX_CODE, "00"));
+        group.init(&variables).unwrap();
+        group
+    }
+
+    #[test]
+    fn test_init_anchored() {
+        // 补丁位置不是匹配位置时，省略号部分不能取自特征码
+        let group = group(serde_json::json!({
+            "version": "1.0.0",
+            "pattern": "85 C0 75 0A",
+            "offset": 6,
+            "replace": "EB ..."
+        }));
+        assert!(group.is_anchored());
+        assert_eq!(group.replace2.to_hex(), "EB??????");
+        assert_eq!(group.get_search_pattern(true).to_hex(), "85C0750A");
+    }
+
+    #[test]
+    fn test_init_resolve() {
+        // 以目标替换匹配位置时，补丁码只能在特征码定位后检查
+        let mut value = serde_json::json!({
+            "version": "1.0.0",
+            "pattern": "E8 ?? ?? ?? ?? 5D",
+            "resolve": { "offset": 1 },
+            "replace": "90 90 90 90 90 ..."
+        });
+        let anchored = group(value.clone());
+        assert!(anchored.is_anchored());
+        assert_eq!(anchored.replace2.to_hex(), "9090909090??");
+
+        // 目标只作为变量导出时，补丁位置仍为匹配位置
+        value["resolve"]["variable"] = "target".into();
+        let exported = group(value);
+        assert!(!exported.is_anchored());
+        assert_eq!(exported.replace2.to_hex(), "90909090905D");
+    }
+
+    #[test]
+    fn test_search_offset() {
+        let fixture = RuleFixture::new("group-offset");
+        let exe = fixture.exe().to_string_lossy().to_string();
+        let foa = fixture.builder.foa(".text", REVOKE_OFFSET).unwrap() as usize;
+        // 以 multi 的特征码定位，修补其后的 revoke 位置
+        let group = group(serde_json::json!({
+            "version": "1.0.0",
+            "pattern": "85 C0 75 0A",
+            "offset": REVOKE_OFFSET - MULTI_OFFSET,
+            "replace": "EB ..."
+        }));
+        assert_eq!(group.replace2.to_hex(), "EB??????");
+
+        let upatch = UPatch::create(&exe, &exe, false).unwrap();
+        let addresses = group.search(&upatch, false, "offset", None).unwrap();
+        assert_eq!(addresses.0[0].start, foa);
+        assert!(group.search(&upatch, true, "offset", None).is_err());
+        drop(upatch);
+
+        // 已修补的文件：特征码仍然匹配，但补丁位置已是补丁码
+        let mut data = std::fs::read(fixture.exe()).unwrap();
+        data[foa] = 0xEB;
+        std::fs::write(fixture.exe(), data).unwrap();
+        let upatch = UPatch::create(&exe, &exe, false).unwrap();
+        assert!(group.search(&upatch, false, "offset", None).is_err());
+        let addresses = group.search(&upatch, true, "offset", None).unwrap();
+        assert_eq!(addresses.0[0].start, foa);
+        assert!(addresses.0[0].patched);
+    }
+
+    #[test]
+    fn test_search_resolve() {
+        let fixture = RuleFixture::new("group-resolve");
+        let exe = fixture.exe().to_string_lossy().to_string();
+        let call = fixture.builder.foa(".text", 13).unwrap() as usize;
+        let next = fixture.builder.foa(".text", 18).unwrap() as usize;
+        let group = group(serde_json::json!({
+            "version": "1.0.0",
+            "pattern": "E8 ?? ?? ?? ?? 5D",
+            "resolve": { "offset": 1, "variable": "target" },
+            "replace": "90 90 90 90 90 ..."
+        }));
+
+        let upatch = UPatch::create(&exe, &exe, false).unwrap();
+        let addresses = group.search(&upatch, false, "resolve", None).unwrap();
+        assert_eq!(addresses.0[0].start, call);
+        assert_eq!(addresses.0[0].target.as_ref().unwrap().start, next);
+        drop(upatch);
+
+        // 已修补的文件：补丁码中没有位移，不解析目标
+        let mut data = std::fs::read(fixture.exe()).unwrap();
+        data[call..call + 5].fill(0x90);
+        std::fs::write(fixture.exe(), data).unwrap();
+        let upatch = UPatch::create(&exe, &exe, false).unwrap();
+        assert!(group.search(&upatch, false, "resolve", None).is_err());
+        let addresses = group.search(&upatch, true, "resolve", None).unwrap();
+        assert_eq!(addresses.0[0].start, call);
+        assert!(addresses.0[0].target.is_none());
+    }
 }
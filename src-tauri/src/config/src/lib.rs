@@ -1,28 +1,48 @@
 pub mod addresses;
 pub mod cache;
 pub mod dfetures;
+pub mod diffs;
 pub mod errors;
+pub mod expr;
 pub mod features;
 pub mod files;
+#[cfg(test)]
+pub(crate) mod fixture;
+pub mod format;
 pub mod groups;
+pub mod lint;
+pub mod overlay;
 pub mod patches;
 pub mod paths;
 pub mod patterns;
 pub mod rules;
+pub mod schema;
+pub mod search_cache;
 pub mod serders;
+pub mod transaction;
 pub mod update;
+pub mod validate;
 pub mod variables;
 pub mod views;
 
 use crate::files::FilesRules;
 use crate::rules::RuleType;
 use crate::serders::skippers::skip_if_empty;
+use errors::ConfigError;
 use errors::Result;
+use format::ConfigFormat;
+use lint::lint_config;
+use log::error;
+use log::warn;
+use overlay::merge_overlay;
 use rules::Rules;
 use serde::Deserialize;
 use serde::Serialize;
+use std::path::Path;
 use std::sync::Arc;
 use utils::version::Version;
+use validate::ConfigIssues;
+use validate::validate_config;
 // use std::sync::Mutex;
 use tokio::sync::Mutex;
 use tokio::sync::MutexGuard;
@@ -85,6 +105,54 @@ pub struct Config {
     pub files: FilesRules,
 }
 
+impl Config {
+    /// 解析并校验配置，有问题时返回全部问题及其 JSON Pointer
+    pub fn from_json(data: &str) -> Result<Self> {
+        Self::from_format(data, ConfigFormat::Json)
+    }
+
+    /// 解析 JSON5、TOML、YAML 等格式的配置，校验规则与 JSON 相同
+    pub fn from_format(data: &str, format: ConfigFormat) -> Result<Self> {
+        Self::from_checked_value(format.parse(data)?)
+    }
+
+    /// 按扩展名或文件头判断格式
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = std::fs::read_to_string(&path)?;
+        Self::from_format(&data, ConfigFormat::detect_path(path, &data))
+    }
+
+    /// 合并本地覆盖配置后再校验，问题的 JSON Pointer 指向合并后的配置
+    pub fn from_json_with_overlay(data: &str, overlay: &str) -> Result<Self> {
+        let mut value = ConfigFormat::Json.parse(data)?;
+        let overlay = serde_json::from_str(overlay)
+            .map_err(|e| ConfigIssues::from_error("", format!("本地覆盖配置解析失败，{}", e)))?;
+        merge_overlay(&mut value, &overlay);
+        Self::from_checked_value(value)
+    }
+
+    fn from_checked_value(value: serde_json::Value) -> Result<Self> {
+        let issues = validate_config(&value);
+        if !issues.is_empty() {
+            issues
+                .0
+                .iter()
+                .for_each(|issue| error!("配置校验失败：{}", issue));
+            return Err(ConfigError::InvalidConfig(issues));
+        }
+        let config: Config =
+            serde_json::from_value(value).map_err(|e| ConfigIssues::from_error("", e))?;
+        // 交叉引用检查只在调试时提示，不阻止加载
+        if setting::DEBUG_MODEL {
+            lint_config(&config)
+                .0
+                .iter()
+                .for_each(|d| warn!("配置检查：{}", d));
+        }
+        Ok(config)
+    }
+}
+
 pub trait ConfigVecWrapperTrait {
     type Item;
     fn get(&self, code: &str) -> Result<&Self::Item>;
@@ -0,0 +1,450 @@
+use crate::Config;
+use crate::expr::Expression;
+use crate::features::Feature;
+use crate::rules::Rule;
+use crate::variables::ISMAIN_CODE;
+use crate::variables::NUM_CODE;
+use crate::variables::NUM_HEX_CODE;
+use crate::variables::Variables;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use utils::patch::types::BytePattern;
+
+// 获取路径时 path、file 字段可用的临时变量
+const PATH_TEMP_CODES: [&str; 3] = ["value", "path", "file"];
+const SAVE_SUFFIX: &str = "_save}";
+const BASE_SUFFIX: &str = "_base}";
+
+// 功能之间的一种引用关系
+type FeatureEdges = fn(&Feature) -> &Vec<String>;
+
+/// 检查项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// dependpatches 中的特征码不存在
+    UnknownPattern,
+    /// dependfeatures、mutexfeatures、syncclosefeatures 中的功能不存在
+    UnknownFeature,
+    /// dependfeatures 或 syncclosefeatures 形成循环
+    FeatureCycle,
+    /// `${var}` 或表达式中的变量没有定义
+    UnknownVariable,
+    /// `${...}` 中的表达式无法解析
+    InvalidExpression,
+    /// `$[code|adj|len]` 中的 code 不是搜索得到的地址
+    UnknownAddress,
+}
+
+impl Display for LintKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintKind::UnknownPattern => write!(f, "特征码不存在"),
+            LintKind::UnknownFeature => write!(f, "功能不存在"),
+            LintKind::FeatureCycle => write!(f, "功能循环依赖"),
+            LintKind::UnknownVariable => write!(f, "变量未定义"),
+            LintKind::InvalidExpression => write!(f, "表达式无效"),
+            LintKind::UnknownAddress => write!(f, "地址未定义"),
+        }
+    }
+}
+
+/// 一条检查结果，location 以 code 定位，如 `rules[wx].features[revoke].dependpatches`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintDiagnostic {
+    pub kind: LintKind,
+    pub location: String,
+    pub message: String,
+}
+
+impl Display for LintDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}：{}，{}", self.location, self.kind, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LintDiagnostics(pub Vec<LintDiagnostic>);
+
+impl LintDiagnostics {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn push<S: Into<String>>(&mut self, kind: LintKind, location: String, message: S) {
+        self.0.push(LintDiagnostic {
+            kind,
+            location,
+            message: message.into(),
+        });
+    }
+}
+
+impl Display for LintDiagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for diagnostic in &self.0 {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+/// 静态检查规则配置中的交叉引用，不读取文件也不执行搜索
+pub fn lint_config(config: &Config) -> LintDiagnostics {
+    let mut diagnostics = LintDiagnostics::default();
+    for rule in &config.rules.0 {
+        RuleLinter::new(rule).lint(&mut diagnostics);
+    }
+    diagnostics
+}
+
+struct RuleLinter<'a> {
+    rule: &'a Rule,
+    location: String,
+    patterns: HashSet<String>,
+    /// 搜索后才有的地址变量：特征码、resolve 变量、命名捕获
+    addresses: HashSet<String>,
+    variables: HashSet<String>,
+    features: Vec<Feature>,
+}
+
+impl<'a> RuleLinter<'a> {
+    fn new(rule: &'a Rule) -> Self {
+        let patterns = rule
+            .patches
+            .0
+            .iter()
+            .flat_map(|patch| &patch.patterns.0)
+            .map(|pattern| pattern.code.clone())
+            .collect::<HashSet<String>>();
+        let addresses = rule.patches.address_codes();
+        let mut variables = rule.patches.address_variable_codes();
+        variables.extend(rule.variables.0.iter().map(|v| v.get_code().to_string()));
+        variables.extend(rule.paths.0.iter().map(|path| path.code.clone()));
+        variables.extend([NUM_CODE, NUM_HEX_CODE, ISMAIN_CODE].map(String::from));
+
+        // 默认功能在 get_path 时才加入，引用检查需要包含
+        let mut features = rule.features.0.clone();
+        features.extend(rule.hfeatures.0.iter().cloned());
+        if let Ok(defaults) = rule.dfeatures.init() {
+            let defaults = defaults
+                .0
+                .into_iter()
+                .filter(|d| features.iter().all(|f| f.code != d.code))
+                .collect::<Vec<Feature>>();
+            features.extend(defaults);
+        }
+
+        Self {
+            rule,
+            location: format!("rules[{}]", rule.code),
+            patterns,
+            addresses,
+            variables,
+            features,
+        }
+    }
+
+    fn lint(&self, diagnostics: &mut LintDiagnostics) {
+        self.lint_features(diagnostics);
+        self.lint_cycles(diagnostics);
+        self.lint_variables(diagnostics);
+        self.lint_addresses(diagnostics);
+    }
+
+    fn lint_features(&self, diagnostics: &mut LintDiagnostics) {
+        let codes = self
+            .features
+            .iter()
+            .map(|f| f.code.as_str())
+            .collect::<HashSet<&str>>();
+        for feature in &self.features {
+            let location = format!("{}.features[{}]", self.location, feature.code);
+            for code in &feature.dependpatches {
+                if !self.patterns.contains(code) {
+                    diagnostics.push(
+                        LintKind::UnknownPattern,
+                        format!("{}.dependpatches", location),
+                        format!("未找到特征码 {}", code),
+                    );
+                }
+            }
+            let references = [
+                ("dependfeatures", &feature.dependfeatures),
+                ("mutexfeatures", &feature.mutexfeatures),
+                ("syncclosefeatures", &feature.syncclosefeatures),
+            ];
+            for (field, references) in references {
+                for code in references
+                    .iter()
+                    .filter(|code| !codes.contains(code.as_str()))
+                {
+                    diagnostics.push(
+                        LintKind::UnknownFeature,
+                        format!("{}.{}", location, field),
+                        format!("未找到功能 {}", code),
+                    );
+                }
+            }
+        }
+    }
+
+    /// 前置功能循环时无法开启，同步关闭循环时关闭会无限递归
+    fn lint_cycles(&self, diagnostics: &mut LintDiagnostics) {
+        let graphs: [(&str, FeatureEdges); 2] = [
+            ("dependfeatures", |f| &f.dependfeatures),
+            ("syncclosefeatures", |f| &f.syncclosefeatures),
+        ];
+        for (field, edges) in graphs {
+            let graph = self
+                .features
+                .iter()
+                .map(|f| {
+                    (
+                        f.code.as_str(),
+                        edges(f).iter().map(String::as_str).collect(),
+                    )
+                })
+                .collect::<HashMap<&str, Vec<&str>>>();
+            for cycle in find_cycles(&self.features, &graph) {
+                diagnostics.push(
+                    LintKind::FeatureCycle,
+                    format!("{}.features[{}].{}", self.location, cycle[0], field),
+                    cycle.join(" -> "),
+                );
+            }
+        }
+    }
+
+    fn lint_variables(&self, diagnostics: &mut LintDiagnostics) {
+        let mut check = |location: String, text: &str, extra: &[&str]| {
+            let mut seen = HashSet::new();
+            for variable in Variables::create_js_varibales(text).0 {
+                let code = variable.get_code();
+                if !seen.insert(code.to_string())
+                    || self.variables.contains(code)
+                    || extra.contains(&code)
+                {
+                    continue;
+                }
+                let expression = match Expression::parse(code) {
+                    Ok(expression) => expression,
+                    Err(e) => {
+                        diagnostics.push(
+                            LintKind::InvalidExpression,
+                            location.clone(),
+                            e.to_string(),
+                        );
+                        continue;
+                    }
+                };
+                for code in expression.identifiers() {
+                    if !self.variables.contains(code) && !extra.contains(&code) {
+                        diagnostics.push(
+                            LintKind::UnknownVariable,
+                            location.clone(),
+                            format!("未定义变量 {}", code),
+                        );
+                    }
+                }
+            }
+        };
+        // 主程序会把 `_save}` 替换为 `_base}`
+        let main_target =
+            |text: &str| format!("{} {}", text, text.replace(SAVE_SUFFIX, BASE_SUFFIX));
+
+        for variable in &self.rule.variables.0 {
+            let location = format!("{}.variables[{}]", self.location, variable.get_code());
+            if let Some(text) = variable.as_str() {
+                check(location, text, &[]);
+            }
+        }
+        for path in &self.rule.paths.0 {
+            let location = format!("{}.paths[{}]", self.location, path.code);
+            check(format!("{}.path", location), &path.path, &PATH_TEMP_CODES);
+            check(format!("{}.file", location), &path.file, &PATH_TEMP_CODES);
+            for method in &path.methods.0 {
+                for arg in method.args.0.iter().filter_map(|arg| arg.as_str()) {
+                    check(
+                        format!("{}.methods[{}].args", location, method.index),
+                        arg,
+                        &[],
+                    );
+                }
+            }
+        }
+        for patch in &self.rule.patches.0 {
+            let location = format!("{}.patches[{}]", self.location, patch.code);
+            check(format!("{}.basefile", location), &patch.basefile, &[]);
+            check(format!("{}.backfile", location), &patch.backfile, &[]);
+            check(
+                format!("{}.savefile", location),
+                &main_target(&patch.savefile),
+                &[],
+            );
+            for pattern in &patch.patterns.0 {
+                let location = format!("{}.patterns[{}]", location, pattern.code);
+                for group in pattern.groups.0.iter().chain(pattern.group.as_ref()) {
+                    let replace = BytePattern::compact(&group.replace);
+                    check(
+                        format!("{}.groups[{}].replace", location, group.version),
+                        &replace,
+                        &[],
+                    );
+                }
+            }
+        }
+        for feature in &self.features {
+            let location = format!("{}.features[{}].target", self.location, feature.code);
+            check(location, &main_target(&feature.target), &[]);
+        }
+    }
+
+    fn lint_addresses(&self, diagnostics: &mut LintDiagnostics) {
+        for patch in &self.rule.patches.0 {
+            for pattern in &patch.patterns.0 {
+                let location = format!(
+                    "{}.patches[{}].patterns[{}]",
+                    self.location, patch.code, pattern.code
+                );
+                for group in pattern.groups.0.iter().chain(pattern.group.as_ref()) {
+                    let replace = BytePattern::compact(&group.replace);
+                    for variable in Variables::create_add_varibales(&replace).0 {
+                        let code = variable.get_code().split('|').next().unwrap_or_default();
+                        if !self.addresses.contains(code) {
+                            diagnostics.push(
+                                LintKind::UnknownAddress,
+                                format!("{}.groups[{}].replace", location, group.version),
+                                format!("未找到地址 {}，引用为 {}", code, variable.get_code()),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 按功能顺序深度优先查找环，每个环只报告一次
+fn find_cycles(features: &[Feature], graph: &HashMap<&str, Vec<&str>>) -> Vec<Vec<String>> {
+    fn visit<'a>(
+        code: &'a str,
+        graph: &HashMap<&'a str, Vec<&'a str>>,
+        stack: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        if let Some(index) = stack.iter().position(|c| *c == code) {
+            let mut cycle = stack[index..]
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>();
+            cycle.push(code.to_string());
+            cycles.push(cycle);
+            return;
+        }
+        if done.contains(code) {
+            return;
+        }
+        stack.push(code);
+        for next in graph.get(code).into_iter().flatten() {
+            visit(next, graph, stack, done, cycles);
+        }
+        stack.pop();
+        done.insert(code);
+    }
+
+    let mut cycles = Vec::new();
+    let mut done = HashSet::new();
+    for feature in features {
+        visit(
+            &feature.code,
+            graph,
+            &mut Vec::new(),
+            &mut done,
+            &mut cycles,
+        );
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConfigVecWrapperTrait;
+    use serde_json::json;
+
+    fn config() -> Config {
+        serde_json::from_value(json!({
+            "version": "1.0.0",
+            "rules": [{
+                "code": "app",
+                "index": 0,
+                "version": "1.0.0",
+                "variables": {
+                    "exe_path": ".",
+                    "exe_base": "app.exe",
+                    "exe_save": "app${num}.exe",
+                    "exe_name_base": "app.exe",
+                    "exe_name_save": "app${num}.exe"
+                },
+                "patches": [{
+                    "code": "app",
+                    "basefile": "${exe_base}",
+                    "backfile": "${exe_base}.bak",
+                    "savefile": "${exe_save}",
+                    "patterns": [
+                        { "code": "revoke", "groups": [{ "version": "1.0.0", "pattern": "74 1C", "replace": "EB ..." }] },
+                        { "code": "multi", "groups": [{ "version": "1.0.0", "pattern": "85 C0 75 0A", "replace": "85 C0 EB ..." }] }
+                    ]
+                }],
+                "features": [
+                    { "code": "revoke", "index": 1, "dependpatches": ["revoke"] },
+                    { "code": "multi", "index": 2, "dependpatches": ["multi"] }
+                ]
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_lint_config() {
+        let mut config = config();
+        assert!(lint_config(&config).is_empty(), "{}", lint_config(&config));
+
+        let rule = config.rules.get_mut("app").unwrap();
+        let revoke = rule.features.get_mut("revoke").unwrap();
+        revoke.dependpatches.push("unknown".to_string());
+        revoke.dependfeatures.push("multi".to_string());
+        revoke.mutexfeatures.push("missing".to_string());
+        revoke.target = "${if(ismain, exe_base, exe_save)} ${le(num,}".to_string();
+        rule.features
+            .get_mut("multi")
+            .unwrap()
+            .dependfeatures
+            .push("revoke".to_string());
+        let pattern = rule.patches.0[0].patterns.get_mut("multi").unwrap();
+        pattern.groups.0[0].replace = "85 C0 $[nowhere|?|1] ${undefined}".to_string();
+
+        let kinds = lint_config(&config)
+            .0
+            .into_iter()
+            .map(|d| d.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                LintKind::UnknownPattern,
+                LintKind::UnknownFeature,
+                LintKind::FeatureCycle,
+                LintKind::UnknownVariable,
+                LintKind::InvalidExpression,
+                LintKind::UnknownAddress,
+            ]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::errors::Result;
+use crate::format::find_config_file;
+use crate::format::read_as_json;
+use serde_json::Value;
+use setting::OVERLAY_NAME;
+use std::path::PathBuf;
+use utils::store::app_data_dir;
+
+pub const OVERLAY_FIELD: &str = "overlay";
+
+// 数组成员按这些字段匹配，依次尝试，分组没有 code 使用 version
+const MERGE_KEYS: [&str; 2] = ["code", "version"];
+
+// 这些数组中合并过的成员标记 overlay，用于界面显示来源
+const TRACKED_FIELDS: [&str; 5] = ["rules", "patterns", "groups", "features", "hfeatures"];
+
+/// 本地覆盖配置文件，与 Store 使用同一目录，可以使用 JSON5、TOML、YAML 格式
+pub fn overlay_path() -> Result<Option<PathBuf>> {
+    Ok(find_config_file(app_data_dir()?, OVERLAY_NAME))
+}
+
+/// 读取本地覆盖配置并转换为 JSON，文件不存在时返回 None
+pub fn read_overlay() -> Result<Option<String>> {
+    match overlay_path()? {
+        Some(path) => Ok(Some(read_as_json(path)?)),
+        None => Ok(None),
+    }
+}
+
+/// 将覆盖配置深度合并到基础配置
+///
+/// 对象逐字段合并；成员带 code 或 version 的数组按该字段匹配，匹配到的合并，未匹配的追加；
+/// 其余数组和值直接替换。禁用条目只需写 code 和 `"disabled": true`
+pub fn merge_overlay(base: &mut Value, overlay: &Value) {
+    merge_value(base, overlay, false);
+}
+
+fn merge_value(base: &mut Value, overlay: &Value, tracked: bool) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let tracked = TRACKED_FIELDS.contains(&key.as_str());
+                let base = base.entry(key.as_str()).or_insert_with(|| match value {
+                    Value::Array(_) => Value::Array(Vec::new()),
+                    Value::Object(_) => Value::Object(Default::default()),
+                    _ => Value::Null,
+                });
+                merge_value(base, value, tracked);
+            }
+        }
+        (Value::Array(base), Value::Array(overlay)) if is_keyed(overlay) => {
+            for item in overlay {
+                let index = merge_key(item)
+                    .and_then(|(key, code)| base.iter().position(|b| b.get(key) == Some(code)));
+                let index = match index {
+                    Some(index) => {
+                        merge_value(&mut base[index], item, false);
+                        index
+                    }
+                    None => {
+                        base.push(item.clone());
+                        base.len() - 1
+                    }
+                };
+                if tracked {
+                    base[index][OVERLAY_FIELD] = Value::Bool(true);
+                }
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+fn is_keyed(items: &[Value]) -> bool {
+    !items.is_empty() && items.iter().all(|item| merge_key(item).is_some())
+}
+
+fn merge_key(item: &Value) -> Option<(&'static str, &Value)> {
+    MERGE_KEYS
+        .into_iter()
+        .find_map(|key| item.get(key).filter(|v| v.is_string()).map(|v| (key, v)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Config;
+    use crate::ConfigVecWrapperTrait;
+    use crate::errors::ConfigError;
+    use crate::fixture::RULE_CODE;
+    use crate::fixture::config_value;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_overlay() {
+        let base = config_value(".").to_string();
+        let overlay = json!({
+            "rules": [{
+                "code": RULE_CODE,
+                "patches": [{
+                    "code": "app",
+                    "patterns": [{
+                        "code": "multi",
+                        "groups": [{ "version": "4.0.1", "pattern": "85 C0 75 0A", "replace": "90 90 ..." }]
+                    }]
+                }],
+                "features": [
+                    { "code": "revoke", "disabled": true },
+                    { "code": "hide", "index": 3, "name": "隐藏", "method": "patch", "dependpatches": ["multi"] }
+                ]
+            }]
+        });
+        let config = Config::from_json_with_overlay(&base, &overlay.to_string()).unwrap();
+        let rule = config.rules.get(RULE_CODE).unwrap();
+        assert!(rule.overlay);
+
+        let patterns = &rule.patches.get("app").unwrap().patterns;
+        assert!(!patterns.get("revoke").unwrap().overlay);
+        let multi = patterns.get("multi").unwrap();
+        assert!(multi.overlay);
+        let groups = multi
+            .groups
+            .0
+            .iter()
+            .map(|g| (g.replace.as_str(), g.overlay))
+            .collect::<Vec<_>>();
+        assert_eq!(groups, vec![("90 90 ...", true), ("85 C0 EB ...", false)]);
+
+        let revoke = rule.features.get("revoke").unwrap();
+        assert!(revoke.disabled && revoke.overlay && revoke.inmain);
+        assert!(rule.features.get("hide").unwrap().overlay);
+        assert!(!rule.features.get("multi").unwrap().overlay);
+
+        // 合并结果与基础配置使用相同的校验
+        let overlay =
+            json!({ "rules": [{ "code": RULE_CODE, "features": [{ "code": "broken" }] }] });
+        let result = Config::from_json_with_overlay(&base, &overlay.to_string());
+        let Err(ConfigError::InvalidConfig(issues)) = result else {
+            panic!("合并后的配置应校验失败");
+        };
+        assert!(
+            issues
+                .0
+                .iter()
+                .all(|issue| issue.pointer.starts_with("/rules/0/features/2"))
+        );
+    }
+}
@@ -1,13 +1,20 @@
+use crate::views::disasm_view::DisasmViews;
 use crate::views::orignal_view::OrignalViews;
 use crate::ConfigVecWrapperTrait;
 use crate::cache::Cache;
+use crate::diffs::DiffFile;
+use crate::diffs::DiffManifest;
 use crate::errors::ConfigError;
 use crate::errors::Result;
 use crate::features::COEXISTS_CODE;
 use crate::features::Feature;
 use crate::patterns::Pattern;
 use crate::patterns::Patterns;
+use crate::search_cache::CachedFile;
+use crate::search_cache::SearchCache;
 use crate::serders::skippers::skip_if_empty;
+use crate::variables::HEX_SUFFIX;
+use crate::variables::VA_SUFFIX;
 use crate::variables::Variables;
 use log::debug;
 use log::error;
@@ -18,13 +25,19 @@ use macros::ImpConfigVecIsEmptyTrait;
 use macros::ImpConfigVecWrapperTrait;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::path::Path;
 use utils::empty::Empty;
 use utils::file::back_file;
-use utils::file::file_is_equal;
+use utils::file::is_backup_of;
+use utils::file::is_copy_of;
+use utils::file::record_copy;
+use utils::file::record_patched;
 use utils::file::remove_file;
+use utils::patch::checksum::PeChecksum;
 use utils::patch::patch::UPatch;
+use utils::patch::signature::SignatureInfo;
 
 #[derive(
     Clone, Serialize, Deserialize, Default, ImpConfigVecIsEmptyTrait, ImpConfigVecWrapperTrait,
@@ -54,6 +67,14 @@ impl Patches {
         Ok(())
     }
 
+    /// 补丁提交后更新备份记录，制作共存时记录共存文件，修补主程序时记录新的哈希
+    pub fn record_files(&self, coexist: bool) -> Result<()> {
+        for patch in &self.0 {
+            patch.record_file(coexist)?;
+        }
+        Ok(())
+    }
+
     pub fn search(&mut self, data_cache: &mut Cache, name: &str) -> Result<()> {
         let all_bak_files = self
             .0
@@ -77,6 +98,36 @@ impl Patches {
         Ok(())
     }
 
+    /// 使用搜索缓存，任一文件校验失败时不做修改并返回 false
+    pub fn restore_search(
+        &mut self,
+        data_cache: &mut Cache,
+        search_cache: &SearchCache,
+    ) -> Result<bool> {
+        for patch in &self.0 {
+            let upatch = Self::build_upatch(patch, data_cache, true, "读取搜索缓存", false)?;
+            match search_cache.find(&patch.code) {
+                Some(cached) if cached.is_valid(patch, upatch) => {}
+                _ => return Ok(false),
+            }
+        }
+        for patch in &mut self.0 {
+            let upatch = Self::build_upatch(patch, data_cache, true, "读取搜索缓存", false)?;
+            if let Some(cached) = search_cache.find(&patch.code) {
+                patch.restore(cached, upatch);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn to_search_cache(&self) -> Result<SearchCache> {
+        let mut search_cache = SearchCache::default();
+        for patch in &self.0 {
+            search_cache.files.push(CachedFile::from_patch(patch)?);
+        }
+        Ok(search_cache)
+    }
+
     pub fn patch(&mut self, data_cache: &mut Cache, feature: &Feature, status: bool) -> Result<()> {
         for code in &feature.dependpatches {
             let patch = self.find_mut_patch_by_pattern_code(code.as_str())?;
@@ -120,6 +171,57 @@ impl Patches {
         Ok(OrignalViews(r))
     }
 
+    pub fn export_diff(
+        &self,
+        data_cache: &mut Cache,
+        pcodes: &[String],
+        manifest: &mut DiffManifest,
+        dir: &str,
+    ) -> Result<String> {
+        let mut keys = Vec::new();
+        for patch in &self.0 {
+            let upatch = Self::build_upatch(patch, data_cache, false, "导出补丁", false)?;
+            let diff_file = DiffFile::from_patch(patch, pcodes, upatch)?;
+            if !diff_file.records.is_empty() {
+                manifest.files.push(diff_file);
+                keys.push(patch.get_savefile());
+            }
+        }
+        let sources = keys
+            .iter()
+            .map(|key| data_cache.get(key).ok_or(ConfigError::CacheNotFindError))
+            .collect::<Result<Vec<_>>>()?;
+        manifest.write(dir, &sources)
+    }
+
+    pub fn import_diff(
+        &mut self,
+        data_cache: &mut Cache,
+        manifest: &DiffManifest,
+        dir: &Path,
+    ) -> Result<()> {
+        for diff_file in &manifest.files {
+            let patch = self.get(diff_file.patch.as_str())?;
+            let upatch = Self::build_upatch(patch, data_cache, false, "导入补丁", true)?;
+            diff_file.apply(upatch, dir)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_disasm(&self, data_cache: &mut Cache, feature: &Feature) -> Result<DisasmViews> {
+        let mut r = Vec::new();
+        for code in &feature.dependpatches {
+            let patch = self.find_patch_by_pattern_code(code.as_str())?;
+            let pattern = self.get_pattern(code.as_str())?;
+            if pattern.disabled {
+                continue;
+            }
+            let upatch = Self::build_upatch(patch, data_cache, false, &feature.code, false)?;
+            r.extend(pattern.read_disasm(upatch)?);
+        }
+        Ok(DisasmViews(r))
+    }
+
     pub fn check_files_and_del(&self, must_exist: bool, use_backfile: bool) -> Result<()> {
         let mut last_error = None;
         self.0.iter().for_each(|patch| {
@@ -163,7 +265,14 @@ impl Patches {
         );
 
         if exists {
-            return data_cache.get_or_insert(key, key, savefile, with_write);
+            let upatch = data_cache.get_or_insert(key, key, savefile, with_write)?;
+            if with_write && patch.checksum {
+                upatch.set_recompute_checksum(true);
+            }
+            if with_write && patch.stripsign {
+                upatch.set_strip_certificate(true);
+            }
+            return Ok(upatch);
         }
         return Err(ConfigError::FileNotExistsError(key.to_string()).into());
     }
@@ -212,12 +321,72 @@ impl Patches {
         Err(ConfigError::DependPatchNotFoundError(code.to_string()).into())
     }
 
+    /// 记录事务提交时各文件的校验和变化
+    pub fn set_checksums(&mut self, checksums: &[(String, PeChecksum)]) {
+        for patch in &mut self.0 {
+            patch.pe_checksum = checksums
+                .iter()
+                .find(|(file, _)| file == patch.get_savefile())
+                .map(|(_, checksum)| *checksum);
+        }
+    }
+
+    /// 读取主程序文件的数字签名
+    pub fn read_signatures(&mut self) {
+        for patch in &mut self.0 {
+            let basefile = patch.get_basefile();
+            if !Path::new(basefile).exists() {
+                continue;
+            }
+            match UPatch::create(basefile, basefile, false) {
+                Ok(upatch) => patch.signature = Some(upatch.get_signature().clone()),
+                Err(e) => error!("读取数字签名失败：{}，{}", basefile, e),
+            }
+        }
+    }
+
     pub fn clone_pattern(&mut self, patches: &Patches) -> Result<&Self> {
         for patch in &mut self.0 {
-            patch.patterns = patches.get(patch.code.as_str())?.patterns.clone();
+            let searched = patches.get(patch.code.as_str())?;
+            patch.patterns = searched.patterns.clone();
+            patch.signature = searched.signature.clone();
         }
         Ok(self)
     }
+
+    /// 搜索后才有的地址变量：特征码、resolve 变量、命名捕获
+    pub fn address_codes(&self) -> HashSet<String> {
+        let mut codes = HashSet::new();
+        for pattern in self.0.iter().flat_map(|patch| &patch.patterns.0) {
+            codes.insert(pattern.code.clone());
+            let groups = pattern.groups.0.iter().chain(pattern.group.as_ref());
+            for group in groups {
+                if let Some(resolve) = &group.resolve
+                    && !resolve.variable.is_empty()
+                {
+                    codes.insert(resolve.variable.clone());
+                }
+                for capture in group.pattern.captures() {
+                    codes.insert(capture.name.clone());
+                }
+            }
+        }
+        codes
+    }
+
+    /// 地址变量以及对应的 VA、原始字节变量
+    pub fn address_variable_codes(&self) -> HashSet<String> {
+        self.address_codes()
+            .into_iter()
+            .flat_map(|code| {
+                [
+                    format!("{}{}", code, VA_SUFFIX),
+                    format!("{}{}", code, HEX_SUFFIX),
+                    code,
+                ]
+            })
+            .collect()
+    }
 }
 
 impl Debug for Patches {
@@ -225,7 +394,12 @@ impl Debug for Patches {
         for p in &self.0 {
             for p in &p.patterns.0 {
                 if let Some(g) = &p.group {
-                    writeln!(f, "特征码：{} = {}", p.get_name(), g.pattern)?;
+                    match &g.xref {
+                        Some(xref) if g.is_xref() => {
+                            writeln!(f, "引用字符串：{} = {}", p.get_name(), xref)?
+                        }
+                        _ => writeln!(f, "特征码：{} = {}", p.get_name(), g.pattern)?,
+                    }
                 }
                 if !p.addresses.is_empty() {
                     for a in &p.addresses.0 {
@@ -260,6 +434,18 @@ pub struct Patch {
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     pub patched: bool,
+    // 保存时重算 PE 校验和
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub checksum: bool,
+    #[serde(skip)]
+    pub pe_checksum: Option<PeChecksum>,
+    // 保存时清除数字签名
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub stripsign: bool,
+    #[serde(skip)]
+    pub signature: Option<SignatureInfo>,
 }
 
 impl Patch {
@@ -268,10 +454,10 @@ impl Patch {
         self.patterns.init(variables)?;
         // 传入 num 构建文件路径
         if let Ok(_) = variables.get_num() {
-            self.backfile = variables.substitute(self.backfile.as_str());
-            self.basefile = variables.substitute(self.basefile.as_str());
+            self.backfile = variables.substitute(self.backfile.as_str())?;
+            self.basefile = variables.substitute(self.basefile.as_str())?;
             self.savefile = variables.fix_main_target(self.savefile.as_str());
-            self.savefile = variables.substitute(self.savefile.as_str());
+            self.savefile = variables.substitute(self.savefile.as_str())?;
         }
         Ok(())
     }
@@ -297,11 +483,22 @@ impl Patch {
             );
             self.patterns.search(upatch)?;
         }
+        self.signature = Some(upatch.get_signature().clone());
         self.supported = self.is_supported();
         self.patched = self.is_patched();
         Ok(())
     }
 
+    pub fn restore(&mut self, cached: &CachedFile, upatch: &UPatch) {
+        debug!("使用 {} 搜索缓存", self.get_name());
+        for pattern in &mut self.patterns.0 {
+            pattern.restore(cached.find(&pattern.code));
+        }
+        self.signature = Some(upatch.get_signature().clone());
+        self.supported = self.is_supported();
+        self.patched = self.is_patched();
+    }
+
     pub fn patch(&mut self, upatch: &mut UPatch, code: &str, status: bool) -> Result<()> {
         let pattern = self.patterns.get_mut(code)?;
         pattern.patch(upatch, status)
@@ -326,14 +523,29 @@ impl Patch {
         if !Path::new(basefile).exists() {
             return Err(ConfigError::BaseFileInvalid(basefile.to_string()).into());
         }
-        if must_exist {
-            if !Path::new(key).exists() || !file_is_equal(basefile, key)? {
-                return Err(ConfigError::SaveFileInvalid.into());
-            }
-        } else {
-            if Path::new(key).exists() && !file_is_equal(basefile, key)? {
-                return Err(ConfigError::SaveFileInvalid.into());
-            }
+        // 主程序直接修补原文件，只要求存在
+        if key == basefile {
+            return Ok(());
+        }
+        // 备份和共存文件按备份记录判断，不比较内容
+        let valid = match use_backfile {
+            true => is_backup_of(basefile, backfile)?,
+            false => is_copy_of(basefile, backfile, savefile)?,
+        };
+        if !valid && (must_exist || Path::new(key).exists()) {
+            return Err(ConfigError::SaveFileInvalid.into());
+        }
+        Ok(())
+    }
+
+    pub fn record_file(&self, coexist: bool) -> Result<()> {
+        let basefile = self.get_basefile();
+        let savefile = self.get_savefile();
+        let backfile = self.get_backfile();
+        if coexist {
+            record_copy(backfile, savefile)?;
+        } else if savefile == basefile {
+            record_patched(basefile, backfile)?;
         }
         Ok(())
     }
@@ -377,4 +589,17 @@ impl Patch {
     pub fn is_patched(&self) -> bool {
         self.patterns.is_patched()
     }
+
+    /// 执行补丁是否会使数字签名失效
+    pub fn breaks_signature(&self) -> bool {
+        let Some(signature) = &self.signature else {
+            return false;
+        };
+        self.patterns
+            .0
+            .iter()
+            .filter(|pattern| !pattern.disabled)
+            .flat_map(|pattern| pattern.addresses.0.iter())
+            .any(|address| signature.is_broken_by(address.start as u64, address.len as u64))
+    }
 }
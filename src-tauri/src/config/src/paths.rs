@@ -91,11 +91,11 @@ impl PathItem {
             let mut temp_vars = Variables::default();
             temp_vars.set_value(VALUE_CODE, &value);
             if !self.path.is_empty() {
-                let path = path_variables.substitute(temp_vars.substitute(&self.path));
+                let path = path_variables.substitute(temp_vars.substitute(&self.path)?)?;
                 temp_vars.set_value(PATH_CODE, &path);
             }
             if !self.file.is_empty() {
-                let file = path_variables.substitute(temp_vars.substitute(&self.file));
+                let file = path_variables.substitute(temp_vars.substitute(&self.file)?)?;
                 temp_vars.set_value(FILE_CODE, &file);
                 if !Path::new(&file).exists() {
                     error!(
@@ -136,7 +136,7 @@ impl Method {
     pub fn init(&mut self, path_variables: &Variables) -> Result<String> {
         // 替换参数变量
         for variable in &mut self.args.0 {
-            let new_value = path_variables.substitute(variable.get_value().to_string());
+            let new_value = path_variables.substitute(variable.get_value().to_string())?;
             variable.set_value(new_value);
         }
 
@@ -3,8 +3,11 @@ use crate::errors::ConfigError;
 use crate::errors::Result;
 use crate::groups::Group;
 use crate::groups::Groups;
+use crate::groups::SearchResults;
+use crate::search_cache::CachedPattern;
 use crate::serders::skippers::skip_if_empty;
 use crate::variables::Variables;
+use crate::views::disasm_view::DisasmView;
 use crate::views::orignal_view::OrignalView;
 use log::debug;
 use log::error;
@@ -15,6 +18,7 @@ use macros::ImpConfigVecWrapperTrait;
 use serde::Deserialize;
 use serde::Serialize;
 use utils::empty::Empty;
+use utils::patch::batch::BatchSearch;
 use utils::patch::patch::UPatch;
 
 #[derive(
@@ -43,9 +47,20 @@ impl Patterns {
         Ok(())
     }
 
+    /// 所有特征码一次扫描完成，再逐个处理结果
     pub fn search(&mut self, upatch: &UPatch) -> Result<()> {
+        let mut batch = BatchSearch::default();
+        for pattern in &self.0 {
+            if let Some(group) = pattern.get_search_group() {
+                group.add_to_batch(&mut batch, &pattern.code);
+            }
+        }
+        let found = match batch.is_empty() {
+            true => SearchResults::default(),
+            false => upatch.search_batch(&batch)?,
+        };
         for pattern in &mut self.0 {
-            pattern.search(upatch)?;
+            pattern.search(upatch, &found)?;
         }
         Ok(())
     }
@@ -87,6 +102,9 @@ pub struct Pattern {
     pub disabled: bool,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
+    pub overlay: bool, // 本地覆盖配置添加或修改
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
     pub supported: bool,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
@@ -168,7 +186,32 @@ impl Pattern {
         Ok(())
     }
 
-    pub fn search(&mut self, upatch: &UPatch) -> Result<()> {
+    /// 使用缓存的搜索结果，与 search 的处理一致
+    pub fn restore(&mut self, cached: Option<&CachedPattern>) {
+        self.searched = true;
+        if self.disabled {
+            self.supported = true;
+            return;
+        }
+        if !self.supported
+            && self.group.is_some()
+            && let Some(cached) = cached
+        {
+            self.addresses = cached.addresses.clone();
+            self.supported = cached.supported;
+            self.group = None;
+        }
+    }
+
+    /// 需要搜索时返回 group
+    pub fn get_search_group(&self) -> Option<&Group> {
+        match self.disabled || self.supported {
+            true => None,
+            false => self.group.as_ref(),
+        }
+    }
+
+    pub fn search(&mut self, upatch: &UPatch, found: &SearchResults) -> Result<()> {
         // 禁用不处理
         self.searched = true;
         if self.disabled {
@@ -180,14 +223,8 @@ impl Pattern {
         {
             debug!("-------------------------------------");
             let name = self.get_name();
-            if let Ok(addresses) = group.search(upatch, false, name) {
-                self.addresses = addresses;
-                self.supported = true;
-                self.group = None;
-                return Ok(());
-            }
-
-            match group.search(upatch, false, name) {
+            let key = (self.code.clone(), false);
+            match group.search(upatch, false, name, found.get(&key)) {
                 Ok(addresses) => {
                     self.addresses = addresses;
                     self.supported = true;
@@ -207,13 +244,14 @@ impl Pattern {
                 }
             }
 
-            if group.replace2.is_empty() || group.replace2.as_str() == "..." {
+            if group.replace2.is_empty() {
                 self.supported = false;
                 self.group = None;
                 return Ok(());
             }
 
-            match group.search(upatch, true, name) {
+            let key = (self.code.clone(), true);
+            match group.search(upatch, true, name, found.get(&key)) {
                 Ok(addresses) => {
                     self.addresses = addresses;
                     self.supported = true;
@@ -273,4 +311,21 @@ impl Pattern {
         }
         self.addresses.read_orignal(upatch)
     }
+
+    pub fn read_disasm(&self, upatch: &UPatch) -> Result<Vec<DisasmView>> {
+        // 禁用不处理
+        if self.disabled {
+            return Ok(Vec::new());
+        }
+
+        if self.addresses.is_empty() {
+            return Err(ConfigError::DependPatchNotFoundError(self.get_name().to_string()).into());
+        }
+        let mut views = self.addresses.read_disasm(upatch)?;
+        views.iter_mut().for_each(|view| {
+            view.pcode = self.code.clone();
+            view.pname = self.get_name().to_string();
+        });
+        Ok(views)
+    }
 }
@@ -2,6 +2,7 @@ use crate::ConfigVecWrapperTrait;
 use crate::cache::Cache;
 use crate::convert_num;
 use crate::dfetures::DFeatures;
+use crate::diffs::DiffManifest;
 use crate::errors::ConfigError;
 use crate::errors::Result;
 use crate::features::COEXISTS_CODE;
@@ -9,16 +10,21 @@ use crate::features::Features;
 use crate::files::FileRules;
 use crate::patches::Patches;
 use crate::paths::Paths;
+use crate::search_cache::SearchCache;
 use crate::serders::skippers::skip_if_empty;
+use crate::transaction::Transaction;
 use crate::variables::ISMAIN_CODE;
 use crate::variables::NUM_CODE;
 use crate::variables::NUM_HEX_CODE;
+use crate::variables::Resolution;
 use crate::variables::Variables;
+use crate::views::disasm_view::DisasmViews;
 use crate::views::orignal_view::OrignalViews;
 use log::debug;
 use log::error;
 use log::info;
 use log::trace;
+use log::warn;
 use macros::FieldDescGetters;
 use macros::FieldNameGetters;
 use macros::ImpConfigVecIsEmptyTrait;
@@ -28,6 +34,7 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_repr::Deserialize_repr;
 use serde_repr::Serialize_repr;
+use std::path::Path;
 use tokio::task::JoinSet;
 use utils::patch::types::Bytes;
 
@@ -61,6 +68,9 @@ pub struct Rule {
     pub disabled: bool,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
+    pub overlay: bool, // 本地覆盖配置添加或修改
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
     pub supported: bool, // 搜索后使用 是否支持
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
@@ -83,6 +93,8 @@ pub struct Rule {
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     pub hfeatures: Features, // 头部功能配置
+    #[serde(skip)]
+    pub resolution: Option<Resolution>, // 变量替换顺序，获取路径后使用
 }
 
 /// init
@@ -91,12 +103,11 @@ impl Rule {
         self.check_is_config_type()?;
         info!("正在获取 {} 安装位置...", self.get_name());
         self.init_path()?
-            // 二次替换，确保全部变量替换完成
-            .init_variables()?
             .init_variables()?
             .init_patches()?
             .init_dfeatures()?
             .init_features()?;
+        self.patches.read_signatures();
         self.rtype = RuleType::Pathed;
         info!("配置规则 {} 初始化完成", self.get_name());
         Ok(self)
@@ -116,7 +127,30 @@ impl Rule {
         rule.patches.check_files_and_del(true, true)?;
 
         let mut cache = Cache::new();
-        rule.patches.search(&mut cache, self.get_name())?;
+        // 本地覆盖配置会改变特征码，缓存按规则版本存储无法区分，不读写缓存
+        let search_cache = match self.overlay {
+            true => None,
+            false => SearchCache::load(&self.code, &self.version),
+        };
+        let restored = match search_cache {
+            Some(search_cache) => rule.patches.restore_search(&mut cache, &search_cache)?,
+            None => false,
+        };
+        if restored {
+            info!("{} 使用搜索缓存，跳过搜索", self.get_name());
+        } else {
+            rule.patches.search(&mut cache, self.get_name())?;
+            let saved = match self.overlay {
+                true => Ok(()),
+                false => rule
+                    .patches
+                    .to_search_cache()
+                    .and_then(|search_cache| search_cache.save(&self.code, &self.version)),
+            };
+            if let Err(e) = saved {
+                warn!("保存 {} 搜索缓存失败：{}", self.get_name(), e);
+            }
+        }
         self.patches.clone_pattern(&rule.patches)?;
         let pvariables = Variables::try_from(&self.patches)?;
         self.variables.extend(pvariables);
@@ -150,7 +184,10 @@ impl Rule {
     }
 
     fn init_variables(&mut self) -> Result<&mut Self> {
-        self.variables.init()?;
+        // 共存序号和搜索后的地址此时还未定义，引用它们的变量在 build_by_num 中替换
+        let mut deferred = self.patches.address_variable_codes();
+        deferred.extend([NUM_CODE, NUM_HEX_CODE, ISMAIN_CODE].map(String::from));
+        self.resolution = Some(self.variables.resolve(&deferred)?);
         trace!("替换后的变量为：\n{:?}", self.variables);
         Ok(self)
     }
@@ -280,8 +317,15 @@ impl Rule {
         let mut rule = self.clone();
         rule.variables.set_value(ISMAIN_CODE, ismain);
         rule.variables.set_value(NUM_CODE, num);
-        rule.variables.set_value(NUM_HEX_CODE, num_hex); 
-        rule.init_variables()?.init_patches()?;
+        rule.variables.set_value(NUM_HEX_CODE, num_hex);
+        // 只重新替换依赖共存序号和地址的变量
+        match &self.resolution {
+            Some(resolution) => rule.variables.resolve_deferred(resolution)?,
+            None => {
+                rule.init_variables()?;
+            }
+        }
+        rule.init_patches()?;
         if num == 10 {
             return Ok(rule);
         }
@@ -297,6 +341,7 @@ impl Rule {
         rule.installed = false;
         // 变量 后续不需要使用了
         rule.variables.clear();
+        rule.resolution = None;
         Ok(rule)
     }
 
@@ -317,11 +362,46 @@ impl Rule {
         status: bool,
         old_cache: Option<&mut Cache>,
     ) -> Result<()> {
-        let (save, cache) = match old_cache {
-            Some(cache) => (false, cache),
-            None => (true, &mut Cache::new()),
-        };
+        match old_cache {
+            Some(cache) => self.patch_in_cache(fcode, status, cache, false),
+            None => {
+                let mut transaction = Transaction::new();
+                self.patch_with_transaction(fcode, status, &mut transaction)?;
+                self.commit_transaction(fcode, &mut transaction)
+            }
+        }
+    }
 
+    /// 在事务中执行补丁，由调用方负责提交
+    pub fn patch_with_transaction(
+        &mut self,
+        fcode: &str,
+        status: bool,
+        transaction: &mut Transaction,
+    ) -> Result<()> {
+        self.patch_in_cache(fcode, status, transaction.get_cache(), true)
+    }
+
+    /// 提交事务，失败时文件已恢复，重新读取补丁状态
+    pub fn commit_transaction(&mut self, fcode: &str, transaction: &mut Transaction) -> Result<()> {
+        if let Err(e) = transaction.commit() {
+            if fcode != COEXISTS_CODE {
+                let _ = self.set_patched(None);
+            }
+            return Err(e);
+        }
+        self.patches.set_checksums(transaction.get_checksums());
+        self.patches.record_files(fcode == COEXISTS_CODE)?;
+        Ok(())
+    }
+
+    fn patch_in_cache(
+        &mut self,
+        fcode: &str,
+        status: bool,
+        cache: &mut Cache,
+        save: bool,
+    ) -> Result<()> {
         if self.rtype != RuleType::Fileed {
             return Err(ConfigError::IsNotFileRule.into());
         }
@@ -366,7 +446,6 @@ impl Rule {
             if !use_backfile {
                 self.set_patched(Some(cache))?;
             }
-            cache.save()?;
         }
 
         info!("{} 补丁 执行完毕", name);
@@ -375,16 +454,17 @@ impl Rule {
     }
 
     pub fn patch_by_replace(&mut self, fcode: &str, ovs: &OrignalViews) -> Result<()> {
-        let mut cache = Cache::new();
+        let mut transaction = Transaction::new();
         if self.rtype != RuleType::Fileed {
             return Err(ConfigError::IsNotFileRule.into());
         }
         let feature = self.features.get(fcode)?;
         // 执行补丁功能
-        self.patches.patch_by_replace(&mut cache, feature, ovs)?;
+        self.patches
+            .patch_by_replace(transaction.get_cache(), feature, ovs)?;
 
-        // 写入到文件
-        cache.save()?;
+        // 写入到文件，失败时与 commit_transaction 一样重置修补状态
+        self.commit_transaction(fcode, &mut transaction)?;
 
         info!("{} 补丁 执行完毕", self.get_name());
 
@@ -405,6 +485,45 @@ impl Rule {
     }
 }
 
+/// diff
+impl Rule {
+    /// 导出补丁文件，fcode 为空时导出当前已修补的全部数据
+    pub fn export_diff(&self, fcode: &str, dir: &str) -> Result<String> {
+        if self.rtype != RuleType::Fileed {
+            return Err(ConfigError::IsNotFileRule.into());
+        }
+        let pcodes = match fcode.is_empty() {
+            true => Vec::new(),
+            false => self.features.get(fcode)?.dependpatches.clone(),
+        };
+        let mut manifest = DiffManifest::new(self.code.as_str(), fcode);
+        let mut cache = Cache::new();
+        self.patches
+            .export_diff(&mut cache, &pcodes, &mut manifest, dir)
+    }
+
+    pub fn import_diff(&mut self, file: &str) -> Result<()> {
+        if self.rtype != RuleType::Fileed {
+            return Err(ConfigError::IsNotFileRule.into());
+        }
+        let manifest = DiffManifest::read(file)?;
+        if manifest.rule != self.code {
+            return Err(ConfigError::InvalidDiffManifest(format!(
+                "规则 {} 与当前规则 {} 不一致",
+                manifest.rule, self.code
+            )));
+        }
+        info!("正在导入补丁：{}", file);
+        let dir = Path::new(file).parent().unwrap_or(Path::new(""));
+        let mut transaction = Transaction::new();
+        self.patches
+            .import_diff(transaction.get_cache(), &manifest, dir)?;
+        self.commit_transaction(manifest.feature.as_str(), &mut transaction)?;
+        self.set_patched(None)?;
+        Ok(())
+    }
+}
+
 /// read
 impl Rule {
     pub fn read_orignal(&self, fcode: &str) -> Result<OrignalViews> {
@@ -412,6 +531,12 @@ impl Rule {
         let feature = self.features.get(fcode)?;
         self.patches.read_orignal(&mut cache, &feature)
     }
+
+    pub fn read_disasm(&self, fcode: &str) -> Result<DisasmViews> {
+        let mut cache = Cache::new();
+        let feature = self.features.get(fcode)?;
+        self.patches.read_disasm(&mut cache, feature)
+    }
 }
 
 /// chech rtype
@@ -466,3 +591,161 @@ impl Default for RuleType {
         RuleType::Config
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use crate::fixture::MULTI_OFFSET;
+    use crate::fixture::REVOKE_OFFSET;
+    use crate::fixture::RULE_CODE;
+    use crate::fixture::RuleFixture;
+    use crate::views::address_view::AddressView;
+    use crate::views::features_view::FeaturesView;
+    use serde_json::Value;
+    use serde_json::json;
+
+    #[test]
+    fn test_rule_pipeline() {
+        let fixture = RuleFixture::new("rule-pipeline");
+        let orignal = fixture.builder.build();
+        let mut config = fixture.config();
+        let rule = config.rules.get_mut(RULE_CODE).unwrap();
+
+        rule.get_path().unwrap();
+        assert!(rule.installed);
+        assert_eq!(rule.rtype, RuleType::Pathed);
+
+        rule.search_address().unwrap();
+        let view = AddressView::from(&*rule);
+        assert!(view.supported);
+        assert!(!view.patched);
+        assert_eq!(view.rtype, RuleType::Search as usize);
+        let revoke = rule.patches.get_pattern("revoke").unwrap();
+        let revoke_foa = fixture.builder.foa(".text", REVOKE_OFFSET).unwrap() as usize;
+        assert_eq!(revoke.addresses.0[0].start, revoke_foa);
+        assert_eq!(
+            revoke.addresses.0[0].start_rva as u64,
+            fixture.builder.rva(".text", REVOKE_OFFSET).unwrap()
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut file_rules = runtime.block_on(rule.walk_files()).unwrap();
+        assert_eq!(file_rules.rules.len(), 1);
+        let main = file_rules.rules.get_mut("0").unwrap();
+        assert!(FeaturesView::from(&main.features).0.is_empty());
+
+        main.patch("revoke", true, None).unwrap();
+        main.patch("multi", true, None).unwrap();
+        assert_eq!(
+            FeaturesView::from(&main.features).0,
+            vec!["revoke", "multi"]
+        );
+        let data = std::fs::read(fixture.exe()).unwrap();
+        let multi_foa = fixture.builder.foa(".text", MULTI_OFFSET).unwrap() as usize;
+        assert_eq!(&data[revoke_foa..revoke_foa + 2], b"\xEB\x1C");
+        assert_eq!(&data[multi_foa..multi_foa + 4], b"\x85\xC0\xEB\x0A");
+
+        // 关闭后恢复原始数据，备份文件不受影响
+        main.patch("revoke", false, None).unwrap();
+        main.patch("multi", false, None).unwrap();
+        assert!(FeaturesView::from(&main.features).0.is_empty());
+        assert_eq!(std::fs::read(fixture.exe()).unwrap(), orignal);
+        assert_eq!(
+            std::fs::read(fixture.dir.join("app.exe.bak")).unwrap(),
+            orignal
+        );
+    }
+
+    #[test]
+    fn test_search_after_patch() {
+        let fixture = RuleFixture::new("search-after-patch");
+        let orignal = fixture.builder.build();
+        let backfile = fixture.dir.join("app.exe.bak");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut config = fixture.config();
+        let rule = config.rules.get_mut(RULE_CODE).unwrap();
+        rule.get_path().unwrap();
+        rule.search_address().unwrap();
+        let mut file_rules = runtime.block_on(rule.walk_files()).unwrap();
+        let main = file_rules.rules.get_mut("0").unwrap();
+        main.patch("revoke", true, None).unwrap();
+        assert_ne!(std::fs::read(fixture.exe()).unwrap(), orignal);
+
+        // 主程序已修补，重新搜索时不覆盖备份
+        let mut config = fixture.config();
+        let rule = config.rules.get_mut(RULE_CODE).unwrap();
+        rule.get_path().unwrap();
+        rule.search_address().unwrap();
+        assert_eq!(std::fs::read(&backfile).unwrap(), orignal);
+        let revoke = rule.patches.get_pattern("revoke").unwrap();
+        assert!(revoke.supported);
+
+        // 主程序更新后重新备份
+        let mut updated = orignal.clone();
+        let rdata = fixture.builder.foa(".rdata", 0).unwrap() as usize;
+        updated[rdata] = b'F';
+        std::fs::write(fixture.exe(), &updated).unwrap();
+        let mut config = fixture.config();
+        let rule = config.rules.get_mut(RULE_CODE).unwrap();
+        rule.get_path().unwrap();
+        rule.search_address().unwrap();
+        assert_eq!(std::fs::read(&backfile).unwrap(), updated);
+    }
+
+    #[test]
+    fn test_walk_files_after_coexist() {
+        let fixture = RuleFixture::new("walk-after-coexist");
+        let mut value: Value = serde_json::from_str(&fixture.config_json()).unwrap();
+        let rule = &mut value["rules"][0];
+        rule["patches"][0]["patterns"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({
+                "code": "coexist",
+                "groups": [
+                    { "version": "4.0.0", "pattern": "66 69 78 74 75 72 65 00", "replace": "66 69 78 74 75 72 ${num_hex} 00" }
+                ]
+            }));
+        rule["features"].as_array_mut().unwrap().push(json!({
+            "code": COEXISTS_CODE,
+            "index": 3,
+            "name": "共存",
+            "method": "patch",
+            "dependpatches": ["coexist"]
+        }));
+        let mut config: Config = serde_json::from_value(value).unwrap();
+        let rule = config.rules.get_mut(RULE_CODE).unwrap();
+        rule.get_path().unwrap();
+        rule.search_address().unwrap();
+
+        let mut coexist = rule.build_by_num(1).unwrap();
+        let mut transaction = Transaction::new();
+        coexist
+            .patch_with_transaction(COEXISTS_CODE, true, &mut transaction)
+            .unwrap();
+        coexist
+            .commit_transaction(COEXISTS_CODE, &mut transaction)
+            .unwrap();
+        let coexist_exe = fixture.dir.join("app1.exe");
+        assert!(coexist_exe.exists());
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut file_rules = runtime.block_on(rule.walk_files()).unwrap();
+        assert_eq!(file_rules.rules.len(), 2);
+        assert!(coexist_exe.exists());
+
+        // 修补主程序后共存文件仍然有效
+        let main = file_rules.rules.get_mut("0").unwrap();
+        main.patch("revoke", true, None).unwrap();
+        let file_rules = runtime.block_on(rule.walk_files()).unwrap();
+        assert_eq!(file_rules.rules.len(), 2);
+        assert!(coexist_exe.exists());
+    }
+}
@@ -0,0 +1,1061 @@
+use crate::validate::ConfigIssue;
+use serde_json::Value;
+use std::sync::LazyLock;
+
+/// 内置 Schema 只解析一次，格式错误属于编码错误，直接 panic 而不是跳过校验
+static CONFIG_SCHEMA: LazyLock<Value> = LazyLock::new(|| {
+    serde_json::from_str(CONFIG_SCHEMA_STR).expect("内置配置 Schema 不是有效的 JSON")
+});
+
+/// 规则配置的 JSON Schema（draft-07），供编辑器提示和加载时校验使用
+///
+/// 只描述配置作者需要填写的字段，搜索和补丁后写入的运行时字段不做限制
+pub fn config_schema() -> &'static Value {
+    &CONFIG_SCHEMA
+}
+
+/// 按 Schema 校验，只支持 config_schema 用到的关键字
+pub struct SchemaValidator<'a> {
+    root: &'a Value,
+}
+
+impl<'a> SchemaValidator<'a> {
+    pub fn new(root: &'a Value) -> Self {
+        Self { root }
+    }
+
+    pub fn validate(&self, value: &Value, issues: &mut Vec<ConfigIssue>) {
+        self.validate_at(self.root, value, "", issues);
+    }
+
+    fn validate_at(
+        &self,
+        schema: &Value,
+        value: &Value,
+        pointer: &str,
+        issues: &mut Vec<ConfigIssue>,
+    ) {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match reference
+                .strip_prefix('#')
+                .and_then(|path| self.root.pointer(path))
+            {
+                Some(schema) => self.validate_at(schema, value, pointer, issues),
+                None => issues.push(ConfigIssue::new(
+                    pointer,
+                    format!("Schema 引用 {} 不存在", reference),
+                )),
+            }
+            return;
+        }
+
+        if let Some(types) = schema.get("type") {
+            let types = match types {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                _ => types.as_str().into_iter().collect::<Vec<&str>>(),
+            };
+            if !types.iter().any(|t| Self::is_type(value, t)) {
+                issues.push(ConfigIssue::new(
+                    pointer,
+                    format!(
+                        "类型应为 {}，实际为 {}",
+                        types.join("|"),
+                        Self::type_name(value)
+                    ),
+                ));
+                return;
+            }
+        }
+
+        if let Some(values) = schema.get("enum").and_then(Value::as_array)
+            && !values.contains(value)
+        {
+            let values = values.iter().map(Value::to_string).collect::<Vec<String>>();
+            issues.push(ConfigIssue::new(
+                pointer,
+                format!("取值应为 {}", values.join("|")),
+            ));
+        }
+
+        if let Some(minimum) = schema.get("minimum").and_then(Value::as_i64)
+            && value.as_i64().is_some_and(|n| n < minimum)
+        {
+            issues.push(ConfigIssue::new(pointer, format!("不能小于 {}", minimum)));
+        }
+
+        if let Some(min_length) = schema.get("minLength").and_then(Value::as_u64)
+            && value
+                .as_str()
+                .is_some_and(|s| (s.chars().count() as u64) < min_length)
+        {
+            issues.push(ConfigIssue::new(pointer, "不能为空"));
+        }
+
+        if let Value::Object(map) = value {
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for field in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(field) {
+                        issues.push(ConfigIssue::new(
+                            &child_pointer(pointer, field),
+                            "缺少必填字段",
+                        ));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let additional = schema.get("additionalProperties");
+            for (key, item) in map {
+                let child = child_pointer(pointer, key);
+                if let Some(schema) = properties
+                    .and_then(|properties| properties.get(key))
+                    .or(additional)
+                {
+                    self.validate_at(schema, item, &child, issues);
+                }
+            }
+        }
+
+        if let Value::Array(items) = value
+            && let Some(schema) = schema.get("items")
+        {
+            for (index, item) in items.iter().enumerate() {
+                self.validate_at(
+                    schema,
+                    item,
+                    &child_pointer(pointer, &index.to_string()),
+                    issues,
+                );
+            }
+        }
+    }
+
+    fn is_type(value: &Value, name: &str) -> bool {
+        match name {
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "null" => value.is_null(),
+            _ => false,
+        }
+    }
+
+    fn type_name(value: &Value) -> &'static str {
+        match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_f64() => "number",
+            Value::Number(_) => "integer",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+}
+
+/// 拼接 JSON Pointer，按 RFC 6901 转义 `~` 和 `/`
+pub fn child_pointer(pointer: &str, key: &str) -> String {
+    format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"))
+}
+
+const CONFIG_SCHEMA_STR: &str = r##"{
+    "$schema": "http://json-schema.org/draft-07/schema#",
+    "title": "BetterWX 规则配置",
+    "$ref": "#/definitions/Config",
+    "definitions": {
+        "Config": {
+            "type": "object",
+            "required": [
+                "version",
+                "rules"
+            ],
+            "properties": {
+                "version": {
+                    "type": "string"
+                },
+                "ruletype": {
+                    "$ref": "#/definitions/RuleType"
+                },
+                "name": {
+                    "type": "string"
+                },
+                "description": {
+                    "type": "string"
+                },
+                "disabled": {
+                    "type": "boolean"
+                },
+                "supported": {
+                    "type": "boolean"
+                },
+                "rules": {
+                    "type": "array",
+                    "items": {
+                        "$ref": "#/definitions/Rule"
+                    }
+                },
+                "files": {
+                    "type": "array"
+                }
+            }
+        },
+        "RuleType": {
+            "type": "integer",
+            "enum": [
+                0,
+                1,
+                2,
+                3
+            ]
+        },
+        "Rule": {
+            "type": "object",
+            "required": [
+                "code",
+                "index",
+                "version",
+                "patches"
+            ],
+            "properties": {
+                "code": {
+                    "$ref": "#/definitions/Code"
+                },
+                "index": {
+                    "$ref": "#/definitions/Index"
+                },
+                "version": {
+                    "type": "string"
+                },
+                "patches": {
+                    "type": "array",
+                    "items": {
+                        "$ref": "#/definitions/Patch"
+                    }
+                },
+                "rtype": {
+                    "$ref": "#/definitions/RuleType"
+                },
+                "ismain": {
+                    "type": "boolean"
+                },
+                "name": {
+                    "type": "string"
+                },
+                "news": {
+                    "type": "string"
+                },
+                "description": {
+                    "type": "string"
+                },
+                "disabled": {
+                    "type": "boolean"
+                },
+                "overlay": {
+                    "type": "boolean"
+                },
+                "supported": {
+                    "type": "boolean"
+                },
+                "patched": {
+                    "type": "boolean"
+                },
+                "installed": {
+                    "type": "boolean"
+                },
+                "paths": {
+                    "type": "array",
+                    "items": {
+                        "$ref": "#/definitions/Path"
+                    }
+                },
+                "variables": {
+                    "$ref": "#/definitions/Variables"
+                },
+                "features": {
+                    "type": "array",
+                    "items": {
+                        "$ref": "#/definitions/Feature"
+                    }
+                },
+                "dfeatures": {
+                    "type": "array",
+                    "items": {
+                        "type": "string"
+                    }
+                },
+                "hfeatures": {
+                    "type": "array",
+                    "items": {
+                        "$ref": "#/definitions/Feature"
+                    }
+                }
+            }
+        },
+        "Code": {
+            "type": "string",
+            "minLength": 1
+        },
+        "Index": {
+            "type": "integer",
+            "minimum": 0
+        },
+        "Variables": {
+            "type": "object",
+            "additionalProperties": {
+                "type": [
+                    "string",
+                    "integer",
+                    "number",
+                    "boolean"
+                ]
+            }
+        },
+        "Path": {
+            "type": "object",
+            "required": [
+                "code",
+                "index",
+                "methods"
+            ],
+            "properties": {
+                "code": {
+                    "$ref": "#/definitions/Code"
+                },
+                "index": {
+                    "$ref": "#/definitions/Index"
+                },
+                "name": {
+                    "type": "string"
+                },
+                "description": {
+                    "type": "string"
+                },
+                "methods": {
+                    "type": "array",
+                    "items": {
+                        "$ref": "#/definitions/Method"
+                    }
+                },
+                "value": {
+                    "type": "string"
+                },
+                "path": {
+                    "type": "string"
+                },
+                "file": {
+                    "type": "string"
+                },
+                "fix": {
+                    "$ref": "#/definitions/PathFix"
+                }
+            }
+        },
+        "Method": {
+            "type": "object",
+            "required": [
+                "method",
+                "index"
+            ],
+            "properties": {
+                "method": {
+                    "type": "string",
+                    "enum": [
+                        "runtime",
+                        "calculate",
+                        "fileinfo",
+                        "regedit",
+                        "readfile"
+                    ]
+                },
+                "index": {
+                    "$ref": "#/definitions/Index"
+                },
+                "args": {
+                    "$ref": "#/definitions/Variables"
+                },
+                "retry": {
+                    "$ref": "#/definitions/Index"
+                },
+                "fix": {
+                    "$ref": "#/definitions/PathFix"
+                }
+            }
+        },
+        "PathFix": {
+            "type": "object",
+            "properties": {
+                "unprefix": {
+                    "type": "string"
+                },
+                "unsuffix": {
+                    "type": "string"
+                },
+                "prefix": {
+                    "type": "string"
+                },
+                "suffix": {
+                    "type": "string"
+                },
+                "pattern": {
+                    "type": "string"
+                },
+                "replace": {
+                    "type": "string"
+                }
+            }
+        },
+        "Patch": {
+            "type": "object",
+            "required": [
+                "code",
+                "savefile",
+                "backfile",
+                "basefile"
+            ],
+            "properties": {
+                "code": {
+                    "$ref": "#/definitions/Code"
+                },
+                "savefile": {
+                    "type": "string"
+                },
+                "backfile": {
+                    "type": "string"
+                },
+                "basefile": {
+                    "type": "string"
+                },
+                "patterns": {
+                    "type": "array",
+                    "items": {
+                        "$ref": "#/definitions/Pattern"
+                    }
+                },
+                "name": {
+                    "type": "string"
+                },
+                "description": {
+                    "type": "string"
+                },
+                "supported": {
+                    "type": "boolean"
+                },
+                "patched": {
+                    "type": "boolean"
+                },
+                "checksum": {
+                    "type": "boolean"
+                },
+                "stripsign": {
+                    "type": "boolean"
+                }
+            }
+        },
+        "Pattern": {
+            "type": "object",
+            "required": [
+                "code"
+            ],
+            "properties": {
+                "code": {
+                    "$ref": "#/definitions/Code"
+                },
+                "groups": {
+                    "type": "array",
+                    "items": {
+                        "$ref": "#/definitions/Group"
+                    }
+                },
+                "group": {
+                    "$ref": "#/definitions/Group"
+                },
+                "name": {
+                    "type": "string"
+                },
+                "description": {
+                    "type": "string"
+                },
+                "disabled": {
+                    "type": "boolean"
+                },
+                "overlay": {
+                    "type": "boolean"
+                },
+                "supported": {
+                    "type": "boolean"
+                },
+                "addresses": {
+                    "type": "array"
+                },
+                "patched": {
+                    "type": "boolean"
+                },
+                "searched": {
+                    "type": "boolean"
+                },
+                "count": {
+                    "$ref": "#/definitions/Index"
+                }
+            }
+        },
+        "Group": {
+            "type": "object",
+            "required": [
+                "version"
+            ],
+            "properties": {
+                "version": {
+                    "type": "string"
+                },
+                "pattern": {
+                    "type": "string"
+                },
+                "replace": {
+                    "type": "string"
+                },
+                "replace2": {
+                    "type": "string"
+                },
+                "name": {
+                    "type": "string"
+                },
+                "description": {
+                    "type": "string"
+                },
+                "disabled": {
+                    "type": "boolean"
+                },
+                "overlay": {
+                    "type": "boolean"
+                },
+                "count": {
+                    "$ref": "#/definitions/Index"
+                },
+                "section": {
+                    "type": "string"
+                },
+                "range": {
+                    "$ref": "#/definitions/SearchRange"
+                },
+                "nth": {
+                    "type": "integer"
+                },
+                "offset": {
+                    "type": "integer"
+                },
+                "near": {
+                    "$ref": "#/definitions/NearPattern"
+                },
+                "resolve": {
+                    "$ref": "#/definitions/Resolver"
+                },
+                "xref": {
+                    "$ref": "#/definitions/StringXref"
+                },
+                "len": {
+                    "$ref": "#/definitions/Index"
+                }
+            }
+        },
+        "SearchRange": {
+            "type": "object",
+            "required": [
+                "start",
+                "end"
+            ],
+            "properties": {
+                "base": {
+                    "type": "string",
+                    "enum": [
+                        "foa",
+                        "rva"
+                    ]
+                },
+                "start": {
+                    "type": [
+                        "integer",
+                        "string"
+                    ]
+                },
+                "end": {
+                    "type": [
+                        "integer",
+                        "string"
+                    ]
+                }
+            }
+        },
+        "NearPattern": {
+            "type": "object",
+            "required": [
+                "pattern",
+                "distance"
+            ],
+            "properties": {
+                "pattern": {
+                    "type": "string"
+                },
+                "distance": {
+                    "$ref": "#/definitions/Index"
+                },
+                "direction": {
+                    "type": "string",
+                    "enum": [
+                        "before",
+                        "after",
+                        "both"
+                    ]
+                }
+            }
+        },
+        "Resolver": {
+            "type": "object",
+            "required": [
+                "offset"
+            ],
+            "properties": {
+                "offset": {
+                    "$ref": "#/definitions/Index"
+                },
+                "size": {
+                    "type": "integer",
+                    "enum": [
+                        0,
+                        1,
+                        4
+                    ]
+                },
+                "next": {
+                    "$ref": "#/definitions/Index"
+                },
+                "variable": {
+                    "type": "string"
+                }
+            }
+        },
+        "StringXref": {
+            "type": "object",
+            "required": [
+                "text"
+            ],
+            "properties": {
+                "text": {
+                    "type": "string"
+                },
+                "encoding": {
+                    "type": "string",
+                    "enum": [
+                        "utf8",
+                        "utf16"
+                    ]
+                },
+                "section": {
+                    "type": "string"
+                }
+            }
+        },
+        "Feature": {
+            "type": "object",
+            "required": [
+                "code",
+                "index"
+            ],
+            "properties": {
+                "code": {
+                    "$ref": "#/definitions/Code"
+                },
+                "index": {
+                    "$ref": "#/definitions/Index"
+                },
+                "name": {
+                    "type": "string"
+                },
+                "method": {
+                    "type": "string"
+                },
+                "icon": {
+                    "type": "string"
+                },
+                "description": {
+                    "type": "string"
+                },
+                "detaildesc": {
+                    "type": "string"
+                },
+                "inhead": {
+                    "type": "boolean"
+                },
+                "inmain": {
+                    "type": "boolean"
+                },
+                "incoexist": {
+                    "type": "boolean"
+                },
+                "bntype": {
+                    "type": "string",
+                    "enum": [
+                        "switch",
+                        "button",
+                        "checkbox"
+                    ]
+                },
+                "severity": {
+                    "type": "string"
+                },
+                "tips": {
+                    "type": "string"
+                },
+                "disabled": {
+                    "type": "boolean"
+                },
+                "overlay": {
+                    "type": "boolean"
+                },
+                "supported": {
+                    "type": "boolean"
+                },
+                "target": {
+                    "type": "string"
+                },
+                "selected": {
+                    "type": "boolean"
+                },
+                "status": {
+                    "type": "boolean"
+                },
+                "tdelay": {
+                    "$ref": "#/definitions/Index"
+                },
+                "dependpatches": {
+                    "$ref": "#/definitions/Codes"
+                },
+                "dependfeatures": {
+                    "$ref": "#/definitions/Codes"
+                },
+                "mutexfeatures": {
+                    "$ref": "#/definitions/Codes"
+                },
+                "syncclosefeatures": {
+                    "$ref": "#/definitions/Codes"
+                }
+            }
+        },
+        "Codes": {
+            "type": "array",
+            "items": {
+                "type": "string"
+            }
+        }
+    }
+}"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use crate::features::Feature;
+    use crate::groups::Group;
+    use crate::groups::NearPattern;
+    use crate::groups::Resolver;
+    use crate::patches::Patch;
+    use crate::paths::Method;
+    use crate::paths::PathFix;
+    use crate::paths::PathItem;
+    use crate::patterns::Pattern;
+    use crate::rules::Rule;
+    use serde::Deserializer;
+    use serde::de::DeserializeOwned;
+    use serde::de::Error;
+    use serde::de::Visitor;
+    use serde::de::value::Error as ValueError;
+    use serde_json::Map;
+    use serde_json::json;
+    use std::collections::BTreeSet;
+    use utils::patch::types::SearchRange;
+    use utils::patch::xref::StringXref;
+
+    /// 只读取 serde 派生的结构体字段名，不含 `#[serde(skip)]` 的字段
+    struct FieldsDeserializer<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for FieldsDeserializer<'_> {
+        type Error = ValueError;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, ValueError> {
+            Err(ValueError::custom("只读取结构体字段"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, ValueError> {
+            *self.0 = fields;
+            Err(ValueError::custom("只读取结构体字段"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+            identifier ignored_any
+        }
+    }
+
+    fn serde_fields<T: DeserializeOwned>() -> BTreeSet<&'static str> {
+        let mut fields: &'static [&'static str] = &[];
+        let _ = T::deserialize(FieldsDeserializer(&mut fields));
+        fields.iter().copied().collect()
+    }
+
+    /// 按 Schema 遍历得到的对象及其定义名，和没有声明的字段
+    #[derive(Default)]
+    struct Walked<'a> {
+        objects: Vec<(String, String, &'a Map<String, Value>)>,
+        undeclared: Vec<String>,
+    }
+
+    fn walk<'a>(
+        schema: &Value,
+        definition: &str,
+        value: &'a Value,
+        pointer: &str,
+        walked: &mut Walked<'a>,
+    ) {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.rsplit('/').next().unwrap();
+            let schema = &config_schema()["definitions"][name];
+            return walk(schema, name, value, pointer, walked);
+        }
+        match value {
+            Value::Object(map) => {
+                walked
+                    .objects
+                    .push((definition.to_string(), pointer.to_string(), map));
+                let properties = schema.get("properties").and_then(Value::as_object);
+                let additional = schema.get("additionalProperties");
+                for (key, item) in map {
+                    let child = child_pointer(pointer, key);
+                    match properties.and_then(|p| p.get(key)).or(additional) {
+                        Some(schema) => walk(schema, "", item, &child, walked),
+                        None => walked.undeclared.push(child),
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(schema) = schema.get("items") {
+                    for (index, item) in items.iter().enumerate() {
+                        let child = child_pointer(pointer, &index.to_string());
+                        walk(schema, "", item, &child, walked);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 填写了全部字段的配置，包括搜索和补丁后写入的运行时字段
+    fn full_config() -> Value {
+        let fix = json!({
+            "unprefix": "\"",
+            "unsuffix": "\"",
+            "prefix": "/",
+            "suffix": "/",
+            "pattern": "\\\\",
+            "replace": "/"
+        });
+        let group = json!({
+            "version": "4.0.0",
+            "pattern": "74 1C",
+            "replace": "EB ...",
+            "replace2": "EB ??",
+            "name": "分组",
+            "description": "说明",
+            "disabled": true,
+            "overlay": true,
+            "count": 1,
+            "section": ".text",
+            "range": { "base": "rva", "start": "0x1000", "end": 8192 },
+            "nth": -1,
+            "offset": 2,
+            "near": { "pattern": "85 C0", "distance": 16, "direction": "before" },
+            "resolve": { "offset": 1, "size": 4, "next": 5, "variable": "target" },
+            "xref": { "text": "fixture", "encoding": "utf16", "section": ".rdata" },
+            "len": 2
+        });
+        let feature = json!({
+            "code": "revoke",
+            "index": 1,
+            "name": "防撤回",
+            "method": "patch",
+            "icon": "icon",
+            "description": "说明",
+            "detaildesc": "详细说明",
+            "inhead": true,
+            "inmain": true,
+            "incoexist": true,
+            "bntype": "switch",
+            "severity": "info",
+            "tips": "提示",
+            "disabled": true,
+            "overlay": true,
+            "supported": true,
+            "target": "${exe_base}",
+            "selected": true,
+            "status": true,
+            "tdelay": 1,
+            "dependpatches": ["revoke"],
+            "dependfeatures": ["multi"],
+            "mutexfeatures": ["multi"],
+            "syncclosefeatures": ["multi"]
+        });
+        let path = json!({
+            "code": "install_location",
+            "index": 0,
+            "name": "安装位置",
+            "description": "说明",
+            "value": "${value}",
+            "path": "${path}",
+            "file": "${file}",
+            "fix": fix,
+            "methods": [{
+                "method": "calculate",
+                "index": 0,
+                "args": { "value": "." },
+                "retry": 1,
+                "fix": fix
+            }]
+        });
+        let patch = json!({
+            "code": "app",
+            "savefile": "${exe_base}",
+            "backfile": "${exe_base}.bak",
+            "basefile": "${exe_base}",
+            "name": "主程序",
+            "description": "说明",
+            "supported": true,
+            "patched": true,
+            "checksum": true,
+            "stripsign": true,
+            "patterns": [{
+                "code": "revoke",
+                "groups": [group],
+                "group": group,
+                "name": "防撤回",
+                "description": "说明",
+                "disabled": true,
+                "overlay": true,
+                "supported": true,
+                "addresses": [],
+                "patched": true,
+                "searched": true,
+                "count": 1
+            }]
+        });
+        json!({
+            "version": "1.0.0",
+            "ruletype": 1,
+            "name": "完整配置",
+            "description": "说明",
+            "disabled": true,
+            "supported": true,
+            "files": [],
+            "rules": [{
+                "code": "app",
+                "index": 0,
+                "version": "1.0.0",
+                "rtype": 1,
+                "ismain": true,
+                "name": "测试程序",
+                "news": "公告",
+                "description": "说明",
+                "disabled": true,
+                "overlay": true,
+                "supported": true,
+                "patched": true,
+                "installed": true,
+                "paths": [path],
+                "variables": { "exe_base": "app.exe" },
+                "patches": [patch],
+                "features": [feature],
+                "dfeatures": ["select"],
+                "hfeatures": [feature]
+            }]
+        })
+    }
+
+    #[test]
+    fn test_config_schema() {
+        let schema = config_schema();
+        assert_eq!(schema["$ref"], "#/definitions/Config");
+        let definitions = [
+            ("Config", serde_fields::<Config>()),
+            ("Rule", serde_fields::<Rule>()),
+            ("Path", serde_fields::<PathItem>()),
+            ("Method", serde_fields::<Method>()),
+            ("PathFix", serde_fields::<PathFix>()),
+            ("Patch", serde_fields::<Patch>()),
+            ("Pattern", serde_fields::<Pattern>()),
+            ("Group", serde_fields::<Group>()),
+            ("SearchRange", serde_fields::<SearchRange>()),
+            ("NearPattern", serde_fields::<NearPattern>()),
+            ("Resolver", serde_fields::<Resolver>()),
+            ("StringXref", serde_fields::<StringXref>()),
+            ("Feature", serde_fields::<Feature>()),
+        ];
+
+        // 完整配置和反序列化再序列化的结果都符合 Schema，且没有未声明的字段
+        let value = full_config();
+        let config: Config = serde_json::from_value(value.clone()).unwrap();
+        let serialized = serde_json::to_value(&config).unwrap();
+        for value in [&value, &serialized] {
+            let mut issues = Vec::new();
+            SchemaValidator::new(schema).validate(value, &mut issues);
+            assert_eq!(issues, Vec::new());
+            let mut walked = Walked::default();
+            walk(schema, "", value, "", &mut walked);
+            assert_eq!(walked.undeclared, Vec::<String>::new());
+        }
+
+        // Schema 声明的字段与 serde 字段一致，完整配置填写了其中每个字段
+        let mut walked = Walked::default();
+        walk(schema, "", &value, "", &mut walked);
+        for (name, fields) in &definitions {
+            let properties = schema["definitions"][name]["properties"]
+                .as_object()
+                .unwrap();
+            let declared = properties.keys().map(String::as_str).collect();
+            assert_eq!(fields, &declared, "{} 的字段与 Schema 不一致", name);
+            let filled = walked
+                .objects
+                .iter()
+                .filter(|(definition, ..)| definition == name)
+                .flat_map(|(.., map)| map.keys().map(String::as_str))
+                .collect();
+            assert_eq!(fields, &filled, "完整配置缺少 {} 的字段", name);
+        }
+
+        // 只有没有默认值的字段是必填的
+        for (name, pointer, map) in &walked.objects {
+            let Some(required) = definitions
+                .iter()
+                .any(|(definition, _)| definition == name)
+                .then(|| schema["definitions"][name]["required"].as_array())
+            else {
+                continue;
+            };
+            for key in map.keys() {
+                let mut value = value.clone();
+                value
+                    .pointer_mut(pointer)
+                    .and_then(Value::as_object_mut)
+                    .unwrap()
+                    .remove(key);
+                let missing = serde_json::from_value::<Config>(value).is_err();
+                let declared = required.is_some_and(|r| r.contains(&Value::from(key.as_str())));
+                assert_eq!(missing, declared, "{}/{} 的必填声明不正确", pointer, key);
+            }
+        }
+    }
+}
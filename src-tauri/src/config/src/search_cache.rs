@@ -0,0 +1,265 @@
+use crate::addresses::Addresses;
+use crate::errors::Result;
+use crate::patches::Patch;
+use crate::patterns::Pattern;
+use crate::serders::skippers::skip_if_empty;
+use log::debug;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::UNIX_EPOCH;
+use utils::errors::UtilsError;
+use utils::file::file_sha256;
+use utils::patch::patch::UPatch;
+use utils::store::Store;
+use utils::store::StoreData;
+use utils::store::StoreError;
+
+/// 搜索结果缓存，按规则 code 存储，版本为规则配置的版本
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchCache {
+    #[serde(default)]
+    pub files: Vec<CachedFile>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachedFile {
+    pub patch: String,
+    /// backfile 的 SHA-256
+    pub sha256: String,
+    /// backfile 的大小和修改时间，都未变化时不再计算 SHA-256
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub modified: u64,
+    #[serde(default)]
+    pub patterns: Vec<CachedPattern>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachedPattern {
+    pub code: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub supported: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub addresses: Addresses,
+}
+
+impl SearchCache {
+    fn store_name(code: &str) -> String {
+        format!("search-{}", code)
+    }
+
+    /// 读取缓存，不存在、版本不一致或解析失败时返回 None
+    pub fn load(code: &str, version: &str) -> Option<Self> {
+        let data = Store::new(&Self::store_name(code))
+            .and_then(|store| store.get_by_version(version))
+            .inspect_err(|e| debug!("读取 {} 搜索缓存失败：{}", code, e))
+            .ok()?;
+        serde_json::from_str(&data)
+            .inspect_err(|e| debug!("解析 {} 搜索缓存失败：{}", code, e))
+            .ok()
+    }
+
+    pub fn save(&self, code: &str, version: &str) -> Result<()> {
+        let data = serde_json::to_string(self)
+            .map_err(|e| UtilsError::from(StoreError::from(e)))?;
+        let store = Store::new(&Self::store_name(code))?;
+        store.save(StoreData::new(version, &data, false))?;
+        Ok(())
+    }
+
+    pub fn find(&self, patch: &str) -> Option<&CachedFile> {
+        self.files.iter().find(|file| file.patch == patch)
+    }
+}
+
+impl CachedFile {
+    pub fn from_patch(patch: &Patch) -> Result<Self> {
+        let patterns = patch
+            .patterns
+            .0
+            .iter()
+            .filter(|pattern| !pattern.disabled)
+            .map(|pattern| CachedPattern {
+                code: pattern.code.clone(),
+                supported: pattern.supported,
+                addresses: pattern.addresses.clone(),
+            })
+            .collect();
+        let (size, modified) = file_stamp(patch.get_backfile())?;
+        Ok(Self {
+            patch: patch.code.clone(),
+            sha256: file_sha256(patch.get_backfile())?,
+            size,
+            modified,
+            patterns,
+        })
+    }
+
+    /// sha256 只在大小相同而修改时间变化时计算
+    fn is_unchanged(&self, file: &str, sha256: impl FnOnce() -> String) -> bool {
+        match file_stamp(file) {
+            Ok(stamp) if stamp == (self.size, self.modified) => true,
+            Ok((size, _)) if size != self.size => false,
+            Ok(_) => sha256() == self.sha256,
+            Err(_) => false,
+        }
+    }
+
+    pub fn find(&self, pattern: &str) -> Option<&CachedPattern> {
+        self.patterns.iter().find(|cached| cached.code == pattern)
+    }
+
+    /// 文件未变化，且每个待搜索的特征码都有缓存并且原始数据仍在原位置
+    pub fn is_valid(&self, patch: &Patch, upatch: &UPatch) -> bool {
+        let backfile = patch.get_backfile();
+        if !self.is_unchanged(backfile, || file_sha256(backfile).unwrap_or_default()) {
+            debug!("{} 文件已变化，搜索缓存失效", patch.code);
+            return false;
+        }
+        patch
+            .patterns
+            .0
+            .iter()
+            .filter(|pattern| pattern.get_search_group().is_some())
+            .all(|pattern| match self.find(&pattern.code) {
+                Some(cached) => cached.is_valid(pattern, upatch),
+                None => false,
+            })
+    }
+}
+
+/// 文件大小和修改时间（纳秒），修改时间为 0 的旧缓存总是重新计算 SHA-256
+fn file_stamp(file: &str) -> Result<(u64, u64)> {
+    let metadata = std::fs::metadata(file)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default();
+    Ok((metadata.len(), modified))
+}
+
+impl CachedPattern {
+    pub fn is_valid(&self, pattern: &Pattern, upatch: &UPatch) -> bool {
+        let valid = self.addresses.0.iter().all(|address| {
+            upatch
+                .read_hex(address.start, address.len)
+                .is_ok_and(|data| data == address.orignal)
+        });
+        if !valid {
+            debug!("{} 缓存的原始数据不一致", pattern.get_name());
+        }
+        valid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConfigVecWrapperTrait;
+    use crate::cache::Cache;
+    use crate::fixture::REVOKE_OFFSET;
+    use crate::fixture::RULE_CODE;
+    use crate::fixture::RuleFixture;
+
+    #[test]
+    fn test_is_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.exe.bak");
+        std::fs::write(&file, b"MZ").unwrap();
+        let file = file.to_str().unwrap();
+        let (size, modified) = file_stamp(file).unwrap();
+        let mut cached = CachedFile {
+            sha256: "AA".to_string(),
+            size,
+            modified,
+            ..Default::default()
+        };
+
+        // 大小和修改时间都未变化时不计算 SHA-256
+        assert!(cached.is_unchanged(file, || unreachable!()));
+
+        // 修改时间变化但内容未变，按 SHA-256 判断
+        cached.modified = 0;
+        assert!(cached.is_unchanged(file, || "AA".to_string()));
+        assert!(!cached.is_unchanged(file, || "BB".to_string()));
+
+        // 大小变化时直接失效
+        cached.size += 1;
+        assert!(!cached.is_unchanged(file, || unreachable!()));
+        assert!(!cached.is_unchanged("missing.bak", || unreachable!()));
+    }
+
+    #[test]
+    fn test_search_cache() {
+        let fixture = RuleFixture::new("search-cache");
+        let revoke_foa = fixture.builder.foa(".text", REVOKE_OFFSET).unwrap() as usize;
+        let mut config = fixture.config();
+        let rule = config.rules.get_mut(RULE_CODE).unwrap();
+        rule.get_path().unwrap();
+        let mut searched = rule.build_by_num(10).unwrap();
+        searched.patches.back_files().unwrap();
+        searched
+            .patches
+            .search(&mut Cache::new(), "缓存测试")
+            .unwrap();
+        searched
+            .patches
+            .to_search_cache()
+            .unwrap()
+            .save(RULE_CODE, "1.0.0")
+            .unwrap();
+
+        assert!(fixture.dir.join("data/search-fixture.data").exists());
+
+        // 规则版本变化时缓存失效
+        assert!(SearchCache::load(RULE_CODE, "1.0.1").is_none());
+        let mut search_cache = SearchCache::load(RULE_CODE, "1.0.0").unwrap();
+        let mut restored = rule.build_by_num(10).unwrap();
+        assert!(
+            restored
+                .patches
+                .restore_search(&mut Cache::new(), &search_cache)
+                .unwrap()
+        );
+        let revoke = restored.patches.get_pattern("revoke").unwrap();
+        assert!(revoke.supported);
+        assert_eq!(revoke.addresses.0[0].start, revoke_foa);
+
+        // 修改时间变化但内容未变，按 SHA-256 判断仍然有效
+        search_cache.files[0].modified = 0;
+        let mut restored = rule.build_by_num(10).unwrap();
+        assert!(
+            restored
+                .patches
+                .restore_search(&mut Cache::new(), &search_cache)
+                .unwrap()
+        );
+
+        // backfile 内容变化时缓存失效，且不修改搜索结果
+        let backfile = searched.patches.0[0].get_backfile().to_owned();
+        let mut data = std::fs::read(&backfile).unwrap();
+        let rdata = fixture.builder.foa(".rdata", 0).unwrap() as usize;
+        data[rdata] = b'F';
+        std::fs::write(&backfile, data).unwrap();
+        let mut restored = rule.build_by_num(10).unwrap();
+        assert!(
+            !restored
+                .patches
+                .restore_search(&mut Cache::new(), &search_cache)
+                .unwrap()
+        );
+        assert!(
+            restored
+                .patches
+                .get_pattern("revoke")
+                .unwrap()
+                .addresses
+                .0
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use crate::cache::Cache;
+use crate::errors::ConfigError;
+use crate::errors::Result;
+use log::debug;
+use log::error;
+use log::info;
+use std::path::Path;
+use std::result::Result as RResult;
+use utils::file::remove_file;
+use utils::patch::checksum::PeChecksum;
+
+const BACKUP_SUFFIX: &str = "bwxbak";
+
+/// 提交前对目标文件的快照
+#[derive(Debug)]
+struct Snapshot {
+    target: String,
+    // 目标文件原本不存在时为 None，回滚时直接删除
+    backup: Option<String>,
+}
+
+impl Snapshot {
+    fn create(target: &str) -> Result<Self> {
+        if !Path::new(target).exists() {
+            return Ok(Self {
+                target: target.to_string(),
+                backup: None,
+            });
+        }
+        let backup = format!("{}.{}", target, BACKUP_SUFFIX);
+        debug!("备份文件：{} -> {}", target, backup);
+        std::fs::copy(target, backup.as_str())?;
+        Ok(Self {
+            target: target.to_string(),
+            backup: Some(backup),
+        })
+    }
+
+    fn restore(&self) -> Result<()> {
+        match &self.backup {
+            Some(backup) => {
+                debug!("恢复文件：{} -> {}", backup, self.target);
+                std::fs::copy(backup, self.target.as_str())?;
+                remove_file(backup)?;
+            }
+            None => remove_file(self.target.as_str())?,
+        }
+        Ok(())
+    }
+
+    fn discard(&self) {
+        if let Some(backup) = &self.backup
+            && let Err(e) = remove_file(backup)
+        {
+            error!("删除备份文件失败：{}，{}", backup, e);
+        }
+    }
+}
+
+/// 多文件补丁事务，所有写入先暂存在内存中，提交时要么全部写入成功，要么全部恢复原文件
+#[derive(Debug)]
+pub struct Transaction {
+    cache: Cache,
+    // 提交时重算过校验和的文件
+    checksums: Vec<(String, PeChecksum)>,
+}
+
+impl Default for Transaction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self {
+            cache: Cache::new_staged(),
+            checksums: Vec::new(),
+        }
+    }
+
+    pub fn get_cache(&mut self) -> &mut Cache {
+        &mut self.cache
+    }
+
+    pub fn get_checksums(&self) -> &[(String, PeChecksum)] {
+        &self.checksums
+    }
+
+    pub fn commit(&mut self) -> Result<()> {
+        let targets: Vec<String> = self
+            .cache
+            .iter()
+            .filter(|(_, patch)| patch.is_with_write())
+            .map(|(_, patch)| patch.get_save().to_string())
+            .collect();
+        if targets.is_empty() {
+            return Ok(());
+        }
+
+        // 备份所有目标文件，任意一个失败都不做写入
+        let mut snapshots = Vec::with_capacity(targets.len());
+        for target in &targets {
+            match Snapshot::create(target) {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(e) => {
+                    error!("备份文件失败：{}，{}", target, e);
+                    snapshots.iter().for_each(Snapshot::discard);
+                    return Err(ConfigError::TransactionCommitError(e.to_string()));
+                }
+            }
+        }
+
+        let saved = self.cache.iter_mut().try_for_each(|(_, patch)| {
+            patch.save().inspect_err(|e| {
+                error!("写入文件失败：{}，{}", patch.get_save(), e);
+            })
+        });
+        if let Err(e) = saved {
+            // 恢复失败时同时保留写入失败的原因
+            return Err(match self.rollback(&snapshots) {
+                Ok(_) => ConfigError::TransactionCommitError(e.to_string()),
+                Err(failed) => ConfigError::TransactionRollbackError(failed, e.to_string()),
+            });
+        }
+        self.checksums = self
+            .cache
+            .iter()
+            .filter_map(|(_, patch)| {
+                let checksum = patch.get_checksum()?;
+                Some((patch.get_save().to_string(), checksum))
+            })
+            .collect();
+
+        snapshots.iter().for_each(Snapshot::discard);
+        self.cache.clear();
+        info!("事务提交完成，共写入 {} 个文件", targets.len());
+        Ok(())
+    }
+
+    /// 恢复所有快照，返回恢复失败的文件
+    fn rollback(&mut self, snapshots: &[Snapshot]) -> RResult<(), String> {
+        // 释放内存映射，避免恢复时文件被占用
+        self.cache.clear();
+        let mut failed = Vec::new();
+        for snapshot in snapshots {
+            if let Err(e) = snapshot.restore() {
+                error!("恢复文件失败：{}，{}", snapshot.target, e);
+                failed.push(snapshot.target.as_str());
+            }
+        }
+        if !failed.is_empty() {
+            return Err(failed.join("，"));
+        }
+        info!("事务已回滚，共恢复 {} 个文件", snapshots.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::RuleFixture;
+    use std::fs;
+    use utils::patch::types::PatchType;
+
+    fn stage(transaction: &mut Transaction, input: &str, save: &str) {
+        transaction
+            .get_cache()
+            .get_or_insert(save, input, save, true)
+            .unwrap()
+            .write(0, PatchType::Data(vec![0x90; 2]))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("app.exe").to_string_lossy().to_string();
+        fs::write(&exe, b"MZ").unwrap();
+        let backup = format!("{}.{}", exe, BACKUP_SUFFIX);
+
+        // 快照恢复原文件，原本不存在的文件直接删除
+        let snapshot = Snapshot::create(&exe).unwrap();
+        fs::write(&exe, b"broken").unwrap();
+        snapshot.restore().unwrap();
+        assert_eq!(fs::read(&exe).unwrap(), b"MZ");
+        assert!(!Path::new(&backup).exists());
+        let save = dir.path().join("app1.exe").to_string_lossy().to_string();
+        let snapshot = Snapshot::create(&save).unwrap();
+        fs::write(&save, b"new").unwrap();
+        snapshot.restore().unwrap();
+        assert!(!Path::new(&save).exists());
+    }
+
+    #[test]
+    fn test_transaction() {
+        let fixture = RuleFixture::new("transaction");
+        let exe = fixture.exe().to_string_lossy().to_string();
+        let orignal = fs::read(&exe).unwrap();
+        let backup = format!("{}.{}", exe, BACKUP_SUFFIX);
+        let save = fixture.dir.join("app1.exe").to_string_lossy().to_string();
+
+        // 任意一个文件写入失败，全部恢复
+        let missing = fixture.dir.join("missing").join("app2.exe");
+        let missing = missing.to_string_lossy().to_string();
+        let mut transaction = Transaction::new();
+        stage(&mut transaction, &exe, &exe);
+        stage(&mut transaction, &exe, &save);
+        stage(&mut transaction, &exe, &missing);
+        let result = transaction.commit();
+        assert!(matches!(
+            result,
+            Err(ConfigError::TransactionCommitError(_))
+        ));
+        assert_eq!(fs::read(&exe).unwrap(), orignal);
+        assert!(!Path::new(&save).exists());
+        assert!(!Path::new(&backup).exists());
+        assert!(transaction.get_cache().is_empty());
+
+        let mut transaction = Transaction::new();
+        stage(&mut transaction, &exe, &exe);
+        stage(&mut transaction, &exe, &save);
+        transaction.commit().unwrap();
+        assert_eq!(fs::read(&exe).unwrap()[..2], [0x90, 0x90]);
+        assert_eq!(fs::read(&save).unwrap(), fs::read(&exe).unwrap());
+        assert!(!Path::new(&backup).exists());
+    }
+}
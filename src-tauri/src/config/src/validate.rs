@@ -0,0 +1,245 @@
+use crate::schema::SchemaValidator;
+use crate::schema::child_pointer;
+use crate::schema::config_schema;
+use crate::variables::Variables;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Display;
+use thiserror::Error;
+use utils::patch::types::BytePattern;
+
+const ELLIPSIS: &str = "...";
+
+/// 配置中的一个问题，pointer 为 JSON Pointer，如 `/rules/0/patches/1/patterns/3/groups/2/replace`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub pointer: String,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    pub fn new<S: Into<String>>(pointer: &str, message: S) -> Self {
+        Self {
+            pointer: pointer.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.pointer, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default, Error)]
+pub struct ConfigIssues(pub Vec<ConfigIssue>);
+
+impl ConfigIssues {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn from_error<E: Display>(pointer: &str, e: E) -> Self {
+        Self(vec![ConfigIssue::new(pointer, e.to_string())])
+    }
+}
+
+impl Display for ConfigIssues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for issue in &self.0 {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// 校验规则配置，返回全部问题
+///
+/// 先按 Schema 校验字段类型，再检查 Schema 无法表达的内容：code 重复、特征码格式、补丁长度
+pub fn validate_config(value: &Value) -> ConfigIssues {
+    let mut issues = Vec::new();
+    SchemaValidator::new(config_schema()).validate(value, &mut issues);
+    for (pointer, rule) in children(value, "", "rules") {
+        check_rule(rule, &pointer, &mut issues);
+    }
+    ConfigIssues(issues)
+}
+
+fn check_rule(rule: &Value, pointer: &str, issues: &mut Vec<ConfigIssue>) {
+    check_unique(children(rule, pointer, "paths"), issues);
+    check_unique(children(rule, pointer, "features"), issues);
+    check_unique(children(rule, pointer, "patches"), issues);
+    // 特征码按 code 在所有补丁文件中查找，整个规则内唯一
+    let patterns = children(rule, pointer, "patches")
+        .into_iter()
+        .flat_map(|(pointer, patch)| children(patch, &pointer, "patterns"))
+        .collect::<Vec<(String, &Value)>>();
+    check_unique(patterns.clone(), issues);
+    for (pointer, pattern) in patterns {
+        check_pattern(pattern, &pointer, issues);
+    }
+}
+
+fn check_pattern(pattern: &Value, pointer: &str, issues: &mut Vec<ConfigIssue>) {
+    let has = |key: &str| pattern.get(key).is_some_and(|v| !v.is_null());
+    let disabled = pattern
+        .get("disabled")
+        .and_then(Value::as_bool)
+        .unwrap_or_default();
+    if !disabled && !has("groups") && !has("group") && !has("addresses") {
+        issues.push(ConfigIssue::new(
+            pointer,
+            "groups、group、addresses 至少需要一个",
+        ));
+    }
+    let mut groups = children(pattern, pointer, "groups");
+    if let Some(group) = pattern.get("group")
+        && group.is_object()
+    {
+        groups.push((child_pointer(pointer, "group"), group));
+    }
+    for (pointer, group) in groups {
+        check_group(group, &pointer, issues);
+    }
+}
+
+fn check_group(group: &Value, pointer: &str, issues: &mut Vec<ConfigIssue>) {
+    let text = |key: &str| group.get(key).and_then(Value::as_str).unwrap_or_default();
+    let has_xref = group.get("xref").is_some_and(|v| !v.is_null());
+    let pattern_len = match BytePattern::parse(text("pattern")) {
+        Ok(pattern) if pattern.is_empty() && has_xref => {
+            group.get("len").and_then(Value::as_u64).unwrap_or_default() as usize
+        }
+        Ok(pattern) if pattern.is_empty() => {
+            let pointer = child_pointer(pointer, "pattern");
+            issues.push(ConfigIssue::new(&pointer, "pattern 和 xref 至少需要一个"));
+            return;
+        }
+        Ok(pattern) => pattern.len(),
+        Err(e) => {
+            issues.push(ConfigIssue::new(
+                &child_pointer(pointer, "pattern"),
+                e.to_string(),
+            ));
+            return;
+        }
+    };
+    if let Some(near) = group.get("near")
+        && let Some(text) = near.get("pattern").and_then(Value::as_str)
+        && let Err(e) = BytePattern::parse(text)
+    {
+        let pointer = child_pointer(&child_pointer(pointer, "near"), "pattern");
+        issues.push(ConfigIssue::new(&pointer, e.to_string()));
+    }
+    let replace_pointer = child_pointer(pointer, "replace");
+    match replace_len(text("replace")) {
+        Ok(Some((len, false))) if len != pattern_len => issues.push(ConfigIssue::new(
+            &replace_pointer,
+            format!("长度不一致，特征码 {} 字节，补丁 {} 字节", pattern_len, len),
+        )),
+        Ok(Some((len, true))) if len > pattern_len => issues.push(ConfigIssue::new(
+            &replace_pointer,
+            format!(
+                "长度不一致，特征码 {} 字节，补丁省略号外 {} 字节",
+                pattern_len, len
+            ),
+        )),
+        Err(message) => issues.push(ConfigIssue::new(&replace_pointer, message)),
+        _ => {}
+    }
+}
+
+/// 补丁码的字节数以及是否包含省略号，包含 `${...}` 变量时无法静态计算，返回 None
+fn replace_len(replace: &str) -> Result<Option<(usize, bool)>, String> {
+    let replace = BytePattern::compact(replace);
+    if replace.is_empty() || replace == ELLIPSIS || replace.contains("${") {
+        return Ok(None);
+    }
+    // $[code|adj|len] 按 len 个通配字节计算
+    let replace = Variables::default()
+        .substitute_add(replace, true, "")
+        .map_err(|e| e.to_string())?;
+    let mut len = 0;
+    for part in replace.splitn(2, ELLIPSIS) {
+        len += BytePattern::parse(part).map_err(|e| e.to_string())?.len();
+    }
+    Ok(Some((len, replace.contains(ELLIPSIS))))
+}
+
+/// 同一数组内 code 不能重复
+fn check_unique(items: Vec<(String, &Value)>, issues: &mut Vec<ConfigIssue>) {
+    let mut seen: HashMap<&str, String> = HashMap::new();
+    for (pointer, item) in items {
+        let Some(code) = item.get("code").and_then(Value::as_str) else {
+            continue;
+        };
+        match seen.get(code) {
+            Some(first) => issues.push(ConfigIssue::new(
+                &child_pointer(&pointer, "code"),
+                format!("code {} 与 {} 重复", code, first),
+            )),
+            None => {
+                seen.insert(code, pointer);
+            }
+        }
+    }
+}
+
+/// 数组字段的成员以及对应的 JSON Pointer
+fn children<'a>(value: &'a Value, pointer: &str, key: &str) -> Vec<(String, &'a Value)> {
+    let pointer = child_pointer(pointer, key);
+    match value.get(key).and_then(Value::as_array) {
+        Some(items) => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (child_pointer(&pointer, &index.to_string()), item))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::config_value;
+
+    #[test]
+    fn test_validate_config() {
+        let mut value = config_value(".");
+        assert!(validate_config(&value).is_empty());
+
+        let patterns = &mut value["rules"][0]["patches"][0]["patterns"];
+        patterns[0]["groups"][1]["replace"] = "EB 1C 90".into();
+        patterns[1]["groups"][0]["pattern"] = "85 C0 7".into();
+        patterns[1]["code"] = "revoke".into();
+        value["rules"][0]["features"][0]["index"] = "1".into();
+        value["rules"][0]["paths"][0]["methods"][0]["method"] = "shell".into();
+        value["rules"][0]["patches"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("savefile");
+
+        let mut pointers = validate_config(&value)
+            .0
+            .into_iter()
+            .map(|issue| issue.pointer)
+            .collect::<Vec<String>>();
+        pointers.sort();
+        assert_eq!(
+            pointers,
+            vec![
+                "/rules/0/features/0/index",
+                "/rules/0/patches/0/patterns/0/groups/1/replace",
+                "/rules/0/patches/0/patterns/1/code",
+                "/rules/0/patches/0/patterns/1/groups/0/pattern",
+                "/rules/0/patches/0/savefile",
+                "/rules/0/paths/0/methods/0/method",
+            ]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::errors::ConfigError;
 use crate::errors::Result;
+use crate::expr::Expression;
 use crate::patches::Patches;
 use macros::ImpConfigVecIsEmptyTrait;
 use macros::ImpConfigVecWrapperTrait;
@@ -9,16 +10,22 @@ use serde::Serialize;
 use serde::Serializer;
 use serde::ser::SerializeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::result::Result as RResult;
-use utils::patch::jump_offset::calculate_jump_offset_bytes;
+use utils::patch::branch::Branch;
+use utils::patch::branch::encode_displacement;
+use utils::patch::capture::CaptureValue;
+use utils::patch::types::Bytes;
 
 pub const LOCATION_CODE: &str = "install_location";
 pub const VERSIUON_CODE: &str = "install_version";
 pub const NUM_CODE: &str = "num";
 pub const NUM_HEX_CODE: &str = "num_hex";
 pub const ISMAIN_CODE: &str = "ismain";
+pub const VA_SUFFIX: &str = "_va";
+pub const HEX_SUFFIX: &str = "_hex";
 
 //const BACK_SUFFIX: &str = "_back}";
 const SAVE_SUFFIX: &str = "_save}";
@@ -27,23 +34,151 @@ const BASE_SUFFIX: &str = "_base}";
 #[derive(Clone, Default, ImpConfigVecIsEmptyTrait, ImpConfigVecWrapperTrait)]
 pub struct Variables(pub Vec<Variable>);
 
+/// 变量的替换顺序，被依赖的变量排在前面
+///
+/// 获取路径时生成，构建共存文件时只需按顺序重新替换 deferred 中的变量
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    order: Vec<String>,
+    // 直接或间接引用了延后变量（共存序号、搜索后的地址）的变量，按替换顺序排列
+    deferred: Vec<String>,
+}
+
+impl Resolution {
+    pub fn order(&self) -> &[String] {
+        &self.order
+    }
+
+    pub fn deferred(&self) -> &[String] {
+        &self.deferred
+    }
+}
+
 impl Variables {
-    pub fn init(&mut self) -> Result<()> {
-        let mut values = Vec::new();
-        for variable in self.0.iter() {
-            let v = if let VariableValue::String(s) = variable.get_value() {
-                VariableValue::String(self.substitute(s.to_string()))
-            } else {
-                variable.get_value().clone()
+    /// 按依赖关系排序后依次替换，任意层级的嵌套一次完成
+    ///
+    /// deferred 为此时还未定义的变量，引用它们的变量保留原文本，之后通过 resolve_deferred 替换；
+    /// 引用其他未定义变量或者存在循环引用时报错
+    pub fn resolve(&mut self, deferred: &HashSet<String>) -> Result<Resolution> {
+        let resolution = self.resolution(deferred)?;
+        self.resolve_codes(&resolution.order)?;
+        Ok(resolution)
+    }
+
+    /// 延后变量定义后，只重新替换依赖它们的变量
+    pub fn resolve_deferred(&mut self, resolution: &Resolution) -> Result<()> {
+        self.resolve_codes(&resolution.deferred)
+    }
+
+    fn resolve_codes(&mut self, codes: &[String]) -> Result<()> {
+        for code in codes {
+            let Some(index) = self.0.iter().position(|v| &v.code == code) else {
+                continue;
+            };
+            let value = match &self.0[index].value {
+                VariableValue::String(s) => self.substitute_value(s)?,
+                _ => continue,
             };
-            values.push(v);
+            self.0[index].value = value;
+        }
+        Ok(())
+    }
+
+    fn resolution(&self, deferred: &HashSet<String>) -> Result<Resolution> {
+        let mut depends: HashMap<&str, Vec<String>> = HashMap::new();
+        for variable in &self.0 {
+            let references = self.references(variable, deferred)?;
+            if let Some(reference) = references
+                .iter()
+                .find(|r| self.find_variable(r).is_none() && !deferred.contains(*r))
+            {
+                return Err(ConfigError::UnresolvedVariable(
+                    variable.code.to_string(),
+                    reference.to_string(),
+                ));
+            }
+            depends.entry(&variable.code).or_insert(references);
+        }
+
+        let mut order = Vec::new();
+        let mut stack = Vec::new();
+        for variable in &self.0 {
+            Self::sort_variable(&variable.code, &depends, &mut stack, &mut order)?;
+        }
+
+        let mut deferred_codes: Vec<String> = Vec::new();
+        for code in &order {
+            if depends[code.as_str()]
+                .iter()
+                .any(|r| deferred.contains(r) || deferred_codes.contains(r))
+            {
+                deferred_codes.push(code.to_string());
+            }
+        }
+        Ok(Resolution {
+            order,
+            deferred: deferred_codes,
+        })
+    }
+
+    /// 深度优先，依赖先于自身加入 order，stack 为当前路径，用于报告循环引用
+    fn sort_variable<'a>(
+        code: &'a str,
+        depends: &'a HashMap<&str, Vec<String>>,
+        stack: &mut Vec<&'a str>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if order.iter().any(|c| c == code) {
+            return Ok(());
         }
-        for (variable, new_value) in self.0.iter_mut().zip(values) {
-            variable.set_value(new_value);
+        if let Some(start) = stack.iter().position(|c| *c == code) {
+            let mut path = stack[start..].to_vec();
+            path.push(code);
+            return Err(ConfigError::VariableCycle(
+                code.to_string(),
+                path.join(" -> "),
+            ));
         }
+        // 延后变量不在 depends 中
+        let Some(references) = depends.get(code) else {
+            return Ok(());
+        };
+        stack.push(code);
+        for reference in references {
+            Self::sort_variable(reference, depends, stack, order)?;
+        }
+        stack.pop();
+        order.push(code.to_string());
         Ok(())
     }
 
+    /// 变量值中 `${...}` 引用的变量，表达式取其中的标识符
+    fn references(&self, variable: &Variable, deferred: &HashSet<String>) -> Result<Vec<String>> {
+        let VariableValue::String(text) = &variable.value else {
+            return Ok(Vec::new());
+        };
+        let mut references: Vec<String> = Vec::new();
+        for js_variable in Variables::create_js_varibales(text).0 {
+            let code = js_variable.code;
+            // 与 evaluate 一致，同名变量优先
+            let codes = if self.find_variable(&code).is_some() || deferred.contains(&code) {
+                vec![code]
+            } else {
+                Expression::parse(&code)?
+                    .identifiers()
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            };
+            for code in codes {
+                if !references.contains(&code) {
+                    references.push(code);
+                }
+            }
+        }
+        Ok(references)
+    }
+
     fn create_varibales_by_regex<S: AsRef<str>>(text: S, regex: &str, replace: &str) -> Self {
         let re = regex::Regex::new(regex).unwrap();
         let js_variables = re
@@ -155,16 +290,37 @@ impl Variables {
         save_file.to_string()
     }
 
-    pub fn substitute<S: Into<String>>(&self, text: S) -> String {
+    /// 替换文本中的 `${...}`，引用了未定义变量的保留原文本
+    pub fn substitute<S: Into<String>>(&self, text: S) -> Result<String> {
         let mut result = text.into();
         let js_variables = Variables::create_js_varibales(&result);
         for js_variable in js_variables.0 {
-            if let Some(v) = self.find_variable(&js_variable.code) {
-                let value = v.value.to_string();
+            if let Some(value) = self.evaluate(&js_variable.code)? {
+                let value = value.to_string();
                 result = result.replace(js_variable.value.to_string().as_str(), value.as_str());
             }
         }
-        result
+        Ok(result)
+    }
+
+    /// 计算 `${...}` 的内容，与变量同名时直接取值，否则按表达式计算
+    pub fn evaluate(&self, code: &str) -> Result<Option<VariableValue>> {
+        if let Some(v) = self.find_variable(code) {
+            return Ok(Some(v.value.clone()));
+        }
+        // 语法错误直接返回，只有引用的变量尚未定义时才保留原文本
+        Expression::parse(code)?.eval(self)
+    }
+
+    /// 整个值只有一个 `${...}` 时保留计算结果的类型
+    fn substitute_value(&self, text: &str) -> Result<VariableValue> {
+        if let Some(code) = text.strip_prefix("${").and_then(|t| t.strip_suffix('}'))
+            && !code.contains('}')
+            && let Some(value) = self.evaluate(code)?
+        {
+            return Ok(value);
+        }
+        Ok(VariableValue::String(self.substitute(text)?))
     }
 
     pub fn substitute_add<S: Into<String>>(
@@ -181,31 +337,78 @@ impl Variables {
             let code_split = v_code.as_str().split('|').collect::<Vec<&str>>();
 
             if code_split.len() != 3 {
-                return Err(ConfigError::InvalidVariable(v_code.to_string()));
+                return Err(ConfigError::InvalidVariable(
+                    value.to_string(),
+                    "格式应为 $[code|adj|len]".to_string(),
+                ));
             }
 
             let code = code_split[0];
 
-            let len = code_split[2]
-                .parse::<usize>()
-                .map_err(|_| ConfigError::InvalidVariable(code.to_string()))?;
+            let len = code_split[2].parse::<usize>().map_err(|_| {
+                ConfigError::InvalidVariable(value.to_string(), "len 应为数字".to_string())
+            })?;
 
             if use_wildcards {
                 result = result.replace(value.as_str(), "??".repeat(len).as_str());
                 continue;
             }
 
+            // $[address|va|8] $[address|rva|4] 写入绝对地址
+            if code_split[1] == "va" || code_split[1] == "rva" {
+                let v_code = if code_split[1] == "va" {
+                    format!("{}{}", code, VA_SUFFIX)
+                } else {
+                    code.to_string()
+                };
+                let v = self
+                    .find_variable(&v_code)
+                    .ok_or(ConfigError::GetVariabledValueError(v_code.to_string()))?;
+                let patch_code = Self::address_to_le_hex(v.to_usize()? as u64, len)
+                    .ok_or(ConfigError::CalcAddressError(pattern_code.to_string()))?;
+                result = result.replace(value.as_str(), patch_code.as_str());
+                continue;
+            }
+
+            // $[address|jmp|5] $[address|jne,4|6] 生成完整的跳转指令，len 为 2 时使用短跳转
+            let (op, op_adjust) = match code_split[1].split_once(',') {
+                Some((op, adjust)) => (op, adjust),
+                None => (code_split[1], "0"),
+            };
+            if let Some(branch) = Branch::from_mnemonic(op) {
+                let target = self
+                    .find_variable(code)
+                    .ok_or(ConfigError::GetVariabledValueError(code.to_string()))?;
+                let current =
+                    self.find_variable(pattern_code)
+                        .ok_or(ConfigError::GetVariabledValueError(
+                            pattern_code.to_string(),
+                        ))?;
+                let index = Self::get_hex_index_by_str(result.as_str(), js_variable.to_str()?)?;
+                let patch_code = branch
+                    .encode_in(
+                        (current.to_usize()? as i64 + index) as u64,
+                        (target.to_usize()? as i64 + op_adjust.parse::<i64>()?) as u64,
+                        len,
+                    )?
+                    .to_hex();
+                result = result.replace(value.as_str(), patch_code.as_str());
+                continue;
+            }
+
             if let Some(v1) = self.find_variable(code)
                 && let Some(v2) = self.find_variable(pattern_code)
             {
                 let target_addr = v1.to_usize()? as u64;
                 let current_addr = v2.to_usize()? as u64;
 
+                // len 不小于 4 时写入 rel32，多余部分用 90 填充，为 1 时写入 rel8
+                let size = if len >= 4 { 4 } else { len };
                 let temp_arg1 = code_split[1].to_string();
                 let (current_adjust, target_adjust) = if temp_arg1 == "?" {
-                    // $[address|?|4] 自动计算 目标地址无偏移
+                    // $[address|?|4] 自动计算 目标地址无偏移，位移相对位移字段的结束位置
                     let index = Self::get_hex_index_by_str(result.as_str(), js_variable.to_str()?)?;
-                    (index + len as i64, 0)
+                    (index + size as i64, 0)
                 } else if temp_arg1.contains(",") {
                     // $[address|?,4|4]
                     let offsets = temp_arg1.split(",").collect::<Vec<&str>>();
@@ -213,7 +416,7 @@ impl Variables {
                         // $[address|?,4|4] 自动计算 + 目标地址偏移
                         let index: i64 =
                             Self::get_hex_index_by_str(result.as_str(), js_variable.to_str()?)?;
-                        (index + len as i64, offsets[1].parse::<i64>()?)
+                        (index + size as i64, offsets[1].parse::<i64>()?)
                     } else {
                         // $[address|12,4|4] 当地地址偏移 + 目标地址偏移
                         (offsets[0].parse::<i64>()?, offsets[1].parse::<i64>()?)
@@ -223,21 +426,13 @@ impl Variables {
                     (code_split[1].parse::<i64>()?, 0)
                 };
 
-                let mut patch_code = calculate_jump_offset_bytes(
+                let mut patch_code = encode_displacement(
                     (current_addr as i64 + current_adjust) as u64,
                     (target_addr as i64 + target_adjust) as u64,
-                    0,
+                    size,
                 )?
                 .to_hex();
-            
-                let parch_code_len = patch_code.len() / 2;
-                if parch_code_len > len {
-                    return Err(ConfigError::CalcAddressError(pattern_code.to_string()));
-                }
-                if parch_code_len < len {
-                    let diff_len = len - parch_code_len;
-                    patch_code = format!("{}{}", patch_code, "90".repeat(diff_len));
-                }
+                patch_code.push_str(&"90".repeat(len - size));
                 result = result.replace(value.as_str(), patch_code.as_str());
             } else {
                 return Err(ConfigError::GetVariabledValueError(code.to_string()).into());
@@ -246,6 +441,14 @@ impl Variables {
         Ok(result)
     }
 
+    /// 地址按小端序写入 len 个字节，超出范围时返回 None
+    fn address_to_le_hex(address: u64, len: usize) -> Option<String> {
+        if len == 0 || len > 8 || (len < 8 && address >> (len * 8) != 0) {
+            return None;
+        }
+        Some(Bytes::new(&address.to_le_bytes()[..len]).to_hex())
+    }
+
     fn get_hex_index_by_str(str1: &str, str2: &str) -> Result<i64> {
         let index = str1
             .find(str2)
@@ -268,7 +471,28 @@ impl TryFrom<&Patches> for Variables {
                     }
                     let v = VariableValue::Usize(add);
                     let variable = Variable::new(pattern.code.clone(), v);
-                    variables.push(variable)
+                    variables.push(variable);
+                    // VA 地址，用于绝对地址引用
+                    if address.start_va > 0 {
+                        let code = format!("{}{}", pattern.code, VA_SUFFIX);
+                        variables.push(Variable::new(code, address.start_va));
+                    }
+                    if let Some(target) = &address.target {
+                        let v = VariableValue::Usize(target.start_rva);
+                        variables.push(Variable::new(target.code.clone(), v));
+                        let code = format!("{}{}", target.code, VA_SUFFIX);
+                        variables.push(Variable::new(code, target.start_va));
+                    }
+                    // 命名捕获：数值以及原始字节
+                    for capture in &address.captures {
+                        let v = match capture.value {
+                            CaptureValue::Unsigned(n) => VariableValue::Usize(n as usize),
+                            CaptureValue::Signed(n) => VariableValue::Number(n),
+                        };
+                        variables.push(Variable::new(capture.code.clone(), v));
+                        let code = format!("{}{}", capture.code, HEX_SUFFIX);
+                        variables.push(Variable::new(code, capture.hex.clone()));
+                    }
                 }
             }
         }
@@ -511,3 +735,101 @@ impl<'de> Deserialize<'de> for Variables {
         Ok(Variables(variables))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(items: &[(&str, &str)]) -> Variables {
+        Variables(items.iter().map(|(c, v)| Variable::new(*c, *v)).collect())
+    }
+
+    #[test]
+    fn test_substitute_expression() {
+        let vars = variables(&[("b", "1")]);
+        assert!(matches!(
+            vars.substitute("${a b}"),
+            Err(ConfigError::InvalidVariable(..))
+        ));
+        // 语法正确但引用未定义的变量时保留原文本
+        assert_eq!(vars.substitute("${b + later}").unwrap(), "${b + later}");
+    }
+
+    #[test]
+    fn test_resolve_variables() {
+        // 声明顺序与依赖顺序相反，需要三层替换
+        let mut vars = variables(&[
+            ("exe_save", "${exe_dir}/app${num}.exe"),
+            ("exe_dir", "${app_dir}/bin"),
+            ("app_dir", "${install_location}/app"),
+            ("exe_name", "${upper(exe_save)}"),
+            (LOCATION_CODE, "C:"),
+            ("count", "${1 + 2}"),
+        ]);
+        let deferred = HashSet::from([NUM_CODE.to_string()]);
+        let resolution = vars.resolve(&deferred).unwrap();
+        assert_eq!(resolution.deferred(), ["exe_save", "exe_name"]);
+        let value = |vars: &Variables, code: &str| vars.find_variable(code).unwrap().to_string();
+        assert_eq!(value(&vars, "exe_dir"), "exe_dir=C:/app/bin");
+        assert_eq!(
+            value(&vars, "exe_save"),
+            "exe_save=C:/app/bin/app${num}.exe"
+        );
+        assert_eq!(vars.find_variable("count").unwrap().as_usize(), Some(3));
+
+        vars.set_value(NUM_CODE, 2usize);
+        vars.resolve_deferred(&resolution).unwrap();
+        assert_eq!(value(&vars, "exe_save"), "exe_save=C:/app/bin/app2.exe");
+        assert_eq!(value(&vars, "exe_name"), "exe_name=C:/APP/BIN/APP2.EXE");
+
+        let mut vars = variables(&[("a", "${b}"), ("b", "${if(c, 1, 2)}"), ("c", "${a}")]);
+        let Err(ConfigError::VariableCycle(_, path)) = vars.resolve(&deferred) else {
+            panic!("应检测到循环引用");
+        };
+        assert_eq!(path, "a -> b -> c -> a");
+
+        let mut vars = variables(&[("a", "${b}/${missing}"), ("b", "1")]);
+        let Err(ConfigError::UnresolvedVariable(code, reference)) = vars.resolve(&deferred) else {
+            panic!("应检测到未定义变量");
+        };
+        assert_eq!((code.as_str(), reference.as_str()), ("a", "missing"));
+
+        // 无法解析的 `${...}` 返回解析错误
+        let mut vars = variables(&[("odd", "${le(1,}")]);
+        assert!(matches!(
+            vars.resolve(&deferred),
+            Err(ConfigError::InvalidVariable(..))
+        ));
+    }
+
+    #[test]
+    fn test_substitute_add() {
+        let mut vars = Variables::default();
+        vars.push(Variable::new("patch", 0x1000usize));
+        vars.push(Variable::new("near", 0x1010usize));
+        vars.push(Variable::new("far", 0x2000usize));
+        let substitute = |text: &str| vars.substitute_add(text, false, "patch");
+
+        // 位移形式：解码 opcode 后的位移，目标为位移字段结束位置加位移
+        let target = |text: &str, size: usize| {
+            let bytes = Bytes::try_from_hex(substitute(text).unwrap()).unwrap();
+            let field = &bytes.as_bytes()[1..1 + size];
+            let disp = match field {
+                [b] => *b as i8 as i64,
+                _ => i32::from_le_bytes(field.try_into().unwrap()) as i64,
+            };
+            0x1000 + 1 + size as i64 + disp
+        };
+        assert_eq!(target("E9$[near|?|4]", 4), 0x1010);
+        assert_eq!(target("E9$[far|?|4]", 4), 0x2000);
+        assert_eq!(target("EB$[near|?|1]", 1), 0x1010);
+        assert_eq!(substitute("E8$[near|?|5]").unwrap(), "E80B00000090");
+        assert!(substitute("EB$[far|?|1]").is_err());
+        assert!(substitute("EB$[near|?|2]").is_err());
+
+        // 指令形式：按槽位长度编码完整指令
+        assert_eq!(substitute("$[near|jmp|2]").unwrap(), "EB0E");
+        assert_eq!(substitute("$[near|jmp|6]").unwrap(), "E90B00000090");
+        assert!(substitute("$[near|call|4]").is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use crate::rules::Rule;
 use crate::serders::skippers::skip_if_empty;
+use crate::views::signature_view::SignatureView;
+use crate::views::signature_view::SignatureViews;
 use serde::Serialize;
 
 #[derive(Debug, Clone, Serialize)]
@@ -13,12 +15,16 @@ pub struct AddressView {
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     pub rtype: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub signatures: Vec<SignatureView>,
 }
 
 impl From<&Rule> for AddressView {
     fn from(rule: &Rule) -> Self {
         Self {
             rtype: rule.rtype.clone() as usize,
+            signatures: SignatureViews::from(&rule.patches).0,
             supported: rule.supported,
             patched: rule.patched,
         }
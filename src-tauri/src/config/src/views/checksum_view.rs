@@ -0,0 +1,34 @@
+use crate::patches::Patches;
+use serde::Serialize;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ChecksumView {
+    pub code: String,
+    pub name: String,
+    pub file: String,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ChecksumViews(pub Vec<ChecksumView>);
+
+impl From<&Patches> for ChecksumViews {
+    fn from(patches: &Patches) -> Self {
+        let views = patches
+            .0
+            .iter()
+            .filter_map(|patch| {
+                let checksum = patch.pe_checksum?;
+                Some(ChecksumView {
+                    code: patch.code.clone(),
+                    name: patch.get_name().to_string(),
+                    file: patch.get_savefile().to_string(),
+                    old: format!("{:#010X}", checksum.old),
+                    new: format!("{:#010X}", checksum.new),
+                })
+            })
+            .collect();
+        Self(views)
+    }
+}
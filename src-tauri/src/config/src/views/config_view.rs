@@ -46,6 +46,9 @@ pub struct InitView {
     pub disabled: bool,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
+    pub overlay: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
     pub supported: bool,
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
@@ -65,6 +68,7 @@ impl TryFrom<&Rule> for InitView {
             news: rule.news.clone(),
             description: rule.description.clone(),
             disabled: rule.disabled,
+            overlay: rule.overlay,
             supported: rule.supported,
         };
         Ok(view)
@@ -0,0 +1,17 @@
+use serde::Serialize;
+use utils::patch::disasm::DisasmLine;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DisasmViews(pub Vec<DisasmView>);
+
+/// 单个基址修改前后的反汇编
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DisasmView {
+    pub pcode: String,
+    pub pname: String,
+    pub start: usize,
+    pub start_rva: usize,
+    pub len: usize,
+    pub orignal: Vec<DisasmLine>,
+    pub replace: Vec<DisasmLine>,
+}
@@ -4,6 +4,8 @@ use crate::features::Features;
 use crate::rules::Rule;
 use crate::rules::Rules;
 use crate::serders::skippers::skip_if_empty;
+use crate::views::checksum_view::ChecksumView;
+use crate::views::checksum_view::ChecksumViews;
 use serde::Serialize;
 
 #[derive(Debug, Default, Clone, Serialize)]
@@ -37,6 +39,9 @@ pub struct FileView {
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     rtype: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    checksums: Vec<ChecksumView>,
 }
 
 impl TryFrom<&Rule> for FileView {
@@ -49,6 +54,7 @@ impl TryFrom<&Rule> for FileView {
             name: rule.name.clone(),
             ismain: rule.ismain,
             index: rule.index,
+            checksums: ChecksumViews::from(&rule.patches).0,
         };
         Ok(view)
     }
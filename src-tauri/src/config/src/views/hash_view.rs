@@ -0,0 +1,34 @@
+use crate::patches::Patches;
+use serde::Serialize;
+use utils::file::file_sha256;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct HashView {
+    pub code: String,
+    pub name: String,
+    pub file: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct HashViews(pub Vec<HashView>);
+
+impl From<&Patches> for HashViews {
+    fn from(patches: &Patches) -> Self {
+        let views = patches
+            .0
+            .iter()
+            .filter_map(|patch| {
+                let file = patch.get_basefile();
+                let sha256 = file_sha256(file).ok()?;
+                Some(HashView {
+                    code: patch.code.clone(),
+                    name: patch.get_name().to_string(),
+                    file: file.to_string(),
+                    sha256,
+                })
+            })
+            .collect();
+        Self(views)
+    }
+}
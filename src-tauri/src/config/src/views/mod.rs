@@ -1,6 +1,11 @@
 pub mod address_view;
+pub mod checksum_view;
 pub mod config_view;
+pub mod disasm_view;
 pub mod features_view;
 pub mod files_view;
+pub mod hash_view;
 pub mod orignal_view;
+pub mod patch_view;
 pub mod path_view;
+pub mod signature_view;
@@ -0,0 +1,23 @@
+use crate::rules::Rule;
+use crate::serders::skippers::skip_if_empty;
+use crate::views::checksum_view::ChecksumView;
+use crate::views::checksum_view::ChecksumViews;
+use crate::views::features_view::FeaturesView;
+use serde::Serialize;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PatchView {
+    pub features: FeaturesView,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub checksums: Vec<ChecksumView>,
+}
+
+impl From<&Rule> for PatchView {
+    fn from(rule: &Rule) -> Self {
+        Self {
+            features: FeaturesView::from(&rule.features),
+            checksums: ChecksumViews::from(&rule.patches).0,
+        }
+    }
+}
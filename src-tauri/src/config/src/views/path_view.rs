@@ -1,6 +1,10 @@
 use crate::features::Features;
 use crate::rules::Rule;
 use crate::serders::skippers::skip_if_empty;
+use crate::views::hash_view::HashView;
+use crate::views::hash_view::HashViews;
+use crate::views::signature_view::SignatureView;
+use crate::views::signature_view::SignatureViews;
 use serde::Serialize;
 
 #[derive(Debug, Clone, Serialize)]
@@ -23,6 +27,12 @@ pub struct PathView {
     #[serde(default)]
     #[serde(skip_serializing_if = "skip_if_empty")]
     pub rtype: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub signatures: Vec<SignatureView>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub hashes: Vec<HashView>,
 }
 
 impl From<&Rule> for PathView {
@@ -39,6 +49,8 @@ impl From<&Rule> for PathView {
             .to_string();
         Self {
             rtype: rule.rtype.clone() as usize,
+            signatures: SignatureViews::from(&rule.patches).0,
+            hashes: HashViews::from(&rule.patches).0,
             hfeatures: rule.hfeatures.clone(),
             installed: rule.installed,
             news: rule.news.clone(),
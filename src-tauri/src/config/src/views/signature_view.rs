@@ -0,0 +1,49 @@
+use crate::patches::Patches;
+use crate::serders::skippers::skip_if_empty;
+use serde::Serialize;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SignatureView {
+    pub code: String,
+    pub name: String,
+    pub file: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub signed: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub signer: String,
+    // 补丁会使签名失效
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub broken: bool,
+    // 保存时清除签名
+    #[serde(default)]
+    #[serde(skip_serializing_if = "skip_if_empty")]
+    pub strip: bool,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SignatureViews(pub Vec<SignatureView>);
+
+impl From<&Patches> for SignatureViews {
+    fn from(patches: &Patches) -> Self {
+        let views = patches
+            .0
+            .iter()
+            .filter_map(|patch| {
+                let signature = patch.signature.as_ref()?;
+                Some(SignatureView {
+                    code: patch.code.clone(),
+                    name: patch.get_name().to_string(),
+                    file: patch.get_basefile().to_string(),
+                    signed: signature.signed,
+                    signer: signature.signer.clone(),
+                    broken: patch.breaks_signature(),
+                    strip: patch.stripsign,
+                })
+            })
+            .collect();
+        Self(views)
+    }
+}
@@ -62,7 +62,10 @@ pub fn run_with_ui() {
             apis::rule::rule_make_coexist,
             apis::rule::rule_del_coexist,
             apis::rule::rule_read_orignal,
+            apis::rule::rule_read_disasm,
             apis::rule::rule_patch_by_replace,
+            apis::rule::rule_export_diff,
+            apis::rule::rule_import_diff,
             apis::cmd::cmd_close_app,
             apis::cmd::cmd_run_app,
             apis::cmd::cmd_open_url,
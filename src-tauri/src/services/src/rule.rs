@@ -7,11 +7,13 @@ use config::ConfigVecWrapperTrait;
 use config::errors::ConfigError;
 use config::features::COEXISTS_CODE;
 use config::rules::Rule;
+use config::transaction::Transaction;
 use config::views::address_view::AddressView;
 use config::views::config_view::ConfigViews;
-use config::views::features_view::FeaturesView;
+use config::views::disasm_view::DisasmViews;
 use config::views::files_view::FileView;
 use config::views::files_view::FilesView;
+use config::views::patch_view::PatchView;
 use config::views::path_view::PathView;
 use log::debug;
 use std::sync::Arc;
@@ -95,15 +97,14 @@ pub async fn rule_walk_files(code: &str) -> Result<FilesView> {
     Ok(view)
 }
 
-pub async fn rule_patch(code: &str, num: usize, fcode: &str, status: bool) -> Result<FeaturesView> {
+pub async fn rule_patch(code: &str, num: usize, fcode: &str, status: bool) -> Result<PatchView> {
     debug!(
         "正在 {}:{} 执行 {} 补丁,status:{}",
         code, num, fcode, status
     );
     rule_file_fn(code, num, |rule: &mut Rule| {
         rule.patch(fcode, status, None)?;
-        let features_view = FeaturesView::from(&rule.features);
-        Ok(features_view)
+        Ok(PatchView::from(&*rule))
     })
     .await
 }
@@ -112,10 +113,13 @@ pub async fn rule_make_coexist(code: &str, num: usize) -> Result<FileView> {
     rule_config_fn(|config| {
         let rule = config.rules.get_mut(code)?;
         let mut new_rule = rule.build_by_num(num)?;
-        new_rule.patch(COEXISTS_CODE, true, None)?;
+        let mut transaction = Transaction::new();
+        new_rule.patch_with_transaction(COEXISTS_CODE, true, &mut transaction)?;
         new_rule.features.retain_features(num == 0);
         let files = config.files.get_mut(code)?;
         let file_view = FileView::try_from(&new_rule)?;
+        // 共存文件全部生成成功后才加入列表
+        new_rule.commit_transaction(COEXISTS_CODE, &mut transaction)?;
         files.rules.push(new_rule);
         Ok(file_view)
     })
@@ -144,9 +148,33 @@ pub async fn rule_read_orignal(code: &str, num: usize, fcode: &str) -> Result<Or
     .await
 }
 
+pub async fn rule_read_disasm(code: &str, num: usize, fcode: &str) -> Result<DisasmViews> {
+    rule_file_fn(code, num, |rule: &mut Rule| {
+        let r = rule.read_disasm(fcode)?;
+        Ok(r)
+    })
+    .await
+}
+
 pub async fn rule_patch_by_replace(code: &str, num: usize, fcode: &str, ovs: &OrignalViews) -> Result<()>{
     rule_file_fn(code, num, |rule: &mut Rule| {
         Ok(rule.patch_by_replace(fcode,ovs)?)
     })
     .await
-}
\ No newline at end of file
+}
+
+pub async fn rule_export_diff(code: &str, num: usize, fcode: &str, dir: &str) -> Result<String> {
+    rule_file_fn(code, num, |rule: &mut Rule| {
+        let r = rule.export_diff(fcode, dir)?;
+        Ok(r)
+    })
+    .await
+}
+
+pub async fn rule_import_diff(code: &str, num: usize, file: &str) -> Result<PatchView> {
+    rule_file_fn(code, num, |rule: &mut Rule| {
+        rule.import_diff(file)?;
+        Ok(PatchView::from(&*rule))
+    })
+    .await
+}
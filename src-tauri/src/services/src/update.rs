@@ -1,11 +1,15 @@
 use crate::errors::Result;
 use crate::rule::config_init;
 use config::Config;
+use config::format::find_config_file;
+use config::format::read_as_json;
+use config::overlay::read_overlay;
 use config::update::Update;
 use config::update::Updates;
 use config::update::VerData;
 use config::views::config_view::ConfigViews;
 use log::debug;
+use log::error;
 use log::info;
 use setting::DEBUG_BASE_PATH;
 use setting::DEBUG_CONFIG_NAME;
@@ -54,7 +58,7 @@ pub async fn config_check(config: &VerData) -> Result<ConfigViews> {
             }
         }
     };
-    let config: Config = serde_json::from_str(&data)?;
+    let config = load_config(&data)?;
     let config_views = config_init(config).await?;
     Ok(config_views)
 }
@@ -85,8 +89,31 @@ pub async fn readme_check(readme: &VerData) -> Result<String> {
     Ok(data)
 }
 
+/// 存在本地覆盖配置时合并后加载，覆盖配置无效时忽略并使用原配置
+fn load_config(data: &str) -> Result<Config> {
+    let overlay = match read_overlay() {
+        Ok(overlay) => overlay,
+        Err(e) => {
+            error!("读取本地覆盖配置失败：{}", e);
+            None
+        }
+    };
+    if let Some(overlay) = overlay {
+        match Config::from_json_with_overlay(data, &overlay) {
+            Ok(config) => {
+                info!("已合并本地覆盖配置");
+                return Ok(config);
+            }
+            Err(e) => error!("本地覆盖配置无效，已忽略：{}", e),
+        }
+    }
+    Ok(Config::from_json(data)?)
+}
+
+/// 调试时读取本地文件，同名的 JSON5、TOML、YAML 文件会转换为 JSON
 fn get_debug_data(name: &str) -> Result<String> {
-    let path = Path::new(DEBUG_BASE_PATH).join(name);
-    let data = fs::read_to_string(path)?;
+    let path = find_config_file(DEBUG_BASE_PATH, name)
+        .unwrap_or_else(|| Path::new(DEBUG_BASE_PATH).join(name));
+    let data = read_as_json(path)?;
     Ok(data)
 }
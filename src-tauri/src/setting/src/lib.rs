@@ -15,3 +15,5 @@ pub const DEBUG_README_NAME: &str = "Readme.md";
 pub const BASE_URL: &str = "https://gitee.com/afaa1991/BetterWX-UI/raw/master/.cargo";
 
 pub const UPDATE_URL: &str = "update.zip";
+
+pub const OVERLAY_NAME: &str = "overlay.json";
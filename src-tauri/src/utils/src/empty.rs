@@ -20,6 +20,12 @@ impl Empty for Option<&usize> {
     }
 }
 
+impl Empty for i64 {
+    fn is_empty(&self) -> bool {
+        *self == 0
+    }
+}
+
 impl Empty for usize {
     fn is_empty(&self) -> bool {
         *self == 0
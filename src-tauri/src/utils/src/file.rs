@@ -1,9 +1,28 @@
 use crate::errors::Result;
-use crate::version::Version;
+use crate::patch::types::Bytes;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs::File;
 use std::fs::copy;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::SystemTime;
 use thiserror::Error;
-use winsys::fileinfo::FileInfo;
+
+// 文件哈希缓存，路径、修改时间或大小变化时重新计算
+static SHA256_CACHE: LazyLock<Mutex<HashMap<PathBuf, FileHash>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone)]
+struct FileHash {
+    modified: SystemTime,
+    size: u64,
+    sha256: String,
+}
 
 #[derive(Debug, Error)]
 pub enum FileError {
@@ -36,13 +55,87 @@ pub fn remove_file(file: &str) -> Result<()> {
     Ok(())
 }
 
+/// 备份记录，保存在备份文件旁，不比较补丁后的文件和备份的内容
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BackupRecord {
+    /// 备份时原文件的 SHA-256，以及之后补丁写入原文件后的 SHA-256
+    #[serde(default)]
+    pub sha256: Vec<String>,
+    /// 由备份生成的文件
+    #[serde(default)]
+    pub copies: Vec<String>,
+}
+
+impl BackupRecord {
+    fn path(backfile: &str) -> String {
+        format!("{}.json", backfile)
+    }
+
+    /// 读取备份记录，不存在或解析失败时返回空记录
+    pub fn load(backfile: &str) -> Self {
+        std::fs::read_to_string(Self::path(backfile))
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, backfile: &str) -> Result<()> {
+        let data = serde_json::to_string(self).map_err(std::io::Error::from)?;
+        std::fs::write(Self::path(backfile), data)?;
+        Ok(())
+    }
+}
+
+/// 原文件的哈希不在备份记录中时（首次备份或原文件已更新）重新备份
 pub fn back_file(from: &str, to: &str) -> Result<()> {
+    if is_backup_of(from, to)? {
+        return Ok(());
+    }
+    copy(from, to)?;
+    let record = BackupRecord {
+        sha256: vec![file_sha256(from)?],
+        copies: Vec::new(),
+    };
+    record.save(to)
+}
+
+/// 备份存在，且原文件的哈希在备份记录中
+pub fn is_backup_of(from: &str, to: &str) -> Result<bool> {
     if !Path::new(to).exists() {
-        copy(from, to)?;
-    } else {
-        if !file_is_equal(from, to)? {
-            copy(from, to)?;
-        }
+        return Ok(false);
+    }
+    let sha256 = file_sha256(from)?;
+    Ok(BackupRecord::load(to).sha256.contains(&sha256))
+}
+
+/// 生成的文件存在，且在原文件未更新时由备份生成
+pub fn is_copy_of(from: &str, to: &str, file: &str) -> Result<bool> {
+    if !Path::new(file).exists() || !is_backup_of(from, to)? {
+        return Ok(false);
+    }
+    Ok(BackupRecord::load(to)
+        .copies
+        .iter()
+        .any(|copy| copy == file))
+}
+
+/// 补丁写入原文件后记录新的哈希，之后备份时不再把它当作更新
+pub fn record_patched(from: &str, to: &str) -> Result<()> {
+    let sha256 = file_sha256(from)?;
+    let mut record = BackupRecord::load(to);
+    if !record.sha256.contains(&sha256) {
+        record.sha256.push(sha256);
+        record.save(to)?;
+    }
+    Ok(())
+}
+
+/// 记录由备份生成的文件
+pub fn record_copy(to: &str, file: &str) -> Result<()> {
+    let mut record = BackupRecord::load(to);
+    if !record.copies.iter().any(|copy| copy == file) {
+        record.copies.push(file.to_string());
+        record.save(to)?;
     }
     Ok(())
 }
@@ -53,12 +146,93 @@ pub fn file_is_equal(from: &str, to: &str) -> Result<bool> {
     if !from_path.exists() || !to_path.exists() {
         return Ok(false);
     }
-    let from = &FileInfo::new(from);
-    let to = &FileInfo::new(to);
-    let from_ver = Version::new(from.get_version()?.as_str());
-    let to_ver = Version::new(to.get_version()?.as_str());
-    let from_size = from.get_size()?;
-    let to_size = to.get_size()?;
-    Ok(to_ver == from_ver && to_size == from_size)
+    if from_path.metadata()?.len() != to_path.metadata()?.len() {
+        return Ok(false);
+    }
+    Ok(file_sha256(from)? == file_sha256(to)?)
 }
 
+/// 文件内容的 SHA-256，按路径、修改时间和大小缓存
+pub fn file_sha256(file: &str) -> Result<String> {
+    check_file_exists(file)?;
+    let metadata = std::fs::metadata(file)?;
+    let modified = metadata.modified()?;
+    let size = metadata.len();
+    let path = PathBuf::from(file);
+    {
+        let cache = SHA256_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(hash) = cache.get(&path)
+            && hash.modified == modified
+            && hash.size == size
+        {
+            return Ok(hash.sha256.clone());
+        }
+    }
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(file)?, &mut hasher)?;
+    let sha256 = Bytes::new(hasher.finalize().to_vec()).to_hex();
+    let mut cache = SHA256_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache.insert(
+        path,
+        FileHash {
+            modified,
+            size,
+            sha256: sha256.clone(),
+        },
+    );
+    Ok(sha256)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_is_equal() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.bin");
+        let b = dir.path().join("b.bin");
+        std::fs::write(&a, b"abc").unwrap();
+        std::fs::write(&b, b"abd").unwrap();
+        let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+        assert_eq!(
+            file_sha256(a).unwrap(),
+            "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"
+        );
+        // 版本和大小相同的热更新也能识别
+        assert!(!file_is_equal(a, b).unwrap());
+        back_file(a, b).unwrap();
+        assert!(file_is_equal(a, b).unwrap());
+    }
+
+    #[test]
+    fn test_backup_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        let (exe, bak, copy) = (path("app.exe"), path("app.exe.bak"), path("app1.exe"));
+        std::fs::write(&exe, b"orignal").unwrap();
+        back_file(&exe, &bak).unwrap();
+        assert!(is_backup_of(&exe, &bak).unwrap());
+
+        // 补丁写入原文件后备份仍然有效，不会被已修补的文件覆盖
+        std::fs::write(&exe, b"patched").unwrap();
+        assert!(!is_backup_of(&exe, &bak).unwrap());
+        record_patched(&exe, &bak).unwrap();
+        assert!(is_backup_of(&exe, &bak).unwrap());
+        back_file(&exe, &bak).unwrap();
+        assert_eq!(std::fs::read(&bak).unwrap(), b"orignal");
+
+        std::fs::write(&copy, b"orignal").unwrap();
+        assert!(!is_copy_of(&exe, &bak, &copy).unwrap());
+        record_copy(&bak, &copy).unwrap();
+        assert!(is_copy_of(&exe, &bak, &copy).unwrap());
+
+        // 原文件更新后重新备份，由旧备份生成的文件失效
+        std::fs::write(&exe, b"updated!").unwrap();
+        assert!(!is_copy_of(&exe, &bak, &copy).unwrap());
+        back_file(&exe, &bak).unwrap();
+        assert_eq!(std::fs::read(&bak).unwrap(), b"updated!");
+        assert!(is_backup_of(&exe, &bak).unwrap());
+        assert!(!is_copy_of(&exe, &bak, &copy).unwrap());
+    }
+}
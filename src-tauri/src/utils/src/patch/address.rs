@@ -0,0 +1,311 @@
+use crate::errors::Result;
+use crate::patch::errors::UPatchError;
+use crate::patch::types::Section;
+use pelite::PeFile;
+use pelite::Wrap;
+
+/// 地址空间
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressSpace {
+    /// 文件偏移
+    Foa,
+    /// 相对虚拟地址
+    Rva,
+    /// 虚拟地址，ImageBase + RVA
+    Va,
+}
+
+/// PE 地址模型，负责 FOA、RVA、VA 之间的相互转换
+#[derive(Debug, Clone, Default)]
+pub struct AddressMap {
+    bitness: u32,
+    image_base: u64,
+    section_alignment: u64,
+    file_alignment: u64,
+    size_of_headers: u64,
+    size_of_image: u64,
+    file_size: u64,
+    sections: Vec<Section>,
+}
+
+impl AddressMap {
+    pub fn from_pe(data: &[u8]) -> Result<Self> {
+        let pe_file =
+            PeFile::from_bytes(data).map_err(|e| UPatchError::InvalidPe(e.to_string()))?;
+        let (
+            bitness,
+            image_base,
+            section_alignment,
+            file_alignment,
+            size_of_headers,
+            size_of_image,
+        ) = match pe_file.optional_header() {
+            Wrap::T32(h) => (
+                32,
+                h.ImageBase as u64,
+                h.SectionAlignment,
+                h.FileAlignment,
+                h.SizeOfHeaders,
+                h.SizeOfImage,
+            ),
+            Wrap::T64(h) => (
+                64,
+                h.ImageBase,
+                h.SectionAlignment,
+                h.FileAlignment,
+                h.SizeOfHeaders,
+                h.SizeOfImage,
+            ),
+        };
+        let sections = pe_file
+            .section_headers()
+            .iter()
+            .map(|section| {
+                let range = section.file_range();
+                Section {
+                    name: section.name().unwrap_or_default().to_string(),
+                    file_start: range.start as u64,
+                    file_end: range.end as u64,
+                    virtual_address: section.VirtualAddress as u64,
+                    virtual_size: section.VirtualSize as u64,
+                }
+            })
+            .collect();
+        Ok(Self {
+            bitness,
+            image_base,
+            section_alignment: section_alignment as u64,
+            file_alignment: file_alignment as u64,
+            size_of_headers: size_of_headers as u64,
+            size_of_image: size_of_image as u64,
+            file_size: data.len() as u64,
+            sections,
+        })
+    }
+
+    /// 32 或 64
+    pub fn get_bitness(&self) -> u32 {
+        self.bitness
+    }
+
+    pub fn get_image_base(&self) -> u64 {
+        self.image_base
+    }
+
+    pub fn get_section_alignment(&self) -> u64 {
+        self.section_alignment
+    }
+
+    pub fn get_file_alignment(&self) -> u64 {
+        self.file_alignment
+    }
+
+    pub fn get_size_of_image(&self) -> u64 {
+        self.size_of_image
+    }
+
+    pub fn get_sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn find_section_by_rva(&self, rva: u64) -> Option<&Section> {
+        self.sections.iter().find(|section| {
+            rva >= section.virtual_address
+                && rva < section.virtual_address + self.virtual_extent(section)
+        })
+    }
+
+    pub fn find_section_by_foa(&self, foa: u64) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|section| section.contains_foa(foa))
+    }
+
+    /// 节在内存中占用的大小，按 SectionAlignment 对齐
+    fn virtual_extent(&self, section: &Section) -> u64 {
+        let size = if section.virtual_size == 0 {
+            section.file_size()
+        } else {
+            section.virtual_size
+        };
+        align_up(size, self.section_alignment)
+    }
+
+    /// 节中实际映射到内存的文件数据大小
+    fn mapped_file_size(&self, section: &Section) -> u64 {
+        section.file_size().min(self.virtual_extent(section))
+    }
+
+    /// 附加数据（overlay）的起始位置
+    pub fn get_overlay_start(&self) -> u64 {
+        self.sections
+            .iter()
+            .map(|section| section.file_end)
+            .max()
+            .unwrap_or(self.size_of_headers)
+            .max(self.size_of_headers)
+    }
+
+    pub fn foa_to_rva(&self, foa: u64) -> Result<u64> {
+        if foa >= self.file_size {
+            return Err(UPatchError::FoaOutOfFile(foa).into());
+        }
+        // PE 头按原样映射
+        if foa < self.size_of_headers {
+            return Ok(foa);
+        }
+        if let Some(section) = self.find_section_by_foa(foa) {
+            let offset = foa - section.file_start;
+            if offset >= self.mapped_file_size(section) {
+                return Err(UPatchError::FoaNotMapped(foa).into());
+            }
+            return Ok(section.virtual_address + offset);
+        }
+        if foa >= self.get_overlay_start() {
+            return Err(UPatchError::FoaInOverlay(foa).into());
+        }
+        Err(UPatchError::FoaNotMapped(foa).into())
+    }
+
+    pub fn rva_to_foa(&self, rva: u64) -> Result<u64> {
+        if rva >= self.size_of_image {
+            return Err(UPatchError::RvaOutOfImage(rva).into());
+        }
+        if rva < self.size_of_headers {
+            return Ok(rva);
+        }
+        if let Some(section) = self.find_section_by_rva(rva) {
+            let offset = rva - section.virtual_address;
+            // 超出文件数据部分（如 .bss）由加载器填零，没有对应的文件偏移
+            if offset >= self.mapped_file_size(section) {
+                return Err(UPatchError::RvaNotInFile(rva).into());
+            }
+            return Ok(section.file_start + offset);
+        }
+        Err(UPatchError::RvaNotInFile(rva).into())
+    }
+
+    pub fn rva_to_va(&self, rva: u64) -> Result<u64> {
+        if rva >= self.size_of_image {
+            return Err(UPatchError::RvaOutOfImage(rva).into());
+        }
+        Ok(self.image_base + rva)
+    }
+
+    pub fn va_to_rva(&self, va: u64) -> Result<u64> {
+        if va < self.image_base || va - self.image_base >= self.size_of_image {
+            return Err(UPatchError::VaOutOfImage(va).into());
+        }
+        Ok(va - self.image_base)
+    }
+
+    pub fn foa_to_va(&self, foa: u64) -> Result<u64> {
+        self.rva_to_va(self.foa_to_rva(foa)?)
+    }
+
+    pub fn va_to_foa(&self, va: u64) -> Result<u64> {
+        self.rva_to_foa(self.va_to_rva(va)?)
+    }
+
+    pub fn convert(&self, value: u64, from: AddressSpace, to: AddressSpace) -> Result<u64> {
+        let rva = match from {
+            AddressSpace::Foa => self.foa_to_rva(value)?,
+            AddressSpace::Rva => value,
+            AddressSpace::Va => self.va_to_rva(value)?,
+        };
+        match to {
+            AddressSpace::Foa => self.rva_to_foa(rva),
+            AddressSpace::Rva => Ok(rva),
+            AddressSpace::Va => self.rva_to_va(rva),
+        }
+    }
+
+    /// RVA 窗口与各节的交集，转换为文件偏移区间
+    pub fn rva_window_to_foa(&self, start: u64, end: u64) -> Vec<(u64, u64)> {
+        self.sections
+            .iter()
+            .filter_map(|section| {
+                let v_start = section.virtual_address;
+                let v_end = v_start + self.mapped_file_size(section);
+                let s = start.max(v_start);
+                let e = end.min(v_end);
+                if s >= e {
+                    return None;
+                }
+                Some((
+                    section.file_start + (s - v_start),
+                    section.file_start + (e - v_start),
+                ))
+            })
+            .collect()
+    }
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    if align <= 1 {
+        return value;
+    }
+    value.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::UtilsError;
+
+    fn address_map() -> AddressMap {
+        AddressMap {
+            bitness: 64,
+            image_base: 0x1_8000_0000,
+            section_alignment: 0x1000,
+            file_alignment: 0x200,
+            size_of_headers: 0x400,
+            size_of_image: 0x4000,
+            file_size: 0xA00,
+            sections: vec![
+                Section {
+                    name: ".text".to_string(),
+                    file_start: 0x400,
+                    file_end: 0x800,
+                    virtual_address: 0x1000,
+                    virtual_size: 0x3F0,
+                },
+                Section {
+                    name: ".data".to_string(),
+                    file_start: 0x800,
+                    file_end: 0x900,
+                    virtual_address: 0x2000,
+                    virtual_size: 0x1800,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_address_convert() {
+        let result = AddressMap::from_pe(b"MZ truncated");
+        assert!(matches!(
+            result,
+            Err(UtilsError::UPatchError(UPatchError::InvalidPe(_)))
+        ));
+
+        let map = address_map();
+        assert_eq!(map.foa_to_rva(0x10).unwrap(), 0x10);
+        assert_eq!(map.foa_to_rva(0x410).unwrap(), 0x1010);
+        assert_eq!(map.rva_to_foa(0x1010).unwrap(), 0x410);
+        assert_eq!(map.foa_to_va(0x410).unwrap(), 0x1_8000_1010);
+        assert_eq!(map.va_to_foa(0x1_8000_2010).unwrap(), 0x810);
+        assert_eq!(
+            map.convert(0x1_8000_1010, AddressSpace::Va, AddressSpace::Foa)
+                .unwrap(),
+            0x410
+        );
+        // .data 未初始化部分
+        assert!(map.rva_to_foa(0x2200).is_err());
+        // 附加数据
+        assert!(map.foa_to_rva(0x950).is_err());
+        assert!(map.foa_to_rva(0xA00).is_err());
+        assert!(map.va_to_rva(0x1000).is_err());
+        assert!(map.rva_to_va(0x4000).is_err());
+    }
+}
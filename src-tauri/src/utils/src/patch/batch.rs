@@ -0,0 +1,143 @@
+use crate::errors::Result;
+use crate::patch::errors::UPatchError;
+use crate::patch::types::BytePattern;
+use crate::patch::types::SearchScope;
+use aho_corasick::AhoCorasick;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// 锚点太短时命中过多，交给单独搜索
+const MIN_ANCHOR_LEN: usize = 2;
+
+/// 一次扫描同时搜索多个特征码，结果按 key 返回
+#[derive(Debug, Clone)]
+pub struct BatchSearch<K> {
+    items: Vec<BatchItem<K>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchItem<K> {
+    pub key: K,
+    pub pattern: BytePattern,
+    pub scope: SearchScope,
+}
+
+impl<K> Default for BatchSearch<K> {
+    fn default() -> Self {
+        Self { items: Vec::new() }
+    }
+}
+
+impl<K: Eq + Hash + Clone> BatchSearch<K> {
+    pub fn add(&mut self, key: K, pattern: BytePattern, scope: SearchScope) {
+        if !pattern.is_empty() {
+            self.items.push(BatchItem {
+                key,
+                pattern,
+                scope,
+            });
+        }
+    }
+
+    pub fn items(&self) -> &[BatchItem<K>] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 以每个特征码最长的无通配符片段构建自动机，扫描一遍 data 后逐个校验完整特征码
+    ///
+    /// 锚点不足 MIN_ANCHOR_LEN 的特征码不在结果中，调用方应单独搜索
+    pub fn search(&self, data: &[u8]) -> Result<HashMap<K, Vec<usize>>> {
+        // 相同锚点共用一个自动机模式
+        let mut anchors: Vec<&[u8]> = Vec::new();
+        let mut anchor_items: Vec<Vec<(usize, usize)>> = Vec::new();
+        for (index, item) in self.items.iter().enumerate() {
+            let (start, len) = item.pattern.anchor();
+            if len < MIN_ANCHOR_LEN {
+                continue;
+            }
+            let anchor = &item.pattern.bytes()[start..start + len];
+            match anchors.iter().position(|a| *a == anchor) {
+                Some(id) => anchor_items[id].push((index, start)),
+                None => {
+                    anchors.push(anchor);
+                    anchor_items.push(vec![(index, start)]);
+                }
+            }
+        }
+
+        let mut results: HashMap<K, Vec<usize>> = HashMap::new();
+        for items in &anchor_items {
+            for (index, _) in items {
+                results.insert(self.items[*index].key.clone(), Vec::new());
+            }
+        }
+        if anchors.is_empty() {
+            return Ok(results);
+        }
+
+        let ac = AhoCorasick::new(&anchors).map_err(|_| UPatchError::PatternBuilderError)?;
+        for m in ac.find_overlapping_iter(data) {
+            for (index, anchor_start) in &anchor_items[m.pattern().as_usize()] {
+                let Some(start) = m.start().checked_sub(*anchor_start) else {
+                    continue;
+                };
+                let item = &self.items[*index];
+                if item.pattern.is_match(&data[start..])
+                    && let Some(poses) = results.get_mut(&item.key)
+                {
+                    poses.push(start);
+                }
+            }
+        }
+        for poses in results.values_mut() {
+            poses.sort();
+            poses.dedup();
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_search() {
+        let data = [0x00, 0x48, 0x8B, 0x05, 0x11, 0x22, 0x48, 0x8B, 0x0D, 0x90];
+        let mut batch = BatchSearch::default();
+        batch.add(
+            "a",
+            BytePattern::parse("48 8B ?5").unwrap(),
+            SearchScope::default(),
+        );
+        batch.add(
+            "b",
+            BytePattern::parse("8B ?? 11 22").unwrap(),
+            SearchScope::default(),
+        );
+        batch.add(
+            "c",
+            BytePattern::parse("48 8B").unwrap(),
+            SearchScope::default(),
+        );
+        batch.add(
+            "d",
+            BytePattern::parse("?? 90").unwrap(),
+            SearchScope::default(),
+        );
+        let results = batch.search(&data).unwrap();
+        assert_eq!(results["a"], vec![1]);
+        assert_eq!(results["b"], vec![2]);
+        assert_eq!(results["c"], vec![1, 6]);
+        // 锚点太短，不参与批量搜索
+        assert!(!results.contains_key("d"));
+    }
+}
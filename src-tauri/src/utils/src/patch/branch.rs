@@ -0,0 +1,216 @@
+use crate::errors::Result;
+use crate::patch::errors::UPatchError;
+use crate::patch::types::Bytes;
+
+const JMP_SHORT: u8 = 0xEB;
+const JMP_NEAR: u8 = 0xE9;
+const CALL_NEAR: u8 = 0xE8;
+const JCC_SHORT: u8 = 0x70;
+const JCC_NEAR: [u8; 2] = [0x0F, 0x80];
+const NOP: u8 = 0x90;
+
+/// Jcc 条件码，值为 opcode 低 4 位
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    O = 0x0,
+    No = 0x1,
+    B = 0x2,
+    Ae = 0x3,
+    E = 0x4,
+    Ne = 0x5,
+    Be = 0x6,
+    A = 0x7,
+    S = 0x8,
+    Ns = 0x9,
+    P = 0xA,
+    Np = 0xB,
+    L = 0xC,
+    Ge = 0xD,
+    Le = 0xE,
+    G = 0xF,
+}
+
+/// 跳转类指令
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Branch {
+    Jmp,
+    Call,
+    Jcc(Condition),
+}
+
+/// 指令形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BranchForm {
+    /// rel8
+    Short,
+    /// rel32
+    Near,
+    /// 能用 rel8 时使用 rel8
+    Auto,
+}
+
+impl Branch {
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        let condition = match mnemonic.to_lowercase().as_str() {
+            "jmp" => return Some(Self::Jmp),
+            "call" => return Some(Self::Call),
+            "jo" => Condition::O,
+            "jno" => Condition::No,
+            "jb" | "jc" | "jnae" => Condition::B,
+            "jae" | "jnb" | "jnc" => Condition::Ae,
+            "je" | "jz" => Condition::E,
+            "jne" | "jnz" => Condition::Ne,
+            "jbe" | "jna" => Condition::Be,
+            "ja" | "jnbe" => Condition::A,
+            "js" => Condition::S,
+            "jns" => Condition::Ns,
+            "jp" | "jpe" => Condition::P,
+            "jnp" | "jpo" => Condition::Np,
+            "jl" | "jnge" => Condition::L,
+            "jge" | "jnl" => Condition::Ge,
+            "jle" | "jng" => Condition::Le,
+            "jg" | "jnle" => Condition::G,
+            _ => return None,
+        };
+        Some(Self::Jcc(condition))
+    }
+
+    /// 指令长度，call 没有 rel8 形式
+    pub fn len(&self, form: BranchForm) -> Option<usize> {
+        match (self, form) {
+            (Self::Call, BranchForm::Short) => None,
+            (_, BranchForm::Short) => Some(2),
+            (Self::Jcc(_), _) => Some(6),
+            (_, _) => Some(5),
+        }
+    }
+
+    /// 编码完整指令，from 为指令所在地址，to 为跳转目标
+    pub fn encode(&self, from: u64, to: u64, form: BranchForm) -> Result<Bytes> {
+        if form == BranchForm::Auto {
+            return self
+                .encode(from, to, BranchForm::Short)
+                .or_else(|_| self.encode(from, to, BranchForm::Near));
+        }
+        let len = self
+            .len(form)
+            .ok_or(UPatchError::BranchFormNotSupported(format!("{:?}", self)))?;
+        let offset = relative_offset(from + len as u64, to);
+        let mut bytes = match (self, form) {
+            (Self::Jmp, BranchForm::Short) => vec![JMP_SHORT],
+            (Self::Jmp, _) => vec![JMP_NEAR],
+            (Self::Call, _) => vec![CALL_NEAR],
+            (Self::Jcc(c), BranchForm::Short) => vec![JCC_SHORT | *c as u8],
+            (Self::Jcc(c), _) => vec![JCC_NEAR[0], JCC_NEAR[1] | *c as u8],
+        };
+        match form {
+            BranchForm::Short => bytes.push(to_rel8(offset, to)? as u8),
+            _ => bytes.extend_from_slice(&to_rel32(offset, to)?.to_le_bytes()),
+        }
+        Ok(Bytes::new(bytes))
+    }
+
+    /// 按槽位长度选择形式，多余部分用 NOP 填充
+    pub fn encode_in(&self, from: u64, to: u64, slot: usize) -> Result<Bytes> {
+        let form = match self.len(BranchForm::Short) {
+            Some(len) if len == slot => BranchForm::Short,
+            _ => BranchForm::Near,
+        };
+        let len = self.len(form).unwrap_or_default();
+        if slot < len {
+            return Err(UPatchError::InvalidBranchLength(slot).into());
+        }
+        let mut bytes = self.encode(from, to, form)?.as_bytes().to_vec();
+        bytes.resize(slot, NOP);
+        Ok(Bytes::new(bytes))
+    }
+}
+
+/// 编码 size 字节的位移，next 为位移字段结束的地址，size 为 1 或 4
+pub fn encode_displacement(next: u64, to: u64, size: usize) -> Result<Bytes> {
+    let offset = relative_offset(next, to);
+    let bytes = match size {
+        1 => vec![to_rel8(offset, to)? as u8],
+        4 => to_rel32(offset, to)?.to_le_bytes().to_vec(),
+        _ => return Err(UPatchError::InvalidDisplacementLength(size).into()),
+    };
+    Ok(Bytes::new(bytes))
+}
+
+fn relative_offset(next: u64, to: u64) -> i64 {
+    (to as i128 - next as i128) as i64
+}
+
+fn to_rel8(offset: i64, to: u64) -> Result<i8> {
+    i8::try_from(offset).map_err(|_| UPatchError::BranchOutOfRange(to, offset).into())
+}
+
+fn to_rel32(offset: i64, to: u64) -> Result<i32> {
+    i32::try_from(offset).map_err(|_| UPatchError::BranchOutOfRange(to, offset).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_branch() {
+        let jmp = Branch::Jmp;
+        assert_eq!(
+            jmp.encode(0x1000, 0x1010, BranchForm::Auto)
+                .unwrap()
+                .to_hex(),
+            "EB0E"
+        );
+        assert_eq!(
+            jmp.encode(0x1000, 0x1010, BranchForm::Near)
+                .unwrap()
+                .to_hex(),
+            "E90B000000"
+        );
+        assert_eq!(
+            jmp.encode(0x1000, 0x800, BranchForm::Auto)
+                .unwrap()
+                .to_hex(),
+            "E9FBF7FFFF"
+        );
+        assert!(jmp.encode(0x1000, 0x800, BranchForm::Short).is_err());
+
+        let jne = Branch::from_mnemonic("jnz").unwrap();
+        assert_eq!(
+            jne.encode(0x1000, 0x1010, BranchForm::Short)
+                .unwrap()
+                .to_hex(),
+            "750E"
+        );
+        assert_eq!(
+            jne.encode_in(0x1000, 0x1010, 6).unwrap().to_hex(),
+            "0F850A000000"
+        );
+
+        let call = Branch::Call;
+        assert!(call.encode(0x1000, 0x1010, BranchForm::Short).is_err());
+        assert_eq!(
+            call.encode_in(0x1000, 0x1010, 6).unwrap().to_hex(),
+            "E80B00000090"
+        );
+        assert!(call.encode_in(0x1000, 0x1010, 4).is_err());
+
+        // 超出 rel32 范围
+        assert!(
+            call.encode(0x1000, 0x1_0000_1000, BranchForm::Near)
+                .is_err()
+        );
+
+        assert_eq!(
+            encode_displacement(0x1005, 0x1010, 4).unwrap().to_hex(),
+            "0B000000"
+        );
+        assert_eq!(
+            encode_displacement(0x1002, 0x1000, 1).unwrap().to_hex(),
+            "FE"
+        );
+        assert!(encode_displacement(0x1002, 0x2000, 1).is_err());
+        assert!(encode_displacement(0x1002, 0x1000, 2).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::errors::Result;
+use crate::patch::errors::UPatchError;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// 捕获值的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureKind {
+    /// `{name:4}` 小端无符号整数
+    Unsigned,
+    /// `{name:i4}` 小端有符号整数
+    Signed,
+    /// `{name:rel4}` 相对位移，以捕获结束处为基准解析为 RVA
+    Relative,
+}
+
+/// 特征码中的命名捕获，如 `8B 88 {off:4} 00 00`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternCapture {
+    pub name: String,
+    /// 在特征码中的偏移
+    pub offset: usize,
+    pub size: usize,
+    pub kind: CaptureKind,
+}
+
+/// 解码后的捕获值
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureValue {
+    Unsigned(u64),
+    Signed(i64),
+}
+
+impl Default for CaptureValue {
+    fn default() -> Self {
+        Self::Unsigned(0)
+    }
+}
+
+impl PatternCapture {
+    /// 解析 `name:spec`，offset 为捕获在特征码中的位置
+    pub fn parse(text: &str, offset: usize) -> Result<Self> {
+        let invalid = || UPatchError::InvalidCapture(text.to_string());
+        let (name, spec) = text.split_once(':').ok_or_else(invalid)?;
+        let valid_name = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(invalid().into());
+        }
+        let (kind, size) = if let Some(size) = spec.strip_prefix("rel") {
+            (CaptureKind::Relative, size)
+        } else if let Some(size) = spec.strip_prefix('i') {
+            (CaptureKind::Signed, size)
+        } else {
+            (CaptureKind::Unsigned, spec)
+        };
+        let size = size.parse::<usize>().map_err(|_| invalid())?;
+        let valid_size = match kind {
+            CaptureKind::Relative => matches!(size, 1 | 4),
+            _ => matches!(size, 1 | 2 | 4 | 8),
+        };
+        if !valid_size {
+            return Err(invalid().into());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            offset,
+            size,
+            kind,
+        })
+    }
+
+    /// 从匹配位置的数据中解码，rva 为匹配位置的 RVA
+    pub fn decode(&self, data: &[u8], rva: u64) -> Result<CaptureValue> {
+        let bytes = data
+            .get(self.offset..self.offset + self.size)
+            .ok_or_else(|| UPatchError::InvalidCapture(self.to_string()))?;
+        let mut buf = [0u8; 8];
+        buf[..self.size].copy_from_slice(bytes);
+        let unsigned = u64::from_le_bytes(buf);
+        // 符号扩展
+        let shift = 64 - self.size as u32 * 8;
+        let signed = ((unsigned << shift) as i64) >> shift;
+        match self.kind {
+            CaptureKind::Unsigned => Ok(CaptureValue::Unsigned(unsigned)),
+            CaptureKind::Signed => Ok(CaptureValue::Signed(signed)),
+            CaptureKind::Relative => {
+                let next = rva + (self.offset + self.size) as u64;
+                next.checked_add_signed(signed)
+                    .map(CaptureValue::Unsigned)
+                    .ok_or_else(|| UPatchError::InvalidCapture(self.to_string()).into())
+            }
+        }
+    }
+}
+
+impl Display for PatternCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            CaptureKind::Unsigned => write!(f, "{{{}:{}}}", self.name, self.size),
+            CaptureKind::Signed => write!(f, "{{{}:i{}}}", self.name, self.size),
+            CaptureKind::Relative => write!(f, "{{{}:rel{}}}", self.name, self.size),
+        }
+    }
+}
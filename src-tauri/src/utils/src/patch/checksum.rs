@@ -0,0 +1,79 @@
+use crate::errors::Result;
+use crate::patch::errors::UPatchError;
+
+// IMAGE_DOS_HEADER.e_lfanew
+const E_LFANEW_OFFSET: usize = 0x3C;
+// Signature(4) + IMAGE_FILE_HEADER(20)
+const OPTIONAL_HEADER_OFFSET: usize = 24;
+// IMAGE_OPTIONAL_HEADER.CheckSum，32 位与 64 位相同
+const CHECKSUM_OFFSET: usize = 64;
+
+/// PE 校验和变化
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PeChecksum {
+    pub old: u32,
+    pub new: u32,
+}
+
+/// OptionalHeader.CheckSum 字段的文件偏移
+pub fn checksum_offset(data: &[u8]) -> Result<usize> {
+    let e_lfanew = data
+        .get(E_LFANEW_OFFSET..E_LFANEW_OFFSET + 4)
+        .ok_or(UPatchError::InvalidPeHeader)?;
+    let e_lfanew = u32::from_le_bytes(e_lfanew.try_into().unwrap()) as usize;
+    let offset = e_lfanew + OPTIONAL_HEADER_OFFSET + CHECKSUM_OFFSET;
+    if data.get(e_lfanew..e_lfanew + 4) != Some(b"PE\0\0") || offset + 4 > data.len() {
+        return Err(UPatchError::InvalidPeHeader.into());
+    }
+    Ok(offset)
+}
+
+pub fn read_checksum(data: &[u8]) -> Result<u32> {
+    let offset = checksum_offset(data)?;
+    Ok(u32::from_le_bytes(
+        data[offset..offset + 4].try_into().unwrap(),
+    ))
+}
+
+/// 与 imagehlp!CheckSumMappedFile 相同的算法，计算时跳过 CheckSum 字段本身
+pub fn compute_checksum(data: &[u8]) -> Result<u32> {
+    let offset = checksum_offset(data)?;
+    let mut sum: u64 = 0;
+    for (i, word) in data.chunks(2).enumerate() {
+        if i * 2 == offset || i * 2 == offset + 2 {
+            continue;
+        }
+        let word = match word {
+            [lo, hi] => u16::from_le_bytes([*lo, *hi]),
+            [lo] => *lo as u16,
+            _ => 0,
+        };
+        sum += word as u64;
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum = (sum & 0xFFFF) + (sum >> 16);
+    Ok((sum as u32).wrapping_add(data.len() as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_checksum() {
+        let mut data = vec![0u8; 0x200];
+        data[E_LFANEW_OFFSET] = 0x80;
+        data[0x80..0x84].copy_from_slice(b"PE\0\0");
+        data[0x100] = 0x34;
+        data[0x101] = 0x12;
+        let offset = checksum_offset(&data).unwrap();
+        assert_eq!(offset, 0x80 + 24 + 64);
+        // "PE" = 0x4550 + 0x1234 + 0x80 + 文件大小
+        let expected = 0x4550 + 0x1234 + 0x80 + 0x200;
+        assert_eq!(compute_checksum(&data).unwrap(), expected);
+        // 校验和字段不参与计算
+        data[offset..offset + 4].copy_from_slice(&0xDEADBEEFu32.to_le_bytes());
+        assert_eq!(compute_checksum(&data).unwrap(), expected);
+        assert_eq!(read_checksum(&data).unwrap(), 0xDEADBEEF);
+    }
+}
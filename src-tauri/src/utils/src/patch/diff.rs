@@ -0,0 +1,337 @@
+use crate::errors::Result;
+use crate::patch::errors::UPatchError;
+use crc32fast::Hasher;
+use sha2::Digest;
+use sha2::Sha256;
+
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_FOOTER: &[u8] = b"EOF";
+// IPS 偏移只有 3 字节，且不能与 "EOF" 冲突
+const IPS_MAX_OFFSET: usize = 0xFFFFFF;
+const IPS_EOF_OFFSET: usize = 0x454F46;
+const IPS_MAX_SIZE: usize = 0xFFFF;
+
+const BPS_HEADER: &[u8] = b"BPS1";
+const BPS_SOURCE_READ: u64 = 0;
+const BPS_TARGET_READ: u64 = 1;
+
+/// 一段连续的修改
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffRecord {
+    pub offset: usize,
+    /// 从补丁文件中导入时可能没有原始数据
+    pub orignal: Vec<u8>,
+    pub replace: Vec<u8>,
+}
+
+impl DiffRecord {
+    pub fn new(offset: usize, orignal: Vec<u8>, replace: Vec<u8>) -> Self {
+        Self {
+            offset,
+            orignal,
+            replace,
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        self.offset + self.replace.len()
+    }
+}
+
+/// 排序并检查记录之间没有重叠
+pub fn sort_records(records: &mut [DiffRecord]) -> Result<()> {
+    records.sort_by_key(|record| record.offset);
+    for pair in records.windows(2) {
+        if pair[0].end() > pair[1].offset {
+            return Err(
+                UPatchError::InvalidDiff(format!("修改区间重叠：{:#X}", pair[1].offset)).into(),
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn encode_ips(records: &[DiffRecord]) -> Result<Vec<u8>> {
+    let mut r = IPS_HEADER.to_vec();
+    for record in records {
+        // 超长记录拆分写入
+        for (i, chunk) in record.replace.chunks(IPS_MAX_SIZE).enumerate() {
+            let offset = record.offset + i * IPS_MAX_SIZE;
+            if offset > IPS_MAX_OFFSET || offset == IPS_EOF_OFFSET {
+                return Err(UPatchError::DiffOffsetOutOfRange(offset).into());
+            }
+            r.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            r.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            r.extend_from_slice(chunk);
+        }
+    }
+    r.extend_from_slice(IPS_FOOTER);
+    Ok(r)
+}
+
+pub fn decode_ips(data: &[u8]) -> Result<Vec<DiffRecord>> {
+    let invalid = |msg: &str| UPatchError::InvalidDiff(format!("IPS {}", msg));
+    if !data.starts_with(IPS_HEADER) {
+        return Err(invalid("文件头无效").into());
+    }
+    let mut records = Vec::new();
+    let mut pos = IPS_HEADER.len();
+    loop {
+        let head = data.get(pos..pos + 3).ok_or(invalid("文件不完整"))?;
+        if head == IPS_FOOTER {
+            break;
+        }
+        let offset = u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize;
+        let size = data.get(pos + 3..pos + 5).ok_or(invalid("文件不完整"))?;
+        let size = u16::from_be_bytes([size[0], size[1]]) as usize;
+        pos += 5;
+        let replace = match size {
+            // RLE 记录
+            0 => {
+                let rle = data.get(pos..pos + 3).ok_or(invalid("文件不完整"))?;
+                pos += 3;
+                vec![rle[2]; u16::from_be_bytes([rle[0], rle[1]]) as usize]
+            }
+            _ => {
+                let replace = data.get(pos..pos + size).ok_or(invalid("文件不完整"))?;
+                pos += size;
+                replace.to_vec()
+            }
+        };
+        records.push(DiffRecord::new(offset, Vec::new(), replace));
+    }
+    Ok(records)
+}
+
+fn write_varint(r: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let x = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            r.push(0x80 | x);
+            break;
+        }
+        r.push(x);
+        value -= 1;
+    }
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64> {
+    let overflow = || UPatchError::InvalidDiff("BPS 数值溢出".to_string());
+    let mut value: u64 = 0;
+    let mut shift: u64 = 1;
+    loop {
+        let x = *data
+            .get(*pos)
+            .ok_or(UPatchError::InvalidDiff("BPS 文件不完整".to_string()))?;
+        *pos += 1;
+        value = ((x & 0x7F) as u64)
+            .checked_mul(shift)
+            .and_then(|v| value.checked_add(v))
+            .ok_or_else(overflow)?;
+        if x & 0x80 != 0 {
+            break;
+        }
+        shift = shift.checked_mul(0x80).ok_or_else(overflow)?;
+        value = value.checked_add(shift).ok_or_else(overflow)?;
+    }
+    Ok(value)
+}
+
+/// 将 data 中记录所在区域替换为原始数据或替换数据，分段送入 f，避免复制整个文件
+fn for_each_chunk<F: FnMut(&[u8])>(data: &[u8], records: &[DiffRecord], replace: bool, mut f: F) {
+    let mut pos = 0;
+    for record in records {
+        f(&data[pos..record.offset]);
+        match replace {
+            true => f(&record.replace),
+            false => f(&record.orignal),
+        }
+        pos = record.end();
+    }
+    f(&data[pos..]);
+}
+
+fn crc32(data: &[u8], records: &[DiffRecord], replace: bool) -> u32 {
+    let mut hasher = Hasher::new();
+    for_each_chunk(data, records, replace, |chunk| hasher.update(chunk));
+    hasher.finalize()
+}
+
+/// records 需已排序且包含原始数据，修改前后文件大小不变
+/// data 可以是修补前或修补后的文件
+pub fn encode_bps(data: &[u8], records: &[DiffRecord]) -> Vec<u8> {
+    let mut r = BPS_HEADER.to_vec();
+    write_varint(&mut r, data.len() as u64);
+    write_varint(&mut r, data.len() as u64);
+    // metadata
+    write_varint(&mut r, 0);
+    let mut pos = 0;
+    let action = |r: &mut Vec<u8>, command: u64, len: usize| {
+        write_varint(r, ((len as u64 - 1) << 2) | command);
+    };
+    for record in records {
+        if record.offset > pos {
+            action(&mut r, BPS_SOURCE_READ, record.offset - pos);
+        }
+        if !record.replace.is_empty() {
+            action(&mut r, BPS_TARGET_READ, record.replace.len());
+            r.extend_from_slice(&record.replace);
+        }
+        pos = record.end();
+    }
+    if data.len() > pos {
+        action(&mut r, BPS_SOURCE_READ, data.len() - pos);
+    }
+
+    r.extend_from_slice(&crc32(data, records, false).to_le_bytes());
+    r.extend_from_slice(&crc32(data, records, true).to_le_bytes());
+    let patch_crc = crc32fast::hash(&r);
+    r.extend_from_slice(&patch_crc.to_le_bytes());
+    r
+}
+
+/// 校验 BPS 补丁并转换为修改记录，只支持等长且不含复制指令的补丁
+pub fn decode_bps(source: &[u8], data: &[u8]) -> Result<Vec<DiffRecord>> {
+    let invalid = |msg: &str| UPatchError::InvalidDiff(format!("BPS {}", msg));
+    if !data.starts_with(BPS_HEADER) || data.len() < BPS_HEADER.len() + 12 {
+        return Err(invalid("文件头无效").into());
+    }
+    let footer = data.len() - 12;
+    let crc = |pos: usize| u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+    if crc32fast::hash(&data[..footer + 8]) != crc(footer + 8) {
+        return Err(invalid("补丁文件校验失败").into());
+    }
+    if crc32fast::hash(source) != crc(footer) {
+        return Err(UPatchError::DiffSourceMismatch.into());
+    }
+
+    let mut pos = BPS_HEADER.len();
+    let source_size = read_varint(data, &mut pos)? as usize;
+    let target_size = read_varint(data, &mut pos)? as usize;
+    if source_size != source.len() || target_size != source_size {
+        return Err(invalid("不支持修改文件大小").into());
+    }
+    let metadata_size = read_varint(data, &mut pos)? as usize;
+    pos = pos.saturating_add(metadata_size);
+
+    let mut records = Vec::new();
+    let mut output: usize = 0;
+    while pos < footer {
+        let action = read_varint(data, &mut pos)?;
+        let len = (action >> 2) as usize + 1;
+        let end = output
+            .checked_add(len)
+            .filter(|end| *end <= target_size)
+            .ok_or(invalid("写入位置超出文件大小"))?;
+        match action & 3 {
+            BPS_SOURCE_READ => {}
+            BPS_TARGET_READ => {
+                let replace = pos
+                    .checked_add(len)
+                    .and_then(|next| data.get(pos..next))
+                    .ok_or(invalid("文件不完整"))?;
+                pos += len;
+                let orignal = &source[output..end];
+                records.push(DiffRecord::new(output, orignal.to_vec(), replace.to_vec()));
+            }
+            _ => return Err(invalid("不支持复制指令").into()),
+        }
+        output = end;
+    }
+    if output != target_size {
+        return Err(invalid("输出大小不一致").into());
+    }
+    if crc32(source, &records, true) != crc(footer + 4) {
+        return Err(invalid("目标文件校验失败").into());
+    }
+    Ok(records)
+}
+
+/// 合并相邻的记录，只保留替换数据，用于比较 IPS 拆分或者 BPS 分段后的记录
+pub fn merge_records(records: &[DiffRecord]) -> Vec<DiffRecord> {
+    let mut records = records.to_vec();
+    records.sort_by_key(|record| record.offset);
+    let mut merged: Vec<DiffRecord> = Vec::new();
+    for record in records {
+        match merged.last_mut() {
+            Some(last) if last.end() == record.offset => last.replace.extend(record.replace),
+            _ => merged.push(DiffRecord::new(record.offset, Vec::new(), record.replace)),
+        }
+    }
+    merged
+}
+
+/// 将修改区域还原为原始数据后计算 SHA-256，
+/// 同一个文件无论是否已应用该补丁，结果都相同
+pub fn source_hash(data: &[u8], records: &[DiffRecord]) -> String {
+    let mut hasher = Sha256::new();
+    for_each_chunk(data, records, false, |chunk| hasher.update(chunk));
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ips_bps_roundtrip() {
+        let source: Vec<u8> = (0..=255u8).cycle().take(1024).collect();
+        let mut records = vec![
+            DiffRecord::new(0x300, source[0x300..0x302].to_vec(), vec![0x90, 0x90]),
+            DiffRecord::new(0x10, source[0x10..0x11].to_vec(), vec![0xEB]),
+        ];
+        sort_records(&mut records).unwrap();
+
+        let ips = encode_ips(&records).unwrap();
+        let decoded = decode_ips(&ips).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].offset, 0x300);
+        assert_eq!(decoded[1].replace, vec![0x90, 0x90]);
+
+        let bps = encode_bps(&source, &records);
+        assert_eq!(decode_bps(&source, &bps).unwrap(), records);
+        // 从修补后的文件导出结果相同
+        let mut patched = source.clone();
+        patched[0x10] = 0xEB;
+        patched[0x300..0x302].copy_from_slice(&[0x90, 0x90]);
+        assert_eq!(encode_bps(&patched, &records), bps);
+
+        let mut other = source.clone();
+        other[0] = 0xFF;
+        assert!(decode_bps(&other, &bps).is_err());
+
+        assert_eq!(
+            source_hash(&source, &records),
+            source_hash(&patched, &records)
+        );
+
+        // IPS 按 0xFFFF 字节拆分，合并后与原记录一致
+        let long = vec![DiffRecord::new(
+            0x20,
+            Vec::new(),
+            vec![0x90; IPS_MAX_SIZE + 2],
+        )];
+        let decoded = decode_ips(&encode_ips(&long).unwrap()).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(merge_records(&decoded), long);
+
+        // 溢出的变长整数和越界的写入返回错误
+        let mut pos = 0;
+        assert!(read_varint(&[0x7F; 16], &mut pos).is_err());
+        let mut hostile = BPS_HEADER.to_vec();
+        write_varint(&mut hostile, source.len() as u64);
+        write_varint(&mut hostile, source.len() as u64);
+        write_varint(&mut hostile, 0);
+        write_varint(&mut hostile, ((u64::MAX >> 2) << 2) | BPS_TARGET_READ);
+        hostile.extend_from_slice(&crc32fast::hash(&source).to_le_bytes());
+        hostile.extend_from_slice(&[0; 4]);
+        let patch_crc = crc32fast::hash(&hostile);
+        hostile.extend_from_slice(&patch_crc.to_le_bytes());
+        assert!(decode_bps(&source, &hostile).is_err());
+    }
+}
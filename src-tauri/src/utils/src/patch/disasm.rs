@@ -0,0 +1,111 @@
+use crate::patch::types::Bytes;
+use iced_x86::Decoder;
+use iced_x86::DecoderOptions;
+use iced_x86::Formatter;
+use iced_x86::Instruction;
+use iced_x86::IntelFormatter;
+use serde::Serialize;
+
+/// 一条反汇编指令
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DisasmLine {
+    pub rva: u64,
+    pub bytes: String,
+    pub text: String,
+    /// 指令包含被修改的字节
+    pub changed: bool,
+}
+
+/// 反汇编 data，ip 为 data[0] 的 RVA，changed 为被修改字节的区间（相对 data）
+pub fn disassemble(
+    data: &[u8],
+    ip: u64,
+    bitness: u32,
+    changed: &[(usize, usize)],
+) -> Vec<DisasmLine> {
+    let mut decoder = Decoder::with_ip(bitness, data, ip, DecoderOptions::NONE);
+    let mut formatter = IntelFormatter::new();
+    let mut instruction = Instruction::default();
+    let mut lines = Vec::new();
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+        let start = (instruction.ip() - ip) as usize;
+        let end = start + instruction.len();
+        let mut text = String::new();
+        if instruction.is_invalid() {
+            text.push_str("(bad)");
+        } else {
+            formatter.format(&instruction, &mut text);
+        }
+        lines.push(DisasmLine {
+            rva: instruction.ip(),
+            bytes: Bytes::new(&data[start..end]).to_hex(),
+            text,
+            changed: changed.iter().any(|(s, e)| start < *e && *s < end),
+        });
+    }
+    lines
+}
+
+/// 在 target 之前找一个尽量早的起点，使解码出的指令边界恰好落在 target 上
+pub fn align_start(data: &[u8], ip: u64, bitness: u32, target: usize) -> usize {
+    let mut instruction = Instruction::default();
+    for start in 0..target {
+        let mut decoder = Decoder::with_ip(
+            bitness,
+            &data[start..],
+            ip + start as u64,
+            DecoderOptions::NONE,
+        );
+        let mut pos = start;
+        while pos < target && decoder.can_decode() {
+            decoder.decode_out(&mut instruction);
+            if instruction.is_invalid() {
+                break;
+            }
+            pos += instruction.len();
+        }
+        if pos == target {
+            return start;
+        }
+    }
+    target
+}
+
+/// 两段等长数据中不同字节的区间
+pub fn diff_ranges(a: &[u8], b: &[u8]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        if x == y {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, end)) if *end == i => *end = i + 1,
+            _ => ranges.push((i, i + 1)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        // push rbp; mov rbp,rsp; xor eax,eax; ret
+        let orignal = [0x55, 0x48, 0x89, 0xE5, 0x31, 0xC0, 0xC3];
+        let replace = [0x55, 0x48, 0x89, 0xE5, 0xB0, 0x01, 0xC3];
+        let changed = diff_ranges(&orignal, &replace);
+        assert_eq!(changed, vec![(4, 6)]);
+
+        let lines = disassemble(&replace, 0x1000, 64, &changed);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[2].rva, 0x1004);
+        assert_eq!(lines[2].text, "mov al,1");
+        assert!(lines[2].changed);
+        assert!(!lines[1].changed);
+
+        assert_eq!(align_start(&orignal, 0x1000, 64, 4), 0);
+    }
+}
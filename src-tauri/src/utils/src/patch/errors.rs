@@ -20,9 +20,18 @@ pub enum UPatchError {
     #[error("无效特征码")]
     PatternBuilderError,
 
+    #[error("无效特征码：{0}")]
+    InvalidBytePattern(String),
+
+    #[error("无效的特征码捕获：{0}")]
+    InvalidCapture(String),
+
     #[error("未搜索到特征码")]
     PatternNotFindError,
 
+    #[error("未找到引用字符串 {0} 的代码")]
+    StringXrefNotFound(String),
+
     #[error("找不到{0}缓存")]
     PatchWithCacheNotFind(String),
 
@@ -40,4 +49,70 @@ pub enum UPatchError {
 
     #[error("FOA转换RVA失败")]
     FOAToRVAError,
+
+    #[error("FOA {0:#X} 超出文件大小")]
+    FoaOutOfFile(u64),
+
+    #[error("FOA {0:#X} 位于附加数据中，未映射到内存")]
+    FoaInOverlay(u64),
+
+    #[error("FOA {0:#X} 未映射到内存")]
+    FoaNotMapped(u64),
+
+    #[error("RVA {0:#X} 超出映像大小")]
+    RvaOutOfImage(u64),
+
+    #[error("RVA {0:#X} 没有对应的文件数据")]
+    RvaNotInFile(u64),
+
+    #[error("VA {0:#X} 超出映像范围")]
+    VaOutOfImage(u64),
+
+    #[error("未找到节：{0}")]
+    SectionNotFound(String),
+
+    #[error("无效的搜索范围：{0}")]
+    InvalidSearchRange(String),
+
+    #[error("解析 PE 失败：{0}")]
+    InvalidPe(String),
+
+    #[error("文件 {0} 被占用，请关闭相关程序后重试")]
+    TargetFileLocked(String),
+
+    #[error("替换文件 {0} 失败：{1}")]
+    ReplaceFileError(String, String),
+
+    #[error("文件 {0} 以内存映射方式打开，无法原子保存")]
+    AtomicSaveMapped(String),
+
+    #[error("临时文件 {0} 校验失败，写入数据不完整")]
+    TempFileVerifyError(String),
+
+    #[error("无效的PE文件头")]
+    InvalidPeHeader,
+
+    #[error("数字签名解析失败：{0}")]
+    InvalidSignature(String),
+
+    #[error("无效的补丁文件：{0}")]
+    InvalidDiff(String),
+
+    #[error("补丁偏移 {0:#X} 超出 IPS 格式支持范围，请使用 BPS 格式")]
+    DiffOffsetOutOfRange(usize),
+
+    #[error("补丁与当前文件不匹配，请确认文件版本")]
+    DiffSourceMismatch,
+
+    #[error("跳转目标 {0:#X} 超出范围，偏移量：{1}")]
+    BranchOutOfRange(u64, i64),
+
+    #[error("{0} 不支持短跳转")]
+    BranchFormNotSupported(String),
+
+    #[error("跳转指令长度 {0} 无效")]
+    InvalidBranchLength(usize),
+
+    #[error("位移长度 {0} 无效，应为 1 或不小于 4")]
+    InvalidDisplacementLength(usize),
 }
@@ -0,0 +1,315 @@
+use crate::errors::Result;
+use crate::patch::types::Section;
+use std::path::Path;
+
+const FILE_ALIGNMENT: u64 = 0x200;
+const SECTION_ALIGNMENT: u64 = 0x1000;
+const DOS_HEADER_SIZE: usize = 0x40;
+const FILE_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const DATA_DIRECTORY_COUNT: u32 = 16;
+
+const SCN_CNT_CODE: u32 = 0x0000_0020;
+const SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+const SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const SCN_MEM_READ: u32 = 0x4000_0000;
+const SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// 测试用的 PE 节
+#[derive(Debug, Clone)]
+pub struct PeSection {
+    pub name: String,
+    pub data: Vec<u8>,
+    pub characteristics: u32,
+}
+
+/// 生成最小可解析的 PE 文件，用于在任意平台上测试搜索和补丁
+///
+/// 文件对齐 0x200，内存对齐 0x1000，节按添加顺序依次排列，没有导入表和数字签名
+#[derive(Debug, Clone)]
+pub struct PeBuilder {
+    bitness: u32,
+    image_base: u64,
+    sections: Vec<PeSection>,
+}
+
+impl Default for PeBuilder {
+    fn default() -> Self {
+        Self {
+            bitness: 64,
+            image_base: 0x1_4000_0000,
+            sections: Vec::new(),
+        }
+    }
+}
+
+impl PeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 32 或 64，32 位时默认基址为 0x400000
+    pub fn bitness(mut self, bitness: u32) -> Self {
+        if bitness == 32 && self.image_base > u32::MAX as u64 {
+            self.image_base = 0x40_0000;
+        }
+        self.bitness = bitness;
+        self
+    }
+
+    pub fn image_base(mut self, image_base: u64) -> Self {
+        self.image_base = image_base;
+        self
+    }
+
+    /// 添加节，.text 为可执行代码，.data 可写，其余为只读数据
+    pub fn section(self, name: &str, data: &[u8]) -> Self {
+        let characteristics = match name {
+            ".text" => SCN_CNT_CODE | SCN_MEM_EXECUTE | SCN_MEM_READ,
+            ".data" => SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ | SCN_MEM_WRITE,
+            _ => SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ,
+        };
+        self.section_with(name, data, characteristics)
+    }
+
+    pub fn section_with(mut self, name: &str, data: &[u8], characteristics: u32) -> Self {
+        self.sections.push(PeSection {
+            name: name.to_string(),
+            data: data.to_vec(),
+            characteristics,
+        });
+        self
+    }
+
+    /// 节在文件和内存中的位置，与 build 生成的文件一致
+    pub fn sections(&self) -> Vec<Section> {
+        let mut file_start = self.size_of_headers();
+        let mut virtual_address = align(file_start, SECTION_ALIGNMENT);
+        self.sections
+            .iter()
+            .map(|section| {
+                let size = section.data.len() as u64;
+                let layout = Section {
+                    name: section.name.clone(),
+                    file_start,
+                    file_end: file_start + align(size, FILE_ALIGNMENT),
+                    virtual_address,
+                    virtual_size: size,
+                };
+                file_start = layout.file_end;
+                virtual_address += align(size.max(1), SECTION_ALIGNMENT);
+                layout
+            })
+            .collect()
+    }
+
+    /// 节内偏移对应的文件偏移
+    pub fn foa(&self, name: &str, offset: u64) -> Option<u64> {
+        self.find(name).map(|section| section.file_start + offset)
+    }
+
+    /// 节内偏移对应的 RVA
+    pub fn rva(&self, name: &str, offset: u64) -> Option<u64> {
+        self.find(name)
+            .map(|section| section.virtual_address + offset)
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let layout = self.sections();
+        let size_of_headers = self.size_of_headers();
+        let size_of_image = layout
+            .last()
+            .map(|section| {
+                section.virtual_address + align(section.virtual_size.max(1), SECTION_ALIGNMENT)
+            })
+            .unwrap_or_else(|| align(size_of_headers, SECTION_ALIGNMENT));
+        let file_size = layout
+            .last()
+            .map(|section| section.file_end)
+            .unwrap_or(size_of_headers);
+        let mut data = vec![0u8; file_size as usize];
+
+        // DOS 头
+        data[0..2].copy_from_slice(b"MZ");
+        put_u32(&mut data, 0x3C, DOS_HEADER_SIZE as u32);
+
+        // NT 头
+        let mut pos = DOS_HEADER_SIZE;
+        data[pos..pos + 4].copy_from_slice(b"PE\0\0");
+        pos += 4;
+        let (machine, characteristics) = match self.bitness {
+            32 => (0x014C, 0x0102),
+            _ => (0x8664, 0x0022),
+        };
+        put_u16(&mut data, pos, machine);
+        put_u16(&mut data, pos + 2, layout.len() as u16);
+        put_u16(&mut data, pos + 16, self.size_of_optional_header() as u16);
+        put_u16(&mut data, pos + 18, characteristics);
+        pos += FILE_HEADER_SIZE;
+
+        // 可选头
+        let code = layout
+            .iter()
+            .zip(&self.sections)
+            .find(|(_, section)| section.characteristics & SCN_CNT_CODE != 0);
+        let size_of_code = code
+            .map(|(layout, _)| layout.file_end - layout.file_start)
+            .unwrap_or_default();
+        let base_of_code = code
+            .map(|(layout, _)| layout.virtual_address)
+            .unwrap_or_default();
+        let opt = pos;
+        put_u16(
+            &mut data,
+            opt,
+            if self.bitness == 32 { 0x10B } else { 0x20B },
+        );
+        put_u32(&mut data, opt + 4, size_of_code as u32);
+        put_u32(&mut data, opt + 16, base_of_code as u32);
+        put_u32(&mut data, opt + 20, base_of_code as u32);
+        match self.bitness {
+            32 => put_u32(&mut data, opt + 28, self.image_base as u32),
+            _ => put_u64(&mut data, opt + 24, self.image_base),
+        }
+        put_u32(&mut data, opt + 32, SECTION_ALIGNMENT as u32);
+        put_u32(&mut data, opt + 36, FILE_ALIGNMENT as u32);
+        put_u16(&mut data, opt + 40, 6);
+        put_u16(&mut data, opt + 48, 6);
+        put_u32(&mut data, opt + 56, size_of_image as u32);
+        put_u32(&mut data, opt + 60, size_of_headers as u32);
+        // Windows GUI
+        put_u16(&mut data, opt + 68, 2);
+        match self.bitness {
+            32 => {
+                put_u32(&mut data, opt + 72, 0x10_0000);
+                put_u32(&mut data, opt + 76, 0x1000);
+                put_u32(&mut data, opt + 80, 0x10_0000);
+                put_u32(&mut data, opt + 84, 0x1000);
+                put_u32(&mut data, opt + 92, DATA_DIRECTORY_COUNT);
+            }
+            _ => {
+                put_u64(&mut data, opt + 72, 0x10_0000);
+                put_u64(&mut data, opt + 80, 0x1000);
+                put_u64(&mut data, opt + 88, 0x10_0000);
+                put_u64(&mut data, opt + 96, 0x1000);
+                put_u32(&mut data, opt + 108, DATA_DIRECTORY_COUNT);
+            }
+        }
+        pos += self.size_of_optional_header();
+
+        // 节表和节数据
+        for (layout, section) in layout.iter().zip(&self.sections) {
+            let mut name = [0u8; 8];
+            let len = section.name.len().min(8);
+            name[..len].copy_from_slice(&section.name.as_bytes()[..len]);
+            data[pos..pos + 8].copy_from_slice(&name);
+            put_u32(&mut data, pos + 8, layout.virtual_size as u32);
+            put_u32(&mut data, pos + 12, layout.virtual_address as u32);
+            put_u32(
+                &mut data,
+                pos + 16,
+                (layout.file_end - layout.file_start) as u32,
+            );
+            put_u32(&mut data, pos + 20, layout.file_start as u32);
+            put_u32(&mut data, pos + 36, section.characteristics);
+            pos += SECTION_HEADER_SIZE;
+
+            let start = layout.file_start as usize;
+            data[start..start + section.data.len()].copy_from_slice(&section.data);
+        }
+        data
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, file: P) -> Result<()> {
+        std::fs::write(file, self.build())?;
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Option<Section> {
+        self.sections()
+            .into_iter()
+            .find(|section| section.name == name)
+    }
+
+    fn size_of_optional_header(&self) -> usize {
+        match self.bitness {
+            32 => 224,
+            _ => 240,
+        }
+    }
+
+    fn size_of_headers(&self) -> u64 {
+        let size = DOS_HEADER_SIZE
+            + 4
+            + FILE_HEADER_SIZE
+            + self.size_of_optional_header()
+            + SECTION_HEADER_SIZE * self.sections.len();
+        align(size as u64, FILE_ALIGNMENT)
+    }
+}
+
+fn align(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+fn put_u16(data: &mut [u8], pos: usize, value: u16) {
+    data[pos..pos + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(data: &mut [u8], pos: usize, value: u32) {
+    data[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(data: &mut [u8], pos: usize, value: u64) {
+    data[pos..pos + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::image::ImageFormat;
+    use crate::patch::image::open_image;
+    use crate::patch::patch::UPatch;
+    use crate::patch::types::BytePattern;
+    use crate::patch::types::PatchDataType;
+    use crate::patch::types::SearchScope;
+
+    #[test]
+    fn test_pe_builder() {
+        let builder = PeBuilder::new()
+            .section(".text", b"\x48\x89\x5C\x24\x08\xC3")
+            .section(".rdata", b"hello\0");
+        let data = builder.build();
+        let image = open_image(&data).unwrap();
+        assert_eq!(image.format(), ImageFormat::Pe);
+        assert_eq!(image.bitness(), 64);
+        assert_eq!(image.image_base(), 0x1_4000_0000);
+        let names = image
+            .sections()
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![".text", ".rdata"]);
+        let foa = builder.foa(".rdata", 1).unwrap();
+        assert_eq!(
+            image.foa_to_rva(foa).unwrap(),
+            builder.rva(".rdata", 1).unwrap()
+        );
+
+        let upatch = UPatch::new(PatchDataType::Data(data), "a.exe", "a.exe", false).unwrap();
+        let pattern = BytePattern::parse("89 5C ?? 08").unwrap();
+        let scope = SearchScope::new(Some(".text".to_string()), None);
+        let found = upatch.search_all_in(&pattern, &scope).unwrap();
+        assert_eq!(found, vec![builder.foa(".text", 1).unwrap() as usize]);
+
+        let image = open_image(
+            &PeBuilder::new()
+                .bitness(32)
+                .section(".text", b"\x90")
+                .build(),
+        )
+        .unwrap();
+        assert_eq!(image.bitness(), 32);
+        assert_eq!(image.image_base(), 0x40_0000);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::errors::Result;
+use crate::patch::address::AddressMap;
+use crate::patch::errors::UPatchError;
+use crate::patch::types::Section;
+use log::debug;
+use std::fmt::Debug;
+
+const MZ_MAGIC: &[u8] = b"MZ";
+const ELF_MAGIC: &[u8] = b"\x7FELF";
+
+/// 文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ImageFormat {
+    Pe,
+    #[default]
+    Raw,
+}
+
+/// 可执行文件的地址模型，UPatch 通过它完成 FOA、RVA、VA 转换
+///
+/// 没有节信息的格式（如原始文件）sections 返回空，按节限定的功能退化为整个文件
+pub trait BinaryImage: Debug + Send + Sync {
+    fn format(&self) -> ImageFormat;
+
+    /// 32 或 64，用于反汇编
+    fn bitness(&self) -> u32;
+
+    fn image_base(&self) -> u64;
+
+    fn sections(&self) -> &[Section];
+
+    fn foa_to_rva(&self, foa: u64) -> Result<u64>;
+
+    fn rva_to_foa(&self, rva: u64) -> Result<u64>;
+
+    fn rva_to_va(&self, rva: u64) -> Result<u64>;
+
+    fn va_to_rva(&self, va: u64) -> Result<u64>;
+
+    /// RVA 窗口对应的文件偏移区间
+    fn rva_window_to_foa(&self, start: u64, end: u64) -> Vec<(u64, u64)>;
+
+    fn foa_to_va(&self, foa: u64) -> Result<u64> {
+        self.rva_to_va(self.foa_to_rva(foa)?)
+    }
+
+    fn va_to_foa(&self, va: u64) -> Result<u64> {
+        self.rva_to_foa(self.va_to_rva(va)?)
+    }
+}
+
+/// 根据文件头选择格式，以 MZ 开头但 PE 解析失败时返回错误，其他格式按原始文件处理
+pub fn open_image(data: &[u8]) -> Result<Box<dyn BinaryImage>> {
+    if data.starts_with(MZ_MAGIC) {
+        return Ok(Box::new(AddressMap::from_pe(data)?));
+    }
+    if data.starts_with(ELF_MAGIC) {
+        debug!("暂不支持 ELF，按原始文件处理");
+    }
+    Ok(Box::new(RawImage::new(data.len() as u64)))
+}
+
+impl BinaryImage for AddressMap {
+    fn format(&self) -> ImageFormat {
+        ImageFormat::Pe
+    }
+
+    fn bitness(&self) -> u32 {
+        self.get_bitness()
+    }
+
+    fn image_base(&self) -> u64 {
+        self.get_image_base()
+    }
+
+    fn sections(&self) -> &[Section] {
+        self.get_sections()
+    }
+
+    fn foa_to_rva(&self, foa: u64) -> Result<u64> {
+        AddressMap::foa_to_rva(self, foa)
+    }
+
+    fn rva_to_foa(&self, rva: u64) -> Result<u64> {
+        AddressMap::rva_to_foa(self, rva)
+    }
+
+    fn rva_to_va(&self, rva: u64) -> Result<u64> {
+        AddressMap::rva_to_va(self, rva)
+    }
+
+    fn va_to_rva(&self, va: u64) -> Result<u64> {
+        AddressMap::va_to_rva(self, va)
+    }
+
+    fn rva_window_to_foa(&self, start: u64, end: u64) -> Vec<(u64, u64)> {
+        AddressMap::rva_window_to_foa(self, start, end)
+    }
+}
+
+/// 原始文件，文件偏移、RVA、VA 相同，没有节
+#[derive(Debug, Clone, Default)]
+pub struct RawImage {
+    size: u64,
+}
+
+impl RawImage {
+    pub fn new(size: u64) -> Self {
+        Self { size }
+    }
+
+    fn check(&self, value: u64) -> Result<u64> {
+        if value >= self.size {
+            return Err(UPatchError::FoaOutOfFile(value).into());
+        }
+        Ok(value)
+    }
+}
+
+impl BinaryImage for RawImage {
+    fn format(&self) -> ImageFormat {
+        ImageFormat::Raw
+    }
+
+    fn bitness(&self) -> u32 {
+        64
+    }
+
+    fn image_base(&self) -> u64 {
+        0
+    }
+
+    fn sections(&self) -> &[Section] {
+        &[]
+    }
+
+    fn foa_to_rva(&self, foa: u64) -> Result<u64> {
+        self.check(foa)
+    }
+
+    fn rva_to_foa(&self, rva: u64) -> Result<u64> {
+        self.check(rva)
+    }
+
+    fn rva_to_va(&self, rva: u64) -> Result<u64> {
+        self.check(rva)
+    }
+
+    fn va_to_rva(&self, va: u64) -> Result<u64> {
+        self.check(va)
+    }
+
+    fn rva_window_to_foa(&self, start: u64, end: u64) -> Vec<(u64, u64)> {
+        let end = end.min(self.size);
+        match start < end {
+            true => vec![(start, end)],
+            false => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::patch::UPatch;
+    use crate::patch::types::BytePattern;
+    use crate::patch::types::PatchDataType;
+    use crate::patch::types::SearchScope;
+
+    #[test]
+    fn test_raw_image() {
+        // 以 MZ 开头但不是 PE 时不按原始文件处理，避免修补损坏的文件
+        assert!(open_image(b"MZ not a pe file").is_err());
+        let data = PatchDataType::Data(b"MZ not a pe file".to_vec());
+        assert!(UPatch::new(data, "bad.exe", "bad.exe", false).is_err());
+
+        let image = open_image(b"raw data 16 byte").unwrap();
+        assert_eq!(image.format(), ImageFormat::Raw);
+        assert!(image.sections().is_empty());
+        assert_eq!(image.foa_to_va(4).unwrap(), 4);
+        assert!(image.rva_to_foa(16).is_err());
+        assert_eq!(image.rva_window_to_foa(8, 100), vec![(8, 16)]);
+
+        let image = open_image(b"\x7FELF\x02\x01\x01").unwrap();
+        assert_eq!(image.format(), ImageFormat::Raw);
+
+        // 原始文件按节搜索时退化为整个文件
+        let data = PatchDataType::Data(b"\x00\x11\x22\x33\x44".to_vec());
+        let upatch = UPatch::new(data, "raw.bin", "raw.bin", false).unwrap();
+        let pattern = BytePattern::parse("22 ?3").unwrap();
+        let scope = SearchScope::new(Some(".text".to_string()), None);
+        assert_eq!(upatch.search_all_in(&pattern, &scope).unwrap(), vec![2]);
+        assert_eq!(upatch.foa_to_rva(2).unwrap(), 2);
+    }
+}
@@ -9,11 +9,7 @@ pub fn calculate_jump_offset(
 ) -> Result<i32> {
     let next_instr_addr = current_addr as i64 + offset_adjust;
     let raw_offset = target_addr as i64 - next_instr_addr;
-    
-    let raw_offset = raw_offset as i32;
-    if raw_offset < i32::MIN || raw_offset > i32::MAX {
-        return Err(UPatchError::OffsetOutRangeError.into());
-    }
+    let raw_offset = i32::try_from(raw_offset).map_err(|_| UPatchError::OffsetOutRangeError)?;
     Ok(raw_offset)
 }
 
@@ -1,4 +1,16 @@
+pub mod address;
+pub mod batch;
+pub mod branch;
+pub mod capture;
+pub mod checksum;
+pub mod diff;
+pub mod disasm;
 pub mod errors;
+#[cfg(any(test, feature = "fixture"))]
+pub mod fixture;
+pub mod image;
 pub mod patch;
+pub mod signature;
 pub mod types;
-pub mod jump_offset;
+pub mod xref;
+pub mod jump_offset;
\ No newline at end of file
@@ -1,17 +1,38 @@
 use crate::errors::Result;
+use crate::patch::batch::BatchSearch;
+use crate::patch::checksum::PeChecksum;
+use crate::patch::checksum::checksum_offset;
+use crate::patch::checksum::compute_checksum;
+use crate::patch::checksum::read_checksum;
+use crate::patch::diff::DiffRecord;
 use crate::patch::errors::UPatchError;
+use crate::patch::image::BinaryImage;
+use crate::patch::image::ImageFormat;
+use crate::patch::image::open_image;
+use crate::patch::signature::SignatureInfo;
+use crate::patch::types::BytePattern;
 use crate::patch::types::Bytes;
 use crate::patch::types::Hex;
 use crate::patch::types::PatchDataType;
 use crate::patch::types::PatchType;
+use crate::patch::types::RangeBase;
+use crate::patch::types::SaveMode;
+use crate::patch::types::SearchScope;
+use crate::patch::types::Section;
+use crate::patch::xref::StringXref;
+use crate::patch::xref::find_rip_references;
 use aobscan::PatternBuilder;
+use log::debug;
 use log::info;
 use log::warn;
 use memmap2::Mmap;
 use memmap2::MmapMut;
-use pelite::PeFile;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::HashMap;
 use std::fs::File;
 use std::fs::OpenOptions;
+use std::hash::Hash;
 use std::io::Write;
 
 pub struct UPatch {
@@ -19,15 +40,31 @@ pub struct UPatch {
     file: String,
     save: String,
     with_write: bool,
-    sections: Vec<(u64, u64, u64)>,
+    save_mode: SaveMode,
+    recompute_checksum: bool,
+    checksum: Option<PeChecksum>,
+    strip_certificate: bool,
+    signature: SignatureInfo,
+    signature_broken: bool,
+    image: Box<dyn BinaryImage>,
 }
 
 impl UPatch {
     pub fn create(input: &str, save: &str, with_write: bool) -> Result<Self> {
+        Self::create_with_mode(input, save, with_write, SaveMode::default())
+    }
+
+    /// 原子保存需要替换目标文件，只有直接覆盖源文件时才使用 mmap 写入
+    pub fn create_with_mode(
+        input: &str,
+        save: &str,
+        with_write: bool,
+        save_mode: SaveMode,
+    ) -> Result<Self> {
         let data = match with_write {
             true => {
                 // 另存为时应使用 fs::read，mmap_mut 在 drop 时 flush 源文件？
-                if save == input {
+                if save == input && save_mode == SaveMode::Direct {
                     Self::open_with_map_mut(input)
                 } else {
                     Self::open_with_fs(input)
@@ -42,17 +79,39 @@ impl UPatch {
                 Self::open_with_fs(input)?
             }
         };
-        return Self::new(data, input, save, with_write);
+        let mut patch = Self::new(data, input, save, with_write)?;
+        patch.save_mode = save_mode;
+        Ok(patch)
+    }
+
+    /// 以内存副本打开，写入不会直接落盘，需调用 save 保存
+    pub fn create_staged(input: &str, save: &str) -> Result<Self> {
+        let data = Self::open_with_fs(input)?;
+        Self::new(data, input, save, true)
     }
 
     pub fn new(data: PatchDataType, file: &str, save: &str, with_write: bool) -> Result<Self> {
-        let sections = Self::init_sections(&data)?;
+        let image = open_image(Self::get_data_by_datetype(&data))?;
+        let signature = match image.format() {
+            ImageFormat::Pe => SignatureInfo::from_pe(Self::get_data_by_datetype(&data))
+                .unwrap_or_else(|e| {
+                    warn!("读取数字签名失败：{}，{}", file, e);
+                    SignatureInfo::default()
+                }),
+            _ => SignatureInfo::default(),
+        };
         Ok(Self {
             data,
             file: file.to_string(),
             save: save.to_string(),
             with_write,
-            sections: sections,
+            save_mode: SaveMode::default(),
+            recompute_checksum: false,
+            checksum: None,
+            strip_certificate: false,
+            signature,
+            signature_broken: false,
+            image,
         })
     }
 
@@ -95,6 +154,43 @@ impl UPatch {
         self.save.as_str()
     }
 
+    pub fn set_save_mode(&mut self, save_mode: SaveMode) -> &mut Self {
+        self.save_mode = save_mode;
+        self
+    }
+
+    pub fn get_save_mode(&self) -> SaveMode {
+        self.save_mode
+    }
+
+    pub fn set_recompute_checksum(&mut self, recompute_checksum: bool) -> &mut Self {
+        self.recompute_checksum = recompute_checksum;
+        self
+    }
+
+    /// 最近一次保存时重算的校验和
+    pub fn get_checksum(&self) -> Option<PeChecksum> {
+        self.checksum
+    }
+
+    pub fn set_strip_certificate(&mut self, strip_certificate: bool) -> &mut Self {
+        self.strip_certificate = strip_certificate;
+        self
+    }
+
+    pub fn get_signature(&self) -> &SignatureInfo {
+        &self.signature
+    }
+
+    /// 已写入的数据是否使数字签名失效
+    pub fn is_signature_broken(&self) -> bool {
+        self.signature_broken
+    }
+
+    pub fn is_with_write(&self) -> bool {
+        self.with_write
+    }
+
     pub fn check_pos(&self, pos: usize, len: usize) -> Result<(usize, usize)> {
         if pos > self.get_data().len() {
             return Err(UPatchError::OutRangePos1Error.into());
@@ -123,74 +219,222 @@ impl UPatch {
         self.read(pos, len)?.to_utf8()
     }
 
-    pub fn search(&self, pattern: &str) -> Result<Vec<usize>> {
-        self.search_by_pattern(pattern, false)
+    pub fn search(&self, pattern: &BytePattern) -> Result<Vec<usize>> {
+        self.search_by_pattern(pattern, false, &SearchScope::default())
     }
 
-    pub fn search_all(&self, pattern: &str) -> Result<Vec<usize>> {
-        self.search_by_pattern(pattern, true)
+    pub fn search_all(&self, pattern: &BytePattern) -> Result<Vec<usize>> {
+        self.search_by_pattern(pattern, true, &SearchScope::default())
     }
 
-    fn search_by_pattern(&self, pattern: &str, all: bool) -> Result<Vec<usize>> {
-        if pattern.is_empty() || pattern.len() % 2 != 0 {
+    pub fn search_all_in(&self, pattern: &BytePattern, scope: &SearchScope) -> Result<Vec<usize>> {
+        self.search_by_pattern(pattern, true, scope)
+    }
+
+    fn search_by_pattern(
+        &self,
+        pattern: &BytePattern,
+        all: bool,
+        scope: &SearchScope,
+    ) -> Result<Vec<usize>> {
+        if pattern.is_empty() {
             return Err(UPatchError::PatternBuilderError.into());
         }
-        let pattern = PatternBuilder::from_hex_string(pattern)
+        let data = self.get_data();
+        let mut results = Vec::new();
+        for (start, end) in self.get_scope_ranges(scope)? {
+            debug!("搜索范围：{}，FOA：[{:#X}, {:#X})", scope, start, end);
+            let poses = if pattern.is_byte_aligned() {
+                // 整字节通配符使用 aobscan 多线程搜索
+                Self::search_by_aobscan(&data[start..end], pattern, all)?
+            } else {
+                // 半字节通配符使用掩码搜索
+                pattern.find_in(&data[start..end], all)
+            };
+            results.extend(poses.into_iter().map(|pos| pos + start));
+            if !all && !results.is_empty() {
+                break;
+            }
+        }
+        if results.is_empty() {
+            return Err(UPatchError::PatternNotFindError.into());
+        }
+        Ok(results)
+    }
+
+    /// 一次扫描搜索多个特征码，并按各自的范围过滤
+    ///
+    /// 范围无效或无法批量搜索的特征码不在结果中，调用方应单独搜索
+    pub fn search_batch<K: Eq + Hash + Clone>(
+        &self,
+        batch: &BatchSearch<K>,
+    ) -> Result<HashMap<K, Vec<usize>>> {
+        let mut results = batch.search(self.get_data())?;
+        for item in batch.items() {
+            let Some(poses) = results.get_mut(&item.key) else {
+                continue;
+            };
+            if item.scope.is_all() {
+                continue;
+            }
+            match self.get_scope_ranges(&item.scope) {
+                Ok(ranges) => poses.retain(|pos| {
+                    ranges
+                        .iter()
+                        .any(|(start, end)| *start <= *pos && *pos + item.pattern.len() <= *end)
+                }),
+                Err(_) => {
+                    results.remove(&item.key);
+                }
+            }
+        }
+        debug!(
+            "批量搜索 {} 个特征码，命中 {} 个",
+            batch.len(),
+            results.values().filter(|p| !p.is_empty()).count()
+        );
+        Ok(results)
+    }
+
+    /// 搜索引用字符串的 RIP 相对指令，scope 未指定节时扫描 .text，返回指令的 FOA
+    pub fn search_string_xrefs(
+        &self,
+        xref: &StringXref,
+        scope: &SearchScope,
+    ) -> Result<Vec<usize>> {
+        let pattern = BytePattern::parse(Bytes::new(xref.to_bytes()).to_hex())?;
+        let string_scope = SearchScope::new(Some(xref.get_section().to_string()), None);
+        let targets = self
+            .search_all_in(&pattern, &string_scope)?
+            .into_iter()
+            .map(|foa| self.foa_to_rva(foa as u64))
+            .collect::<Result<Vec<u64>>>()?;
+        debug!("字符串 {} 的 RVA：{:#X?}", xref, targets);
+
+        let mut code_scope = scope.clone();
+        if code_scope.section.is_none() {
+            code_scope.section = Some(".text".to_string());
+        }
+        let data = self.get_data();
+        let bitness = self.get_bitness();
+        let mut results = Vec::new();
+        for (start, end) in self.get_scope_ranges(&code_scope)? {
+            let ip = self.foa_to_rva(start as u64)?;
+            let poses = find_rip_references(&data[start..end], ip, bitness, &targets);
+            results.extend(poses.into_iter().map(|pos| pos + start));
+        }
+        if results.is_empty() {
+            return Err(UPatchError::StringXrefNotFound(xref.to_string()).into());
+        }
+        Ok(results)
+    }
+
+    /// 将搜索范围转换为文件偏移区间
+    pub fn get_scope_ranges(&self, scope: &SearchScope) -> Result<Vec<(usize, usize)>> {
+        let len = self.len() as u64;
+        let mut ranges = match &scope.section {
+            // 没有节信息的格式按整个文件处理
+            Some(name) if self.get_sections().is_empty() => {
+                debug!("{} 没有节信息，忽略节 {}", self.file, name);
+                vec![(0, len)]
+            }
+            Some(name) => {
+                let ranges = self
+                    .get_sections()
+                    .iter()
+                    .filter(|section| section.name.eq_ignore_ascii_case(name))
+                    .map(|section| (section.file_start, section.file_end.min(len)))
+                    .collect::<Vec<(u64, u64)>>();
+                if ranges.is_empty() {
+                    return Err(UPatchError::SectionNotFound(name.to_string()).into());
+                }
+                ranges
+            }
+            None => vec![(0, len)],
+        };
+        if let Some(range) = &scope.range {
+            if range.start >= range.end {
+                return Err(UPatchError::InvalidSearchRange(scope.to_string()).into());
+            }
+            let windows = match range.base {
+                RangeBase::Foa => vec![(range.start, range.end.min(len))],
+                RangeBase::Rva => self.image.rva_window_to_foa(range.start, range.end),
+            };
+            ranges = ranges
+                .iter()
+                .flat_map(|(s1, e1)| {
+                    windows
+                        .iter()
+                        .map(move |(s2, e2)| (*s1.max(s2), *e1.min(e2)))
+                })
+                .collect();
+        }
+        let ranges = ranges
+            .into_iter()
+            .filter(|(start, end)| start < end)
+            .map(|(start, end)| (start as usize, end as usize))
+            .collect::<Vec<(usize, usize)>>();
+        if ranges.is_empty() {
+            return Err(UPatchError::InvalidSearchRange(scope.to_string()).into());
+        }
+        Ok(ranges)
+    }
+
+    fn search_by_aobscan(data: &[u8], pattern: &BytePattern, all: bool) -> Result<Vec<usize>> {
+        let pattern = PatternBuilder::from_hex_string(pattern.to_hex().as_str())
             .map_err(|_| UPatchError::PatternBuilderError)?
             .with_all_threads()
             .build();
 
         let mut results = Vec::new();
-        let data = self.get_data();
         pattern.scan(data, |offset| {
             if offset < data.len() {
                 results.push(offset);
             }
             all
         });
-        if results.is_empty() {
-            return Err(UPatchError::PatternNotFindError.into());
-        }
+        results.sort();
         Ok(results)
     }
 
-    pub fn init_sections(data: &PatchDataType) -> Result<Vec<(u64, u64, u64)>> {
+    pub fn get_image(&self) -> &dyn BinaryImage {
+        self.image.as_ref()
+    }
 
-        let pe_data = Self::get_data_by_datetype(data);
-        let pe_file = PeFile::from_bytes(pe_data).map_err(|_| UPatchError::FOAToRVAError)?;
-        let sections_headers = pe_file.section_headers();
-        let mut sections = Vec::new();
-        for section in sections_headers {
-            let range = section.file_range();
-            sections.push((
-                range.start as u64,
-                range.end as u64,
-                section.VirtualAddress as u64,
-            ));
-        }
-        Ok(sections)
+    pub fn get_format(&self) -> ImageFormat {
+        self.image.format()
+    }
+
+    pub fn get_sections(&self) -> &[Section] {
+        self.image.sections()
+    }
+
+    pub fn get_bitness(&self) -> u32 {
+        self.image.bitness()
+    }
+
+    pub fn get_image_base(&self) -> u64 {
+        self.image.image_base()
     }
 
     pub fn foa_to_rva(&self, foa: u64) -> Result<u64> {
-        let sections = &self.sections;
-        for section in sections {
-            let section_start = section.0;
-            let section_end = section.1;
-            let v_address = section.2;
-            // 处理未映射到节的数据（如PE头）
-            if foa < section_start {
-                return Ok(foa);
-            }
-            // 检查 FOA 是否在当前节内
-            if foa >= section_start && foa < section_end {
-                // 计算节内偏移
-                let offset_in_section = foa - section_start;
-                // 转换为 RVA: 节起始RVA + 节内偏移
-                return Ok(v_address + offset_in_section);
-            }
-        }
+        self.image.foa_to_rva(foa)
+    }
 
-        Err(UPatchError::FOAToRVAError.into())
+    pub fn rva_to_foa(&self, rva: u64) -> Result<u64> {
+        self.image.rva_to_foa(rva)
+    }
+
+    pub fn rva_to_va(&self, rva: u64) -> Result<u64> {
+        self.image.rva_to_va(rva)
+    }
+
+    pub fn va_to_rva(&self, va: u64) -> Result<u64> {
+        self.image.va_to_rva(va)
+    }
+
+    pub fn foa_to_va(&self, foa: u64) -> Result<u64> {
+        self.image.foa_to_va(foa)
     }
 
     pub fn write(&mut self, pos: usize, data: PatchType) -> Result<&Self> {
@@ -203,6 +447,9 @@ impl UPatch {
         };
         let len = new_data.len();
         let (pos1, pos2) = self.check_pos(pos, len)?;
+        if self.signature.is_broken_by(pos as u64, len as u64) {
+            self.signature_broken = true;
+        }
         let new_data_bytes = new_data.as_bytes();
 
         match &mut self.data {
@@ -219,16 +466,131 @@ impl UPatch {
         Ok(self)
     }
 
-    pub fn save(&self) -> Result<()> {
+    /// 写入导入的补丁记录
+    pub fn apply_diff(&mut self, records: &[DiffRecord]) -> Result<()> {
+        for record in records {
+            self.check_pos(record.offset, record.replace.len())?;
+        }
+        for record in records {
+            self.write(record.offset, PatchType::Data(record.replace.clone()))?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> Result<()> {
         if !self.with_write {
             // return Err(UPatchError::ReadOnlyError.into());
             // 跳过保存
             return Ok(());
         }
-        match self.file.as_str() == self.save.as_str() {
-            true => self.save_to(),
-            false => self.save_as(),
+        if self.strip_certificate {
+            self.strip_certificate()?;
+        }
+        if self.recompute_checksum {
+            match self.image.format() {
+                ImageFormat::Pe => {
+                    self.update_checksum()?;
+                }
+                _ => warn!("{} 不是 PE 文件，跳过校验和", self.save),
+            }
+        }
+        match (self.save_mode, &self.data) {
+            (_, PatchDataType::Mmap(_)) => Err(UPatchError::ReadOnlyError.into()),
+            // 目标文件正被映射，无法替换，不降级为原地写回
+            (SaveMode::Atomic, PatchDataType::MmapMut(_)) => {
+                Err(UPatchError::AtomicSaveMapped(self.save.clone()).into())
+            }
+            (SaveMode::Atomic, PatchDataType::Data(_)) => self.save_atomic(),
+            (SaveMode::Direct, _) => match self.file.as_str() == self.save.as_str() {
+                true => self.save_to(),
+                false => self.save_as(),
+            },
+        }
+    }
+
+    /// 清除证书表与安全目录项，避免留下失效的签名
+    fn strip_certificate(&mut self) -> Result<()> {
+        if !self.signature.signed {
+            return Ok(());
+        }
+        let start = self.signature.cert_offset as usize;
+        let end = start + self.signature.cert_size as usize;
+        info!(
+            "正在清除数字签名：{}，签名者：{}",
+            self.save, self.signature.signer
+        );
+        self.write(
+            self.signature.get_directory_offset() as usize,
+            PatchType::Data(vec![0; 8]),
+        )?;
+        match &mut self.data {
+            // 证书表位于文件末尾时直接截断
+            PatchDataType::Data(data) if end >= data.len() => data.truncate(start),
+            _ => {
+                self.write(start, PatchType::Data(vec![0; end - start]))?;
+            }
+        }
+        self.signature = SignatureInfo::from_pe(self.get_data())?;
+        self.image = open_image(self.get_data())?;
+        Ok(())
+    }
+
+    /// 重算并写回 OptionalHeader.CheckSum
+    fn update_checksum(&mut self) -> Result<PeChecksum> {
+        let data = self.get_data();
+        let offset = checksum_offset(data)?;
+        let checksum = PeChecksum {
+            old: read_checksum(data)?,
+            new: compute_checksum(data)?,
+        };
+        info!(
+            "文件 {} 校验和：{:#010X} -> {:#010X}",
+            self.save, checksum.old, checksum.new
+        );
+        if checksum.old != checksum.new {
+            self.write(offset, PatchType::Data(checksum.new.to_le_bytes().to_vec()))?;
         }
+        self.checksum = Some(checksum);
+        Ok(checksum)
+    }
+
+    fn save_atomic(&self) -> Result<()> {
+        info!("正在原子保存文件：{}", self.save);
+        let temp = format!("{}.tmp", self.save);
+        let data = self.get_data();
+        let result = Self::write_temp(temp.as_str(), data)
+            .and_then(|_| Self::verify_temp(temp.as_str(), data))
+            .and_then(|_| self.replace_with(temp.as_str()));
+        if result.is_err() {
+            let _ = std::fs::remove_file(temp.as_str());
+        }
+        result
+    }
+
+    fn write_temp(temp: &str, data: &[u8]) -> Result<()> {
+        let mut temp_file = File::create(temp)?;
+        temp_file.write_all(data)?;
+        temp_file.sync_all()?;
+        Ok(())
+    }
+
+    fn verify_temp(temp: &str, data: &[u8]) -> Result<()> {
+        let written = std::fs::read(temp)?;
+        if written.len() != data.len() || Sha256::digest(&written) != Sha256::digest(data) {
+            return Err(UPatchError::TempFileVerifyError(temp.to_string()).into());
+        }
+        Ok(())
+    }
+
+    fn replace_with(&self, temp: &str) -> Result<()> {
+        std::fs::rename(temp, self.save.as_str()).map_err(|e| {
+            // 32: ERROR_SHARING_VIOLATION
+            match e.kind() == std::io::ErrorKind::PermissionDenied || e.raw_os_error() == Some(32) {
+                true => UPatchError::TargetFileLocked(self.save.clone()),
+                false => UPatchError::ReplaceFileError(self.save.clone(), e.to_string()),
+            }
+        })?;
+        Ok(())
     }
 
     fn save_to(&self) -> Result<()> {
@@ -249,3 +611,63 @@ impl UPatch {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::fixture::PeBuilder;
+    use std::path::Path;
+
+    fn write_and_save(patch: &mut UPatch) -> Result<()> {
+        patch.write(0x200, PatchType::Data(vec![0x90; 4]))?;
+        patch.save()
+    }
+
+    #[test]
+    fn test_verify_temp() {
+        let dir = tempfile::tempdir().unwrap();
+        let temp = dir.path().join("app.exe.tmp");
+        let temp = temp.to_str().unwrap();
+
+        // 临时文件与内存数据不一致时校验失败
+        assert!(UPatch::verify_temp(temp, b"MZ").is_err());
+        std::fs::write(temp, b"MZ").unwrap();
+        assert!(UPatch::verify_temp(temp, b"MZ").is_ok());
+        assert!(UPatch::verify_temp(temp, b"ZM").is_err());
+    }
+
+    #[test]
+    fn test_save_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.exe");
+        PeBuilder::new()
+            .section(".text", &[0xCC; 16])
+            .write(&file)
+            .unwrap();
+        let file = file.to_str().unwrap();
+        let temp = format!("{}.tmp", file);
+
+        // 默认原子保存，覆盖源文件时也不使用 mmap
+        let mut patch = UPatch::create(file, file, true).unwrap();
+        assert!(matches!(patch.data, PatchDataType::Data(_)));
+        write_and_save(&mut patch).unwrap();
+        drop(patch);
+        assert_eq!(std::fs::read(file).unwrap()[0x200..0x204], [0x90; 4]);
+        assert!(!Path::new(&temp).exists());
+
+        // 替换失败时删除临时文件，目标文件不变
+        let locked = dir.path().join("locked");
+        std::fs::create_dir_all(locked.join("keep")).unwrap();
+        let locked = locked.to_str().unwrap();
+        let mut patch = UPatch::create(file, locked, true).unwrap();
+        assert!(write_and_save(&mut patch).is_err());
+        assert!(!Path::new(&format!("{}.tmp", locked)).exists());
+        assert!(Path::new(locked).join("keep").is_dir());
+
+        // 直接覆盖时使用 mmap，之后切换为原子保存返回错误
+        let mut patch = UPatch::create_with_mode(file, file, true, SaveMode::Direct).unwrap();
+        assert!(matches!(patch.data, PatchDataType::MmapMut(_)));
+        patch.set_save_mode(SaveMode::Atomic);
+        assert!(write_and_save(&mut patch).is_err());
+    }
+}
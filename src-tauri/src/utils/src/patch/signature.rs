@@ -0,0 +1,247 @@
+use crate::errors::Result;
+use crate::patch::checksum::checksum_offset;
+use crate::patch::errors::UPatchError;
+use pelite::PeFile;
+use pelite::image::IMAGE_DATA_DIRECTORY;
+use pelite::image::IMAGE_DIRECTORY_ENTRY_SECURITY;
+use std::mem::size_of;
+
+// commonName 2.5.4.3
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+/// Authenticode 签名信息
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SignatureInfo {
+    pub signed: bool,
+    pub signer: String,
+    /// 证书表的文件偏移
+    pub cert_offset: u64,
+    pub cert_size: u64,
+    // 安全目录项的文件偏移
+    directory_offset: u64,
+    checksum_offset: u64,
+}
+
+impl SignatureInfo {
+    pub fn from_pe(data: &[u8]) -> Result<Self> {
+        let pe_file = PeFile::from_bytes(data).map_err(|_| UPatchError::InvalidPeHeader)?;
+        let directories = pe_file.data_directory();
+        let directory = directories
+            .get(IMAGE_DIRECTORY_ENTRY_SECURITY)
+            .ok_or(UPatchError::InvalidPeHeader)?;
+        let directory_offset =
+            directory as *const IMAGE_DATA_DIRECTORY as usize - data.as_ptr() as usize;
+        let mut info = Self {
+            directory_offset: directory_offset as u64,
+            checksum_offset: checksum_offset(data)? as u64,
+            ..Default::default()
+        };
+        if directory.VirtualAddress == 0 || directory.Size == 0 {
+            return Ok(info);
+        }
+        // 安全目录的 VirtualAddress 是文件偏移
+        info.cert_offset = directory.VirtualAddress as u64;
+        info.cert_size = directory.Size as u64;
+        let security = pe_file
+            .security()
+            .map_err(|e| UPatchError::InvalidSignature(e.to_string()))?;
+        info.signed = true;
+        info.signer = parse_signer(security.certificate_data()).unwrap_or_default();
+        Ok(info)
+    }
+
+    pub fn get_directory_offset(&self) -> u64 {
+        self.directory_offset
+    }
+
+    /// 修改 [pos, pos + len) 是否会使签名失效
+    /// Authenticode 哈希不包含校验和、安全目录项和证书表本身
+    pub fn is_broken_by(&self, pos: u64, len: u64) -> bool {
+        if !self.signed || len == 0 {
+            return false;
+        }
+        let end = pos + len;
+        let excluded = [
+            (self.checksum_offset, self.checksum_offset + 4),
+            (
+                self.directory_offset,
+                self.directory_offset + size_of::<IMAGE_DATA_DIRECTORY>() as u64,
+            ),
+            (self.cert_offset, self.cert_offset + self.cert_size),
+        ];
+        !excluded
+            .iter()
+            .any(|(start, stop)| pos >= *start && end <= *stop)
+    }
+}
+
+/// DER 元素
+struct Der<'a> {
+    tag: u8,
+    content: &'a [u8],
+    raw: &'a [u8],
+}
+
+fn read_der(data: &[u8]) -> Option<(Der<'_>, &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (len, header) = match first {
+        0..=0x7F => (first, 2),
+        0x81..=0x84 => {
+            let n = first - 0x80;
+            let len = data
+                .get(2..2 + n)?
+                .iter()
+                .fold(0usize, |len, b| (len << 8) | *b as usize);
+            (len, 2 + n)
+        }
+        // 不支持不定长编码
+        _ => return None,
+    };
+    let end = header.checked_add(len)?;
+    let raw = data.get(..end)?;
+    Some((
+        Der {
+            tag,
+            content: &raw[header..],
+            raw,
+        },
+        &data[end..],
+    ))
+}
+
+fn children(mut data: &[u8]) -> Vec<Der<'_>> {
+    let mut r = Vec::new();
+    while let Some((der, rest)) = read_der(data) {
+        r.push(der);
+        data = rest;
+    }
+    r
+}
+
+/// 从 PKCS#7 SignedData 中取出签名者证书的 CN
+fn parse_signer(data: &[u8]) -> Option<String> {
+    let (content_info, _) = read_der(data)?;
+    let content = children(content_info.content);
+    let explicit = content.iter().find(|der| der.tag == 0xA0)?;
+    let (signed_data, _) = read_der(explicit.content)?;
+    let signed_data = children(signed_data.content);
+    let certificates = signed_data.iter().find(|der| der.tag == 0xA0)?;
+    let certificates = children(certificates.content);
+
+    // SignerInfo.issuerAndSerialNumber
+    let signer_id = signed_data
+        .iter()
+        .rfind(|der| der.tag == 0x31)
+        .and_then(|infos| children(infos.content).into_iter().next())
+        .and_then(|info| children(info.content).into_iter().nth(1))
+        .map(|sid| children(sid.content));
+
+    let mut first_subject = None;
+    for certificate in &certificates {
+        let Some(tbs) = children(certificate.content).into_iter().next() else {
+            continue;
+        };
+        let mut fields = children(tbs.content);
+        // 跳过 version
+        if fields.first().is_some_and(|der| der.tag == 0xA0) {
+            fields.remove(0);
+        }
+        // serialNumber, signature, issuer, validity, subject
+        if fields.len() < 5 {
+            continue;
+        }
+        let subject = common_name(&fields[4]);
+        if first_subject.is_none() {
+            first_subject = subject.clone();
+        }
+        if let Some(sid) = &signer_id
+            && sid.len() >= 2
+            && sid[0].raw == fields[2].raw
+            && sid[1].content == fields[0].content
+        {
+            return subject;
+        }
+    }
+    first_subject
+}
+
+fn common_name(name: &Der) -> Option<String> {
+    for rdn in children(name.content) {
+        for attribute in children(rdn.content) {
+            let attribute = children(attribute.content);
+            if attribute.len() < 2 || attribute[0].tag != 0x06 {
+                continue;
+            }
+            if attribute[0].content != OID_COMMON_NAME {
+                continue;
+            }
+            let value = &attribute[1];
+            return Some(match value.tag {
+                // BMPString
+                0x1E => {
+                    let units: Vec<u16> = value
+                        .content
+                        .chunks_exact(2)
+                        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                        .collect();
+                    String::from_utf16_lossy(&units)
+                }
+                _ => String::from_utf8_lossy(value.content).to_string(),
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+        let len = content.len();
+        let mut r = match len {
+            0..0x80 => vec![tag, len as u8],
+            _ => vec![tag, 0x82, (len >> 8) as u8, len as u8],
+        };
+        r.extend_from_slice(content);
+        r
+    }
+
+    fn name(cn: &str) -> Vec<u8> {
+        let mut attribute = der(0x06, OID_COMMON_NAME);
+        attribute.extend(der(0x0C, cn.as_bytes()));
+        der(0x30, &der(0x31, &der(0x30, &attribute)))
+    }
+
+    fn certificate(serial: u8, issuer: &str, subject: &str) -> Vec<u8> {
+        let mut tbs = der(0x02, &[serial]);
+        tbs.extend(der(0x30, &[]));
+        tbs.extend(name(issuer));
+        tbs.extend(der(0x30, &[]));
+        tbs.extend(name(subject));
+        der(0x30, &der(0x30, &tbs))
+    }
+
+    #[test]
+    fn test_parse_signer() {
+        let mut certificates = certificate(1, "Root CA", "Code Signing CA");
+        certificates.extend(certificate(2, "Code Signing CA", "Tencent Technology"));
+        let mut sid = name("Code Signing CA");
+        sid.extend(der(0x02, &[2]));
+        let mut signer_info = der(0x02, &[1]);
+        signer_info.extend(der(0x30, &sid));
+
+        let mut signed_data = der(0x02, &[1]);
+        signed_data.extend(der(0x31, &[]));
+        signed_data.extend(der(0x30, &[]));
+        signed_data.extend(der(0xA0, &certificates));
+        signed_data.extend(der(0x31, &der(0x30, &signer_info)));
+
+        let mut content_info = der(0x06, &[0x2A]);
+        content_info.extend(der(0xA0, &der(0x30, &signed_data)));
+        let data = der(0x30, &content_info);
+
+        assert_eq!(parse_signer(&data).unwrap(), "Tencent Technology");
+    }
+}
@@ -1,8 +1,14 @@
-use std::fmt::Display;
+use crate::empty::Empty;
 use crate::errors::Result;
+use crate::patch::capture::PatternCapture;
 use crate::patch::errors::UPatchError;
 use memmap2::Mmap;
 use memmap2::MmapMut;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use std::fmt::Display;
 
 #[derive(Debug, Clone)]
 pub struct Bytes(Vec<u8>);
@@ -107,6 +113,358 @@ impl Display for Bytes {
     }
 }
 
+/// 特征码，支持空格分隔、`//` 行内注释以及半字节通配符
+///
+/// 例如：`48 8B ?? 4? ?5 // 注释`，`?` 所在的半字节不参与匹配，
+/// `{off:4}` 为命名捕获，按通配符匹配并在搜索后解码为变量
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BytePattern {
+    bytes: Vec<u8>,
+    masks: Vec<u8>,
+    captures: Vec<PatternCapture>,
+}
+
+impl BytePattern {
+    pub fn parse<S: AsRef<str>>(text: S) -> Result<Self> {
+        let text = text.as_ref();
+        let mut bytes = Vec::new();
+        let mut masks = Vec::new();
+        let mut captures = Vec::new();
+        for token in Self::normalize(text).split_whitespace() {
+            let mut rest = token;
+            while !rest.is_empty() {
+                if let Some(capture) = rest.strip_prefix('{') {
+                    let end = capture
+                        .find('}')
+                        .ok_or(UPatchError::InvalidBytePattern(text.to_string()))?;
+                    let capture = PatternCapture::parse(&capture[..end], bytes.len())?;
+                    bytes.resize(bytes.len() + capture.size, 0);
+                    masks.resize(masks.len() + capture.size, 0);
+                    captures.push(capture);
+                    rest = &rest[end + 2..];
+                    continue;
+                }
+                let end = rest.find('{').unwrap_or(rest.len());
+                let chars = rest[..end].chars().collect::<Vec<char>>();
+                if chars.len() % 2 != 0 {
+                    return Err(UPatchError::InvalidBytePattern(text.to_string()).into());
+                }
+                for pair in chars.chunks(2) {
+                    let (hi, hi_mask) = Self::parse_nibble(pair[0], text)?;
+                    let (lo, lo_mask) = Self::parse_nibble(pair[1], text)?;
+                    bytes.push(hi << 4 | lo);
+                    masks.push(hi_mask << 4 | lo_mask);
+                }
+                rest = &rest[end..];
+            }
+        }
+        Ok(Self {
+            bytes,
+            masks,
+            captures,
+        })
+    }
+
+    /// 去除注释，保留空格分隔，供模板类字符串（replace）在变量替换前预处理
+    pub fn normalize(text: &str) -> String {
+        text.lines()
+            .map(|line| match line.find("//") {
+                Some(index) => &line[..index],
+                None => line,
+            })
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    /// 去除注释和空白，得到紧凑字符串
+    pub fn compact(text: &str) -> String {
+        Self::normalize(text)
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    }
+
+    fn parse_nibble(c: char, text: &str) -> Result<(u8, u8)> {
+        if c == '?' {
+            return Ok((0, 0));
+        }
+        let n = c
+            .to_digit(16)
+            .ok_or(UPatchError::InvalidBytePattern(text.to_string()))?;
+        Ok((n as u8, 0xF))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn masks(&self) -> &[u8] {
+        &self.masks
+    }
+
+    pub fn captures(&self) -> &[PatternCapture] {
+        &self.captures
+    }
+
+    /// 通配符是否都是整字节
+    pub fn is_byte_aligned(&self) -> bool {
+        self.masks.iter().all(|m| *m == 0x00 || *m == 0xFF)
+    }
+
+    pub fn is_match(&self, data: &[u8]) -> bool {
+        data.len() >= self.len()
+            && self
+                .bytes
+                .iter()
+                .zip(&self.masks)
+                .zip(data)
+                .all(|((b, m), d)| d & m == *b & m)
+    }
+
+    /// 在 data 中搜索，以最长的无通配符片段作为锚点
+    pub fn find_in(&self, data: &[u8], all: bool) -> Vec<usize> {
+        let mut results = Vec::new();
+        if self.is_empty() || data.len() < self.len() {
+            return results;
+        }
+        let (anchor_start, anchor_len) = self.anchor();
+        let last = data.len() - self.len();
+        let mut pos = 0;
+        while pos <= last {
+            let next = if anchor_len == 0 {
+                Some(pos)
+            } else {
+                let anchor = &self.bytes[anchor_start..anchor_start + anchor_len];
+                let window = &data[pos + anchor_start..last + anchor_start + anchor_len];
+                window
+                    .windows(anchor_len)
+                    .position(|w| w == anchor)
+                    .map(|index| pos + index)
+            };
+            let Some(start) = next else {
+                break;
+            };
+            if self.is_match(&data[start..]) {
+                results.push(start);
+                if !all {
+                    break;
+                }
+            }
+            pos = start + 1;
+        }
+        results
+    }
+
+    /// 最长的无通配符片段 (起点, 长度)
+    pub fn anchor(&self) -> (usize, usize) {
+        let (mut best_start, mut best_len) = (0, 0);
+        let mut start = 0;
+        for (i, m) in self.masks.iter().enumerate() {
+            if *m != 0xFF {
+                start = i + 1;
+                continue;
+            }
+            if i + 1 - start > best_len {
+                best_start = start;
+                best_len = i + 1 - start;
+            }
+        }
+        (best_start, best_len)
+    }
+
+    /// 用 orignal 中对应位置的半字节填充通配符
+    pub fn apply(&self, orignal: &[u8]) -> Result<Bytes> {
+        if orignal.len() != self.len() {
+            return Err(UPatchError::InvalidBytePattern(self.to_hex()).into());
+        }
+        let bytes = self
+            .bytes
+            .iter()
+            .zip(&self.masks)
+            .zip(orignal)
+            .map(|((b, m), o)| b & m | o & !m)
+            .collect::<Vec<u8>>();
+        Ok(Bytes::new(bytes))
+    }
+
+    /// 紧凑格式，通配符使用 `?` 表示
+    pub fn to_hex(&self) -> String {
+        let mut s = String::with_capacity(self.len() * 2);
+        for (b, m) in self.bytes.iter().zip(&self.masks) {
+            for shift in [4, 0] {
+                if (m >> shift) & 0xF == 0 {
+                    s.push('?');
+                } else {
+                    s.push_str(&format!("{:X}", (b >> shift) & 0xF));
+                }
+            }
+        }
+        s
+    }
+
+    /// 与 to_hex 相同，但保留命名捕获，用于序列化
+    pub fn to_text(&self) -> String {
+        let hex = self.to_hex();
+        let mut s = String::with_capacity(hex.len());
+        let mut pos = 0;
+        for capture in &self.captures {
+            s.push_str(&hex[pos * 2..capture.offset * 2]);
+            s.push_str(&capture.to_string());
+            pos = capture.offset + capture.size;
+        }
+        s.push_str(&hex[pos * 2..]);
+        s
+    }
+}
+
+impl Display for BytePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl Empty for BytePattern {
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl TryFrom<&str> for BytePattern {
+    type Error = UPatchError;
+
+    fn try_from(value: &str) -> core::result::Result<Self, UPatchError> {
+        Self::parse(value).map_err(|_| UPatchError::InvalidBytePattern(value.to_string()))
+    }
+}
+
+impl Serialize for BytePattern {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_text())
+    }
+}
+
+impl<'de> Deserialize<'de> for BytePattern {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        BytePattern::try_from(text.as_str()).map_err(serde::de::Error::custom)
+    }
+}
+
+/// PE 节信息，file 为文件偏移范围，virtual 为内存偏移（RVA）
+#[derive(Debug, Clone, Default)]
+pub struct Section {
+    pub name: String,
+    pub file_start: u64,
+    pub file_end: u64,
+    pub virtual_address: u64,
+    pub virtual_size: u64,
+}
+
+impl Section {
+    pub fn contains_foa(&self, foa: u64) -> bool {
+        foa >= self.file_start && foa < self.file_end
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.file_end - self.file_start
+    }
+}
+
+/// 搜索范围的地址类型
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RangeBase {
+    #[default]
+    Foa,
+    Rva,
+}
+
+/// 搜索窗口 [start, end)，数值可以是数字或者 `0x` 开头的16进制字符串
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchRange {
+    #[serde(default)]
+    pub base: RangeBase,
+    #[serde(deserialize_with = "deserialize_hex_u64")]
+    pub start: u64,
+    #[serde(deserialize_with = "deserialize_hex_u64")]
+    pub end: u64,
+}
+
+impl Empty for SearchRange {
+    fn is_empty(&self) -> bool {
+        self.start == 0 && self.end == 0
+    }
+}
+
+/// 搜索范围，section 和 range 同时存在时取交集
+#[derive(Debug, Clone, Default)]
+pub struct SearchScope {
+    pub section: Option<String>,
+    pub range: Option<SearchRange>,
+}
+
+impl SearchScope {
+    pub fn new(section: Option<String>, range: Option<SearchRange>) -> Self {
+        Self { section, range }
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.section.is_none() && self.range.is_none()
+    }
+}
+
+impl Display for SearchScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_all() {
+            return write!(f, "全部");
+        }
+        if let Some(section) = &self.section {
+            write!(f, "节 {} ", section)?;
+        }
+        if let Some(range) = &self.range {
+            write!(f, "{:?} [{:#X}, {:#X})", range.base, range.start, range.end)?;
+        }
+        Ok(())
+    }
+}
+
+fn deserialize_hex_u64<'de, D>(deserializer: D) -> core::result::Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum HexOrNumber {
+        Number(u64),
+        Hex(String),
+    }
+    match HexOrNumber::deserialize(deserializer)? {
+        HexOrNumber::Number(n) => Ok(n),
+        HexOrNumber::Hex(s) => {
+            let s = s.trim();
+            let r = match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => s.parse::<u64>(),
+            };
+            r.map_err(serde::de::Error::custom)
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum PatchType {
     String(String),
@@ -131,9 +489,76 @@ impl From<Vec<u8>> for PatchType {
     }
 }
 
+/// 保存方式
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SaveMode {
+    /// 直接覆盖目标文件
+    Direct,
+    /// 先写入临时文件，校验后再替换目标文件
+    #[default]
+    Atomic,
+}
+
 #[derive(Debug)]
 pub enum PatchDataType {
     Mmap(Mmap),
     MmapMut(MmapMut),
     Data(Vec<u8>),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::capture::CaptureValue;
+
+    #[test]
+    fn test_byte_pattern_parse() {
+        let pattern = BytePattern::parse("48 8B ?? 4? ?5 // mov rcx,[rax+??]\nC3").unwrap();
+        assert_eq!(pattern.len(), 6);
+        assert_eq!(pattern.to_hex(), "488B??4??5C3");
+        assert_eq!(pattern.masks(), &[0xFF, 0xFF, 0x00, 0xF0, 0x0F, 0xFF]);
+        assert!(pattern.is_match(&[0x48, 0x8B, 0x12, 0x4C, 0x05, 0xC3]));
+        assert!(!pattern.is_match(&[0x48, 0x8B, 0x12, 0x5C, 0x05, 0xC3]));
+        assert!(BytePattern::parse("48 8").is_err());
+        assert!(BytePattern::parse("4G").is_err());
+    }
+
+    #[test]
+    fn test_byte_pattern_capture() {
+        let pattern = BytePattern::parse("8B 88 {off:4} 00 E8{target:rel4}").unwrap();
+        assert_eq!(pattern.len(), 12);
+        assert_eq!(pattern.to_hex(), "8B88????????00E8????????");
+        assert_eq!(pattern.to_text(), "8B88{off:4}00E8{target:rel4}");
+        let data = [
+            0x8B, 0x88, 0x10, 0x02, 0, 0, 0x00, 0xE8, 0xF0, 0xFF, 0xFF, 0xFF,
+        ];
+        assert!(pattern.is_match(&data));
+        let captures = pattern.captures();
+        assert_eq!(
+            captures[0].decode(&data, 0x1000).unwrap(),
+            CaptureValue::Unsigned(0x210)
+        );
+        // 0x1000 + 12 - 0x10
+        assert_eq!(
+            captures[1].decode(&data, 0x1000).unwrap(),
+            CaptureValue::Unsigned(0xFFC)
+        );
+        assert!(BytePattern::parse("8B {off:3}").is_err());
+        assert!(BytePattern::parse("8B {off:4").is_err());
+    }
+
+    #[test]
+    fn test_byte_pattern_find() {
+        let data = [0x90, 0x48, 0x8B, 0x01, 0x48, 0x8B, 0x41, 0x48, 0x89, 0x41];
+        let pattern = BytePattern::parse("48 8? ?1").unwrap();
+        assert_eq!(pattern.find_in(&data, true), vec![1, 4, 7]);
+        assert_eq!(pattern.find_in(&data, false), vec![1]);
+        let pattern = BytePattern::parse("48 8B 4?").unwrap();
+        assert_eq!(pattern.find_in(&data, true), vec![4]);
+        let replace = BytePattern::parse("?? 8B 4?").unwrap();
+        assert_eq!(
+            replace.apply(&[0x48, 0x89, 0x01]).unwrap().to_hex(),
+            "488B41"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::empty::Empty;
+use iced_x86::Decoder;
+use iced_x86::DecoderOptions;
+use iced_x86::Instruction;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// 字符串编码
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StringEncoding {
+    #[default]
+    Utf8,
+    Utf16,
+}
+
+/// 通过引用的字符串定位代码
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StringXref {
+    pub text: String,
+    #[serde(default)]
+    pub encoding: StringEncoding,
+    // 字符串所在节，默认 .rdata
+    #[serde(default)]
+    pub section: String,
+}
+
+impl StringXref {
+    pub fn get_section(&self) -> &str {
+        if self.section.is_empty() {
+            ".rdata"
+        } else {
+            &self.section
+        }
+    }
+
+    /// 编码后的字符串，包含结尾的 0，避免匹配到更长字符串的前缀
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self.encoding {
+            StringEncoding::Utf8 => self.text.bytes().chain([0]).collect(),
+            StringEncoding::Utf16 => self
+                .text
+                .encode_utf16()
+                .chain([0])
+                .flat_map(|c| c.to_le_bytes())
+                .collect(),
+        }
+    }
+}
+
+impl Display for StringXref {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}({:?})", self.text, self.encoding)
+    }
+}
+
+impl Empty for StringXref {
+    fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+/// 扫描 code 中引用 targets 的 RIP 相对指令，ip 为 code[0] 的 RVA，返回指令相对 code 的偏移
+pub fn find_rip_references(code: &[u8], ip: u64, bitness: u32, targets: &[u64]) -> Vec<usize> {
+    let mut decoder = Decoder::with_ip(bitness, code, ip, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    let mut results = Vec::new();
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+        if instruction.is_invalid() || !instruction.is_ip_rel_memory_operand() {
+            continue;
+        }
+        if targets.contains(&instruction.ip_rel_memory_address()) {
+            results.push((instruction.ip() - ip) as usize);
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_rip_references() {
+        let xref = StringXref {
+            text: "ab".to_string(),
+            encoding: StringEncoding::Utf16,
+            ..Default::default()
+        };
+        assert_eq!(xref.to_bytes(), vec![0x61, 0, 0x62, 0, 0, 0]);
+
+        // nop; lea rcx,[rip+0x10]; ret
+        let code = [0x90, 0x48, 0x8D, 0x0D, 0x10, 0x00, 0x00, 0x00, 0xC3];
+        assert_eq!(find_rip_references(&code, 0x1000, 64, &[0x1018]), vec![1]);
+        assert!(find_rip_references(&code, 0x1000, 64, &[0x2000]).is_empty());
+    }
+}
@@ -30,6 +30,33 @@ pub enum StoreError {
     LockPoisoned,
 }
 
+#[cfg(any(test, feature = "fixture"))]
+thread_local! {
+    static TEST_DATA_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
+/// 测试时把当前线程的应用数据目录指向临时目录，避免读写真实的数据目录
+#[cfg(any(test, feature = "fixture"))]
+pub fn set_test_data_dir(dir: Option<PathBuf>) {
+    TEST_DATA_DIR.with(|data_dir| *data_dir.borrow_mut() = dir);
+}
+
+/// 应用数据目录，不存在时创建
+pub fn app_data_dir() -> Result<PathBuf> {
+    #[cfg(any(test, feature = "fixture"))]
+    if let Some(dir) = TEST_DATA_DIR.with(|data_dir| data_dir.borrow().clone()) {
+        fs::create_dir_all(&dir)?;
+        return Ok(dir);
+    }
+    let roaming_dir = match get_known_folder_path(KnownFolder::RoamingAppData) {
+        Some(dir) => dir,
+        None => current_dir().map_err(|_| StoreError::GetDataFolderError)?,
+    };
+    let app_dir = roaming_dir.join(MAIN_PKG_NAME);
+    fs::create_dir_all(&app_dir)?;
+    Ok(app_dir)
+}
+
 #[derive(Default)]
 pub struct Store {
     data: Arc<RwLock<StoreData>>,
@@ -38,14 +65,7 @@ pub struct Store {
 
 impl Store {
     pub fn new(name: &str) -> Result<Self> {
-        let roaming_dir = match get_known_folder_path(KnownFolder::RoamingAppData) {
-            Some(dir) => dir,
-            None => current_dir().map_err(|_| StoreError::GetDataFolderError)?,
-        };
-
-        let app_dir = roaming_dir.join(MAIN_PKG_NAME);
-        fs::create_dir_all(&app_dir)?;
-        let file_path = app_dir.join(format!("{}.data", name));
+        let file_path = app_data_dir()?.join(format!("{}.data", name));
         let mut store = Self::default();
         store.file_path = file_path.clone();
         if !file_path.exists() {
@@ -1,4 +1,6 @@
 use super::errors::Result;
+use crate::patch::types::BytePattern;
+use crate::patch::types::Bytes;
 use log::debug;
 use thiserror::Error;
 
@@ -37,35 +39,24 @@ pub fn replace_ellipsis<S: AsRef<str>>(ellipsis_str: S, orignal: S) -> Result<St
     Ok(ellipsis_str.to_string())
 }
 
-pub fn replace_wildcards<S: AsRef<str>>(wildcard_str: S, orignal: S) -> Result<String> {
-    let wildcard_str = wildcard_str.as_ref();
+pub fn replace_wildcards<S: AsRef<str>>(wildcard: &BytePattern, orignal: S) -> Result<String> {
     let orignal = orignal.as_ref();
-    // 确保两个字符串长度相同
-
-    if wildcard_str.is_empty() {
-        return Ok(wildcard_str.to_string());
+    if wildcard.is_empty() {
+        return Ok(String::new());
     }
-    // 确保两个字符串长度相同
+    // 确保原始字符不为空
     if orignal.is_empty() {
         return Err(ToolsError::ReplaceWildcardsorignalEmptyError.into());
     }
+    let orignal = Bytes::try_from_hex(orignal.to_string())?;
     // 确保两个字符串长度相同
-    if wildcard_str.len() != orignal.len() {
-        debug!("通配符：{}，原始字符串：{}", wildcard_str, orignal);
+    if wildcard.len() != orignal.len() {
+        debug!("通配符：{}，原始字符串：{}", wildcard, orignal);
         return Err(ToolsError::ReplaceWildcardsDifferentLengthError.into());
     }
-    // 将str2转换为字符向量以便修改
-    let mut wildcard_chars: Vec<char> = wildcard_str.chars().collect();
-    // 遍历str2，替换通配符
-    for (i, c) in wildcard_str.chars().enumerate() {
-        if c == '?' {
-            // 使用str1对应位置的字符替换
-            wildcard_chars[i] = orignal.chars().nth(i).unwrap();
-        }
-    }
-    // 将字符向量转换回字符串
-    Ok(wildcard_chars.into_iter().collect::<String>())
-} 
+    // 使用原始数据对应位置的半字节替换通配符
+    Ok(wildcard.apply(orignal.as_bytes())?.to_hex())
+}
 
 #[macro_export]
 macro_rules! destructure_assign {
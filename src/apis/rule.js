@@ -33,3 +33,15 @@ export async function rule_del_coexist(code, num) {
 export async function rule_read_orignal(code,num,fcode) {
     return await invoke("rule_read_orignal",{code,num,fcode})
 }
+
+export async function rule_read_disasm(code,num,fcode) {
+    return await invoke("rule_read_disasm",{code,num,fcode})
+}
+
+export async function rule_export_diff(code,num,fcode,dir) {
+    return await invoke("rule_export_diff",{code,num,fcode,dir})
+}
+
+export async function rule_import_diff(code,num,file) {
+    return await invoke("rule_import_diff",{code,num,file})
+}